@@ -0,0 +1,440 @@
+// Attestation verification
+// Claim values are hidden behind salted commitments and disclosed one at a time.
+// Checks issuer signatures, disclosed values, freshness and revocation under a
+// policy, and answers "does subject X hold claim Y from an issuer I trust"
+
+use std::collections::HashMap;
+
+use ed25519_dalek::{PublicKey, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::identity::{parse_signature, Attestation};
+use crate::now_secs;
+
+/// Hiding commitment to a claim value: SHA256 over a domain tag, a random 32-byte
+/// salt, the claim name and the value. Without the salt a low-entropy value
+/// (email, handle) cannot be brute-forced from the public attestation.
+pub fn commit_claim_value(claim: &str, value: &str, salt: &[u8; 32]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"zerotrace_claim_v1:");
+    hasher.update(salt);
+    hasher.update(format!("{}:{}", claim, value).as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Opening of one attestation's commitment. The holder keeps it secret and
+/// shows it only to the verifier it chooses to disclose that claim to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimReveal {
+    pub attestation_id: String,    // `Attestation::id()` this opens
+    pub claim: String,
+    pub value: String,             // plaintext claim value
+    pub salt: String,              // hex, 32 bytes
+}
+
+impl ClaimReveal {
+    /// Recompute the commitment this reveal opens
+    pub fn commitment(&self) -> anyhow::Result<String> {
+        let salt: [u8; 32] = hex::decode(&self.salt)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Claim salt must be 32 bytes"))?;
+        Ok(commit_claim_value(&self.claim, &self.value, &salt))
+    }
+}
+
+/// Freshness rules applied when verifying attestations
+#[derive(Debug, Clone)]
+pub struct AttestationPolicy {
+    pub max_age_secs: Option<u64>,  // None = attestations never go stale
+    pub max_clock_skew_secs: u64,   // Tolerance for timestamps slightly in the future
+}
+
+impl Default for AttestationPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_secs: Some(365 * 24 * 60 * 60),
+            max_clock_skew_secs: 300,
+        }
+    }
+}
+
+/// Issuer-signed withdrawal of an attestation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationRevocation {
+    pub issuer: String,
+    pub attestation_id: String,    // `Attestation::id()` (or `Credential::id()`) being withdrawn
+    pub revoked_at: u64,
+    pub signature: String,         // hex signature by the issuer's current key
+}
+
+impl AttestationRevocation {
+    /// Bytes signed by the issuer
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_attestation_revoke_v1:{}:{}:{}",
+            self.issuer, self.attestation_id, self.revoked_at
+        )
+    }
+
+    pub fn verify(&self, issuer_key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| issuer_key.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+/// All attestations withdrawn by one issuer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevocationList {
+    pub issuer: String,
+    pub revocations: Vec<AttestationRevocation>,
+}
+
+impl RevocationList {
+    pub fn new(issuer: &str) -> Self {
+        Self { issuer: issuer.to_string(), revocations: Vec::new() }
+    }
+
+    /// Check every entry belongs to this issuer and is signed by `issuer_key`
+    pub fn verify(&self, issuer_key: &PublicKey) -> anyhow::Result<()> {
+        for revocation in &self.revocations {
+            if revocation.issuer != self.issuer || !revocation.verify(issuer_key) {
+                return Err(anyhow::anyhow!("Revocation of {} is not signed by the issuer", revocation.attestation_id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_revoked(&self, attestation: &Attestation) -> bool {
+        attestation.issuer == self.issuer && self.revocations.iter().any(|r| r.attestation_id == attestation.id())
+    }
+}
+
+impl AttestationPolicy {
+    /// Reject issue times in the future, past expiry or older than `max_age_secs`
+    pub fn check_freshness(&self, issued_at: u64, expires_at: Option<u64>, now: u64) -> anyhow::Result<()> {
+        if issued_at > now.saturating_add(self.max_clock_skew_secs) {
+            return Err(anyhow::anyhow!("Attestation timestamp is in the future"));
+        }
+        if expires_at.is_some_and(|expires_at| now >= expires_at) {
+            return Err(anyhow::anyhow!("Attestation has expired"));
+        }
+        if let Some(max_age) = self.max_age_secs {
+            if now.saturating_sub(issued_at) > max_age {
+                return Err(anyhow::anyhow!("Attestation is stale"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verifies attestations against an issuer key and a freshness policy
+#[derive(Debug, Clone, Default)]
+pub struct AttestationVerifier {
+    pub policy: AttestationPolicy,
+}
+
+impl AttestationVerifier {
+    pub fn new(policy: AttestationPolicy) -> Self {
+        Self { policy }
+    }
+
+    /// Verify signature and freshness. If a reveal is given it must open `value_hash`.
+    pub fn verify(&self, attestation: &Attestation, issuer_key: &PublicKey, reveal: Option<&ClaimReveal>) -> anyhow::Result<()> {
+        self.verify_at(attestation, issuer_key, reveal, now_secs())
+    }
+
+    /// Same as `verify`, at an explicit time (seconds since the epoch)
+    pub fn verify_at(
+        &self,
+        attestation: &Attestation,
+        issuer_key: &PublicKey,
+        reveal: Option<&ClaimReveal>,
+        now: u64,
+    ) -> anyhow::Result<()> {
+        if !attestation.verify(issuer_key) {
+            return Err(anyhow::anyhow!("Attestation signature is invalid"));
+        }
+        if let Some(reveal) = reveal {
+            if !attestation.opens(reveal) {
+                return Err(anyhow::anyhow!("Reveal does not open the attested commitment"));
+            }
+        }
+        self.policy.check_freshness(attestation.timestamp, attestation.expires_at, now)
+    }
+
+    /// `verify`, then fail if the attestation appears in its issuer's revocation list.
+    /// The list should already be checked with `RevocationList::verify`.
+    pub fn verify_unrevoked(
+        &self,
+        attestation: &Attestation,
+        issuer_key: &PublicKey,
+        reveal: Option<&ClaimReveal>,
+        revocations: &RevocationList,
+    ) -> anyhow::Result<()> {
+        self.verify(attestation, issuer_key, reveal)?;
+        if revocations.issuer != attestation.issuer {
+            return Err(anyhow::anyhow!("Revocation list is for a different issuer"));
+        }
+        if revocations.is_revoked(attestation) {
+            return Err(anyhow::anyhow!("Attestation has been revoked"));
+        }
+        Ok(())
+    }
+}
+
+/// Set of issuers whose attestations are accepted, keyed by identity hash
+#[derive(Debug, Clone, Default)]
+pub struct TrustPolicy {
+    issuers: HashMap<String, PublicKey>,          // issuer identity_hash -> current public key
+    revocations: HashMap<String, RevocationList>, // latest verified list per issuer
+    pub verifier: AttestationVerifier,
+}
+
+impl TrustPolicy {
+    pub fn new(verifier: AttestationVerifier) -> Self {
+        Self { issuers: HashMap::new(), revocations: HashMap::new(), verifier }
+    }
+
+    /// Trust claims signed by `issuer_key` on behalf of `issuer_hash`.
+    /// Call again with the new key after the issuer rotates.
+    pub fn trust_issuer(&mut self, issuer_hash: String, issuer_key: PublicKey) {
+        self.issuers.insert(issuer_hash, issuer_key);
+    }
+
+    pub fn distrust_issuer(&mut self, issuer_hash: &str) {
+        self.issuers.remove(issuer_hash);
+        self.revocations.remove(issuer_hash);
+    }
+
+    /// Replace a trusted issuer's revocation list (e.g. freshly fetched from the server)
+    pub fn update_revocations(&mut self, list: RevocationList) -> anyhow::Result<()> {
+        let key = self
+            .issuers
+            .get(&list.issuer)
+            .ok_or_else(|| anyhow::anyhow!("Issuer is not trusted"))?;
+        list.verify(key)?;
+        self.revocations.insert(list.issuer.clone(), list);
+        Ok(())
+    }
+
+    pub fn is_trusted(&self, issuer_hash: &str) -> bool {
+        self.issuers.contains_key(issuer_hash)
+    }
+
+    /// First valid attestation of `claim` about `subject` from a trusted issuer
+    pub fn find_claim<'a>(
+        &self,
+        subject: &str,
+        claim: &str,
+        attestations: &'a [Attestation],
+    ) -> Option<&'a Attestation> {
+        attestations.iter().find(|a| {
+            a.subject == subject
+                && a.claim == claim
+                && self
+                    .issuers
+                    .get(&a.issuer)
+                    .is_some_and(|key| self.verifier.verify(a, key, None).is_ok())
+                && !self.revocations.get(&a.issuer).is_some_and(|list| list.is_revoked(a))
+        })
+    }
+
+    /// Does `subject` hold `claim` from any trusted issuer?
+    pub fn holds_claim(&self, subject: &str, claim: &str, attestations: &[Attestation]) -> bool {
+        self.find_claim(subject, claim, attestations).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn verify_at_checks_signature_and_reveal() {
+        let (issuer, subject) = (IdentityManager::new(), IdentityManager::new());
+        let (attestation, reveal) = issuer.attest(subject.get_identity_hash(), "email", "a@example.com", None);
+        let verifier = AttestationVerifier::default();
+        let now = attestation.timestamp;
+        verifier.verify_at(&attestation, &issuer.public_key(), Some(&reveal), now).unwrap();
+
+        assert!(verifier.verify_at(&attestation, &subject.public_key(), None, now).is_err());
+        let mut tampered = attestation.clone();
+        tampered.claim = "phone".to_string();
+        assert!(verifier.verify_at(&tampered, &issuer.public_key(), None, now).is_err());
+
+        let mut wrong_value = reveal.clone();
+        wrong_value.value = "b@example.com".to_string();
+        assert!(verifier.verify_at(&attestation, &issuer.public_key(), Some(&wrong_value), now).is_err());
+        let (_, other_reveal) = issuer.attest(subject.get_identity_hash(), "email", "a@example.com", None);
+        assert!(verifier.verify_at(&attestation, &issuer.public_key(), Some(&other_reveal), now).is_err());
+    }
+
+    #[test]
+    fn verify_at_checks_freshness() {
+        let issuer = IdentityManager::new();
+        let (attestation, _) = issuer.attest("subject", "email", "a@example.com", None);
+        let issued = attestation.timestamp;
+        let key = issuer.public_key();
+        let verifier = AttestationVerifier::default();
+        let max_age = verifier.policy.max_age_secs.unwrap();
+
+        // Issued further in the future than the allowed skew
+        assert!(verifier.verify_at(&attestation, &key, None, issued - 301).is_err());
+        verifier.verify_at(&attestation, &key, None, issued - 300).unwrap();
+        verifier.verify_at(&attestation, &key, None, issued + max_age).unwrap();
+        assert!(verifier.verify_at(&attestation, &key, None, issued + max_age + 1).is_err());
+
+        let ageless = AttestationVerifier::new(AttestationPolicy { max_age_secs: None, ..Default::default() });
+        ageless.verify_at(&attestation, &key, None, issued + 100 * 365 * DAY).unwrap();
+
+        let (expiring, _) = issuer.attest("subject", "email", "a@example.com", Some(issued + DAY));
+        verifier.verify_at(&expiring, &key, None, issued + DAY - 1).unwrap();
+        assert!(verifier.verify_at(&expiring, &key, None, issued + DAY).is_err());
+    }
+
+    #[test]
+    fn check_freshness_rules() {
+        let policy = AttestationPolicy { max_age_secs: Some(DAY), max_clock_skew_secs: 60 };
+        policy.check_freshness(1_000, None, 1_000).unwrap();
+        policy.check_freshness(1_060, None, 1_000).unwrap();
+        assert!(policy.check_freshness(1_061, None, 1_000).is_err());
+        assert!(policy.check_freshness(1_000, Some(2_000), 2_000).is_err());
+        policy.check_freshness(1_000, Some(2_000), 1_999).unwrap();
+        assert!(policy.check_freshness(1_000, None, 1_001 + DAY).is_err());
+
+        let ageless = AttestationPolicy { max_age_secs: None, ..policy };
+        ageless.check_freshness(1_000, None, u64::MAX).unwrap();
+        assert!(ageless.check_freshness(1_000, Some(2_000), u64::MAX).is_err());
+    }
+
+    #[test]
+    fn trust_policy_finds_claims_from_trusted_issuers() {
+        let (issuer, stranger) = (IdentityManager::new(), IdentityManager::new());
+        let subject = "subject";
+        let (attestation, _) = issuer.attest(subject, "email", "a@example.com", None);
+        let (untrusted, _) = stranger.attest(subject, "email", "a@example.com", None);
+        let attestations = [untrusted, attestation.clone()];
+
+        let mut policy = TrustPolicy::default();
+        assert!(!policy.holds_claim(subject, "email", &attestations));
+        policy.trust_issuer(issuer.get_identity_hash().to_string(), issuer.public_key());
+        assert!(policy.is_trusted(issuer.get_identity_hash()));
+        assert_eq!(policy.find_claim(subject, "email", &attestations).unwrap().id(), attestation.id());
+
+        assert!(!policy.holds_claim("someone else", "email", &attestations));
+        assert!(!policy.holds_claim(subject, "phone", &attestations));
+
+        // Trusting a hash under the wrong key does not help the stranger
+        policy.trust_issuer(stranger.get_identity_hash().to_string(), issuer.public_key());
+        assert!(!policy.holds_claim(subject, "email", &attestations[..1]));
+
+        policy.distrust_issuer(issuer.get_identity_hash());
+        assert!(!policy.is_trusted(issuer.get_identity_hash()));
+        assert!(!policy.holds_claim(subject, "email", &attestations));
+    }
+
+    #[test]
+    fn trust_policy_follows_issuer_rotation() {
+        let mut issuer = IdentityManager::new();
+        let (old, _) = issuer.attest("subject", "email", "a@example.com", None);
+        let mut policy = TrustPolicy::default();
+        policy.trust_issuer(issuer.get_identity_hash().to_string(), issuer.public_key());
+        assert!(policy.holds_claim("subject", "email", std::slice::from_ref(&old)));
+
+        issuer.rotate_key();
+        let (new, _) = issuer.attest("subject", "email", "a@example.com", None);
+        assert!(!policy.holds_claim("subject", "email", std::slice::from_ref(&new)));
+
+        policy.trust_issuer(issuer.get_identity_hash().to_string(), issuer.public_key());
+        assert!(policy.holds_claim("subject", "email", std::slice::from_ref(&new)));
+        assert!(!policy.holds_claim("subject", "email", std::slice::from_ref(&old)));
+    }
+
+    #[test]
+    fn revocations_are_checked() {
+        let (issuer, other) = (IdentityManager::new(), IdentityManager::new());
+        let (attestation, _) = issuer.attest("subject", "email", "a@example.com", None);
+        let (kept, _) = issuer.attest("subject", "phone", "555", None);
+        let key = issuer.public_key();
+
+        let mut list = RevocationList::new(issuer.get_identity_hash());
+        list.revocations.push(issuer.revoke_attestation(&attestation).unwrap());
+        list.verify(&key).unwrap();
+        assert!(list.is_revoked(&attestation));
+        assert!(!list.is_revoked(&kept));
+
+        let verifier = AttestationVerifier::default();
+        assert!(verifier.verify_unrevoked(&attestation, &key, None, &list).is_err());
+        verifier.verify_unrevoked(&kept, &key, None, &list).unwrap();
+        let other_list = RevocationList::new(other.get_identity_hash());
+        assert!(verifier.verify_unrevoked(&kept, &key, None, &other_list).is_err());
+
+        let mut policy = TrustPolicy::default();
+        policy.trust_issuer(issuer.get_identity_hash().to_string(), key);
+        assert!(policy.holds_claim("subject", "email", std::slice::from_ref(&attestation)));
+        policy.update_revocations(list).unwrap();
+        assert!(!policy.holds_claim("subject", "email", std::slice::from_ref(&attestation)));
+        assert!(policy.holds_claim("subject", "phone", std::slice::from_ref(&kept)));
+    }
+
+    #[test]
+    fn forged_revocation_lists_are_rejected() {
+        let (issuer, other) = (IdentityManager::new(), IdentityManager::new());
+        let (attestation, _) = issuer.attest("subject", "email", "a@example.com", None);
+        let mut policy = TrustPolicy::default();
+        policy.trust_issuer(issuer.get_identity_hash().to_string(), issuer.public_key());
+
+        // Entry claims to be from the issuer but is signed by someone else
+        let mut forged = issuer.revoke_attestation(&attestation).unwrap();
+        forged.signature = hex::encode(other.sign(forged.signing_payload().as_bytes()).to_bytes());
+        let mut list = RevocationList::new(issuer.get_identity_hash());
+        list.revocations.push(forged);
+        assert!(list.verify(&issuer.public_key()).is_err());
+        assert!(policy.update_revocations(list).is_err());
+
+        // Another issuer's genuine entry inside the issuer's list
+        let (foreign, _) = other.attest("subject", "email", "a@example.com", None);
+        let mut list = RevocationList::new(issuer.get_identity_hash());
+        list.revocations.push(other.revoke_attestation(&foreign).unwrap());
+        assert!(policy.update_revocations(list).is_err());
+
+        // A list for an issuer that is not trusted
+        assert!(policy.update_revocations(RevocationList::new(other.get_identity_hash())).is_err());
+        assert!(policy.holds_claim("subject", "email", std::slice::from_ref(&attestation)));
+    }
+
+    #[test]
+    fn claim_commitments_only_open_with_the_exact_reveal() {
+        let issuer = IdentityManager::new();
+        let (attestation, reveal) = issuer.create_attestation("email", "a@example.com");
+        assert_eq!(reveal.commitment().unwrap(), attestation.value_hash);
+        assert!(attestation.opens(&reveal));
+
+        let mut wrong = reveal.clone();
+        wrong.value = "b@example.com".to_string();
+        assert!(!attestation.opens(&wrong));
+        let mut wrong = reveal.clone();
+        wrong.claim = "phone".to_string();
+        assert!(!attestation.opens(&wrong));
+        let mut wrong = reveal.clone();
+        wrong.salt = hex::encode([0u8; 32]);
+        assert!(!attestation.opens(&wrong));
+        let mut short = reveal.clone();
+        short.salt = hex::encode([0u8; 31]);
+        assert!(short.commitment().is_err());
+        assert!(!attestation.opens(&short));
+
+        // Fresh salt per commitment: equal values are unlinkable
+        let (again, _) = issuer.create_attestation("email", "a@example.com");
+        assert_ne!(again.value_hash, attestation.value_hash);
+        assert_ne!(
+            commit_claim_value("email", "a@example.com", &[1u8; 32]),
+            commit_claim_value("email", "a@example.com", &[2u8; 32])
+        );
+    }
+}
@@ -1,78 +1,137 @@
 // Example client demonstrating ZeroTrace usage
-// Shows identity creation, message sending with ZK proofs
+// Shows identity creation, client-side encryption and message sending with ZK proofs
 
-use zerotrace::identity::IdentityManager;
-use serde_json::json;
-use std::io;
+use zerotrace::{
+    identity::{Identity, IdentityManager}, sessions::LoginRequest, thread_id_for, CStateSnapshot,
+    Message, SendRequest,
+};
+use ed25519_dalek::PublicKey;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🔐 ZeroTrace Client Example\n");
-    
-    // Create identity
-    println!("1. Creating identity...");
+
+    // Create identities locally
+    println!("1. Creating identities...");
     let alice = IdentityManager::new();
     let alice_hash = alice.get_identity_hash();
     println!("   Alice's identity hash: {}", alice_hash);
-    
+
     let bob = IdentityManager::new();
     let bob_hash = bob.get_identity_hash();
     println!("   Bob's identity hash: {}\n", bob_hash);
-    
+
+    // Register public keys (private keys never leave this process)
+    let client = reqwest::Client::new();
+    for identity in [&alice, &bob] {
+        client
+            .post("http://127.0.0.1:8080/identity/register")
+            .json(&identity.registration_request())
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
     // Create thread ID
-    let thread_id = format!("{}:{}", alice_hash, bob_hash);
+    let thread_id = thread_id_for(alice_hash, bob_hash);
     println!("2. Thread ID: {}\n", thread_id);
-    
-    // Send message
+
+    // Each side derives the thread key from its own secret and the peer's registered public key
+    let alice_pubkey = fetch_public_key(&client, alice_hash).await?;
+    let bob_pubkey = fetch_public_key(&client, bob_hash).await?;
+    let alice_key = alice.derive_thread_key(&thread_id, &bob_pubkey)?;
+    let bob_key = bob.derive_thread_key(&thread_id, &alice_pubkey)?;
+
+    // Fetch Alice's current CSTATE to build the proof against
+    let cstate: CStateSnapshot = client
+        .get(format!("http://127.0.0.1:8080/cstate/{}", alice_hash))
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    // Encrypt and prove locally, then send only ciphertext + EndCap
     println!("3. Sending message...");
     let plaintext = "Hello from ZeroTrace! This message is end-to-end encrypted with ZK proofs.";
-    
-    let client = reqwest::Client::new();
+    let request = SendRequest::seal(&alice, bob_hash, &thread_id, &alice_key, plaintext, &cstate)?;
+
     let response = client
         .post("http://127.0.0.1:8080/send")
-        .json(&json!({
-            "thread_id": thread_id,
-            "recipient_id": bob_hash,
-            "plaintext": plaintext,
-            "sender_identity_hash": alice_hash,
-            "sender_signature": "sig_stub" // In production, sign with alice's key
-        }))
+        .json(&request)
         .send()
         .await?;
-    
+
     if response.status().is_success() {
         let result: serde_json::Value = response.json().await?;
         println!("   ✅ Message sent!");
-        println!("   Response: {}\n", json::to_string_pretty(&result)?);
+        println!("   Response: {}\n", serde_json::to_string_pretty(&result)?);
     } else {
         println!("   ❌ Error: {}", response.status());
         return Ok(());
     }
-    
-    // Read messages
+
+    // Read messages (Bob logs in, then decrypts locally)
     println!("4. Reading messages...");
+    let token = login(&client, &bob).await?;
     let response = client
-        .get(&format!("http://127.0.0.1:8080/read/{}", thread_id))
+        .get(format!("http://127.0.0.1:8080/messages/{}", thread_id))
+        .bearer_auth(&token)
         .send()
         .await?;
-    
+
     if response.status().is_success() {
-        let messages: serde_json::Value = response.json().await?;
-        println!("   Messages:\n{}", json::to_string_pretty(&messages)?);
+        let messages: Vec<Message> = response.json().await?;
+        for msg in messages {
+            let text = msg.decrypt(&bob_key)?;
+            println!("   [{}] {}", &msg.sender_id[..16], text);
+        }
     }
-    
+
     // Check CSTATE
     println!("\n5. Checking CSTATE root...");
     let response = client
-        .get(&format!("http://127.0.0.1:8080/cstate/{}", alice_hash))
+        .get(format!("http://127.0.0.1:8080/cstate/{}", alice_hash))
         .send()
         .await?;
-    
+
     if response.status().is_success() {
         let cstate: serde_json::Value = response.json().await?;
-        println!("   CSTATE: {}", json::to_string_pretty(&cstate)?);
+        println!("   CSTATE: {}", serde_json::to_string_pretty(&cstate)?);
     }
-    
+
     Ok(())
 }
 
+/// Sign a login challenge and return the session token
+async fn login(client: &reqwest::Client, identity: &IdentityManager) -> Result<String, Box<dyn std::error::Error>> {
+    let challenge: serde_json::Value = client
+        .post("http://127.0.0.1:8080/auth/challenge")
+        .json(&serde_json::json!({ "identity_hash": identity.get_identity_hash() }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let challenge = challenge["challenge"].as_str().ok_or("challenge missing from response")?;
+    let session: serde_json::Value = client
+        .post("http://127.0.0.1:8080/auth/login")
+        .json(&LoginRequest::sign(identity, challenge))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(session["token"].as_str().ok_or("token missing from response")?.to_string())
+}
+
+/// Look up a registered identity's public key
+async fn fetch_public_key(client: &reqwest::Client, identity_hash: &str) -> Result<PublicKey, Box<dyn std::error::Error>> {
+    let identity: Identity = client
+        .get(format!("http://127.0.0.1:8080/identity/{}", identity_hash))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(PublicKey::from_bytes(&identity.public_key)?)
+}
@@ -1,139 +1,290 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Result, middleware::Logger};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Result, middleware::Logger, http::StatusCode, ResponseError};
 use actix_files::Files;
 use actix_cors::Cors;
 use serde_json::json;
 use std::sync::Mutex;
 use zerotrace::{
-    decrypt_message, encrypt_message, Message, MessageStore, SendRequest,
-    identity::IdentityManager,
-    commitments::{compute_message_commitment, hash_plaintext, StateCommitment},
-    proofs::{CFCProof, create_endcap, verify_cfc_proof},
+    Message, MessageStore, SendRequest, PROTOCOL_VERSION, Thread, ThreadKind, now_secs, thread_id_for,
+    identity::{Attestation, Identity, RegistrationRequest},
+    attestations::{AttestationRevocation, AttestationVerifier},
+    commitments::compute_cstate_root,
+    rotation::RotationCertificate,
+    derivation::SubkeyCertificate,
+    devices::DeviceRevocation,
+    prekeys::{PrekeyStore, PrekeyUpload},
+    handles::{HandleClaim, HandleRegistry, HandleTransferRequest},
+    sessions::{ChallengeStore, LoginRequest, SessionClaims, SessionIssuer},
+    groups::{is_group_thread, GroupRegistry, GroupUpdate, MembershipAction},
+    proofs::{verify_cfc_proof, SEND_MESSAGE_CFC},
 };
-use chacha20poly1305::XNonce;
 use base64::{Engine as _, engine::general_purpose};
-use hex;
-use uuid;
+use ed25519_dalek::PublicKey;
 
 type AppState = web::Data<Mutex<MessageStore>>;
-type IdentityState = web::Data<Mutex<std::collections::HashMap<String, IdentityManager>>>;
+type IdentityState = web::Data<Mutex<std::collections::HashMap<String, Identity>>>; // public halves only
+type PrekeyState = web::Data<Mutex<PrekeyStore>>;
+type HandleState = web::Data<Mutex<HandleRegistry>>;
+type ChallengeState = web::Data<Mutex<ChallengeStore>>;
+type SessionState = web::Data<SessionIssuer>;
+type GroupsState = web::Data<Mutex<GroupRegistry>>;
 
-/// Send an encrypted message with ZK proof
+/// Max difference between a request's signed timestamp and server time
+const MAX_CLOCK_SKEW_SECS: u64 = 300;
+/// Attestations a single identity can hold on the server
+const MAX_ATTESTATIONS_PER_IDENTITY: usize = 64;
+
+/// Authentication failures, returned as `{"error": code, "message": ...}`
+#[derive(Debug)]
+enum AuthError {
+    UnknownSender,     // 401: no registered public key for the sender
+    StaleRequest,      // 401: signed timestamp outside the accepted window
+    InvalidSignature,  // 403: signature does not verify (forged or tampered)
+    UnknownDevice,     // 403: device key not endorsed by the sender, or revoked
+    InvalidChallenge,  // 401: login challenge unknown, expired or already used
+    MissingSession,    // 401: no `Authorization: Bearer` session token
+    InvalidSession,    // 401: session token forged or expired
+    NotParticipant,    // 403: authenticated identity is not in the thread
+}
+
+impl AuthError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::UnknownSender => "unknown_sender",
+            AuthError::StaleRequest => "stale_request",
+            AuthError::InvalidSignature => "invalid_signature",
+            AuthError::UnknownDevice => "unknown_device",
+            AuthError::InvalidChallenge => "invalid_challenge",
+            AuthError::MissingSession => "missing_session",
+            AuthError::InvalidSession => "invalid_session",
+            AuthError::NotParticipant => "not_participant",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::UnknownSender => write!(f, "Sender has no registered public key"),
+            AuthError::StaleRequest => write!(f, "Request timestamp is outside the accepted window"),
+            AuthError::InvalidSignature => write!(f, "Signature verification failed"),
+            AuthError::UnknownDevice => write!(f, "Device key is not endorsed by this identity or has been revoked"),
+            AuthError::InvalidChallenge => write!(f, "Login challenge is unknown, expired or already used"),
+            AuthError::MissingSession => write!(f, "Log in and send the session token as a Bearer token"),
+            AuthError::InvalidSession => write!(f, "Session token is invalid or has expired"),
+            AuthError::NotParticipant => write!(f, "Identity is not a participant of this thread"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::UnknownSender
+            | AuthError::StaleRequest
+            | AuthError::InvalidChallenge
+            | AuthError::MissingSession
+            | AuthError::InvalidSession => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidSignature | AuthError::UnknownDevice | AuthError::NotParticipant => StatusCode::FORBIDDEN,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(json!({
+            "error": self.code(),
+            "message": self.to_string()
+        }))
+    }
+}
+
+/// First `len` characters of a client-supplied id, for log lines
+fn short(id: &str, len: usize) -> String {
+    id.chars().take(len).collect()
+}
+
+/// Key an identity signs with: its root key, or one of its active device keys
+fn signing_key(identity: &Identity, device_key: Option<&str>) -> std::result::Result<PublicKey, AuthError> {
+    match device_key {
+        None => PublicKey::from_bytes(&identity.public_key).map_err(|_| AuthError::UnknownSender),
+        Some(device_key) => identity
+            .active_device_keys()
+            .into_iter()
+            .find(|key| hex::encode(key.as_bytes()) == device_key)
+            .ok_or(AuthError::UnknownDevice),
+    }
+}
+
+/// Session of the caller, from `Authorization: Bearer <token>`
+fn authenticate(req: &HttpRequest, sessions: &SessionIssuer) -> std::result::Result<SessionClaims, AuthError> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthError::MissingSession)?;
+    sessions.verify(token, now_secs()).map_err(|_| AuthError::InvalidSession)
+}
+
+/// Accept a client-sealed message
+/// 
+/// The client encrypts, commits and proves locally; this endpoint only:
+/// 1. Authenticates the sender's signature against its registered public key
+/// 2. Checks the ciphertext, nonce and protocol version are well-formed
+/// 3. Verifies the CFC proof and that it commits to this message
+/// 4. Checks the proof starts from the sender's current CSTATE root
+/// 5. Checks the VAA nonce (replay protection)
+/// 6. Updates the CSTATE root and stores the ciphertext, with the VAA nonce as
+///    its sequence number (part of the AEAD associated data)
 /// 
-/// This endpoint:
-/// 1. Encrypts the message using XChaCha20-Poly1305
-/// 2. Generates a message commitment
-/// 3. Creates a ZK proof for the state transition
-/// 4. Updates the CSTATE root
-/// 5. Stores the encrypted message
+/// The server holds no thread keys and cannot decrypt anything it stores.
 async fn send_message(
     req: web::Json<SendRequest>,
     state: AppState,
     identity_state: IdentityState,
+    group_state: GroupsState,
 ) -> Result<HttpResponse> {
-    println!("📨 [SEND] Received message from {}", &req.sender_identity_hash[..16]);
-    println!("   Thread: {}", &req.thread_id[..40.min(req.thread_id.len())]);
-    println!("   Plaintext length: {} bytes", req.plaintext.len());
+    let req = req.into_inner();
+    println!("📨 [SEND] Received message from {}", short(&req.sender_identity_hash, 16));
+    println!("   Thread: {}", short(&req.thread_id, 40));
     
-    let mut store = state.lock().unwrap();
-    let mut identities = identity_state.lock().unwrap();
+    // Authenticate sender (root key, or any currently endorsed device key)
+    let sender_pubkey = {
+        let identities = identity_state.lock().unwrap();
+        let identity = identities
+            .get(&req.sender_identity_hash)
+            .ok_or(AuthError::UnknownSender)?;
+        if !is_group_thread(&req.thread_id) {
+            if req.recipient_id == req.sender_identity_hash {
+                return Err(actix_web::error::ErrorBadRequest("Cannot send a message to yourself"));
+            }
+            if !identities.contains_key(&req.recipient_id) {
+                return Err(actix_web::error::ErrorNotFound("Recipient not registered"));
+            }
+        }
+        signing_key(identity, req.device_key.as_deref())?
+    };
+    if now_secs().abs_diff(req.timestamp) > MAX_CLOCK_SKEW_SECS {
+        return Err(AuthError::StaleRequest.into());
+    }
+    if !req.verify_signatures(&sender_pubkey) {
+        println!("   ❌ Invalid sender signature");
+        return Err(AuthError::InvalidSignature.into());
+    }
+    println!("   ✅ Sender signature verified");
+    if is_group_thread(&req.thread_id) {
+        let groups = group_state.lock().unwrap();
+        if req.recipient_id != req.thread_id || !groups.is_member(&req.thread_id, &req.sender_identity_hash) {
+            return Err(AuthError::NotParticipant.into());
+        }
+        let epoch = groups.get(&req.thread_id).map(|g| g.state.epoch);
+        if Some(req.epoch) != epoch || !groups.is_keyed(&req.thread_id) {
+            println!("   ❌ Message is not under the group's current key epoch");
+            return Err(actix_web::error::ErrorConflict("Message is not encrypted under the group's current key epoch"));
+        }
+    } else if req.thread_id != thread_id_for(&req.sender_identity_hash, &req.recipient_id) {
+        return Err(AuthError::NotParticipant.into());
+    }
     
-    // Get or create sender identity (for demo, create if not exists)
-    let sender = identities.entry(req.sender_identity_hash.clone())
-        .or_insert_with(|| {
-            println!("   🔑 Creating identity from seed");
-            IdentityManager::from_seed(req.sender_identity_hash.as_bytes())
-        });
-    
-    // Encrypt message
-    println!("   🔐 Encrypting message...");
-    let key = store.get_or_create_key(&req.thread_id);
-    let (ciphertext, nonce) = encrypt_message(&key, &req.plaintext)
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    println!("   ✅ Encryption complete ({} bytes)", ciphertext.len());
-    
-    // Compute commitments
-    println!("   📝 Computing message commitment...");
-    let plaintext_hash = hash_plaintext(&req.plaintext);
-    let nonce_bytes = nonce.as_slice();
-    let message_commitment = compute_message_commitment(
-        &req.sender_identity_hash,
-        &req.thread_id,
-        nonce_bytes,
-        &plaintext_hash,
-    );
-    
-    // Get current CSTATE root
-    let start_root = store.get_cstate_root(&req.sender_identity_hash);
+    let ciphertext = general_purpose::STANDARD
+        .decode(&req.ciphertext)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let nonce_bytes = general_purpose::STANDARD
+        .decode(&req.iv)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    if nonce_bytes.len() != 24 {
+        return Err(actix_web::error::ErrorBadRequest("iv must be a 24-byte XChaCha20 nonce"));
+    }
+    if req.version != PROTOCOL_VERSION {
+        return Err(actix_web::error::ErrorBadRequest("Unsupported message protocol version"));
+    }
+    println!("   Ciphertext length: {} bytes", ciphertext.len());
+    
+    let mut store = state.lock().unwrap();
+    // Direct threads are created below, once the message is accepted
+    if is_group_thread(&req.thread_id) && !store.is_participant(&req.thread_id, &req.sender_identity_hash) {
+        return Err(AuthError::NotParticipant.into());
+    }
+    let endcap = req.endcap;
+    let proof = &endcap.proof;
     
-    // Create state commitment
-    let thread_roots = store.get_thread_roots(&req.sender_identity_hash);
-    let state_commitment = StateCommitment::new(
-        req.thread_id.clone(),
-        message_commitment.clone(),
-        &thread_roots,
-    );
-    
-    // Generate ZK proof (simulated)
-    println!("   🔍 Generating ZK proof...");
-    let proof = CFCProof::for_send_message(
-        &start_root,
-        &state_commitment.cstate_root,
-        &message_commitment,
-    );
-    
-    // Verify proof (should always pass for stub)
-    if !verify_cfc_proof(&proof) {
+    // Verify proof (simulated)
+    println!("   🔍 Verifying ZK proof...");
+    if proof.cfc_fingerprint != SEND_MESSAGE_CFC || !verify_cfc_proof(proof) {
         println!("   ❌ Proof verification failed!");
-        return Err(actix_web::error::ErrorInternalServerError("Proof verification failed"));
+        return Err(actix_web::error::ErrorBadRequest("Proof verification failed"));
+    }
+    if proof.public_inputs != [req.message_commitment.clone()] {
+        return Err(actix_web::error::ErrorBadRequest("Proof does not commit to this message"));
     }
-    println!("   ✅ ZK proof verified");
     
-    // Create EndCap
-    let vaa_nonce = store.get_next_vaa_nonce(&req.sender_identity_hash);
-    let signature = hex::encode(sender.sign(format!("{}:{}", message_commitment, vaa_nonce).as_bytes()).to_bytes());
+    // Check the state transition against the sender's current CSTATE
+    let start_root = store.get_cstate_root(&req.sender_identity_hash);
+    let mut thread_roots = store.get_thread_roots(&req.sender_identity_hash);
+    thread_roots.push(req.message_commitment.clone());
+    let end_root = compute_cstate_root(&thread_roots);
+    if proof.start_cstate_root != start_root || proof.end_cstate_root != end_root {
+        println!("   ❌ Stale CSTATE root");
+        return Err(actix_web::error::ErrorConflict("Proof is not based on the current CSTATE root"));
+    }
+    println!("   ✅ ZK proof verified");
     
-    let encrypted_blob_address = format!("da://encrypted/{}", uuid::Uuid::new_v4());
-    let endcap = create_endcap(proof, encrypted_blob_address, vaa_nonce, signature);
+    // Replay protection
+    if endcap.vaa_nonce != store.get_vaa_nonce(&req.sender_identity_hash) + 1 {
+        println!("   ❌ Unexpected VAA nonce {}", endcap.vaa_nonce);
+        return Err(actix_web::error::ErrorConflict("Unexpected VAA nonce"));
+    }
+    store.get_next_vaa_nonce(&req.sender_identity_hash);
+    if !is_group_thread(&req.thread_id) {
+        store.direct_thread(&req.sender_identity_hash, &req.recipient_id, now_secs());
+    }
     
     // Update state
-    store.update_cstate_root(&req.sender_identity_hash, state_commitment.cstate_root.clone());
-    store.add_thread_root(&req.sender_identity_hash, message_commitment.clone());
-    println!("   📊 CSTATE root updated: {}", &state_commitment.cstate_root[..16]);
+    store.update_cstate_root(&req.sender_identity_hash, end_root.clone());
+    store.add_thread_root(&req.sender_identity_hash, req.message_commitment.clone());
+    println!("   📊 CSTATE root updated: {}", &end_root[..16]);
     
     // Create message
     let message = Message {
-        thread_id: req.thread_id.clone(),
-        sender_id: req.sender_identity_hash.clone(),
-        ciphertext: general_purpose::STANDARD.encode(&ciphertext),
-        iv: general_purpose::STANDARD.encode(nonce_bytes),
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
-        message_commitment,
+        thread_id: req.thread_id,
+        sender_id: req.sender_identity_hash,
+        ciphertext: req.ciphertext,
+        iv: req.iv,
+        timestamp: now_secs(),
+        message_commitment: req.message_commitment,
+        sequence: endcap.vaa_nonce,
         endcap: Some(endcap),
+        epoch: req.epoch,
+        version: req.version,
     };
     
     store.add_message(message.clone());
     println!("   ✅ Message stored successfully");
-    println!("   📬 Total messages in thread: {}", store.get_messages(&req.thread_id).map(|m| m.len()).unwrap_or(0));
+    println!("   📬 Total messages in thread: {}", store.get_messages(&message.thread_id).map(|m| m.len()).unwrap_or(0));
     
     Ok(HttpResponse::Ok().json(json!({
         "status": "sent",
         "thread_id": message.thread_id,
         "message_id": message.timestamp,
-        "cstate_root": state_commitment.cstate_root,
+        "cstate_root": end_root,
         "proof_verified": true
     })))
 }
 
 /// Get encrypted messages for a thread
 async fn get_messages(
+    req: HttpRequest,
     path: web::Path<String>,
     state: AppState,
+    sessions: SessionState,
 ) -> Result<HttpResponse> {
-    let store = state.lock().unwrap();
+    let session = authenticate(&req, &sessions)?;
     let thread_id = path.into_inner();
+    let store = state.lock().unwrap();
+    let thread = store
+        .get_thread(&thread_id)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Thread not found"))?;
+    if !thread.is_participant(&session.identity_hash) {
+        return Err(AuthError::NotParticipant.into());
+    }
     
     match store.get_messages(&thread_id) {
         Some(messages) => Ok(HttpResponse::Ok().json(messages)),
@@ -141,126 +292,677 @@ async fn get_messages(
     }
 }
 
-/// Decrypt and read messages for a thread
-/// Returns decrypted plaintext messages with metadata
-async fn decrypt_and_read(
+#[derive(serde::Deserialize)]
+struct ChallengeRequest {
+    identity_hash: String,
+}
+
+/// Issue a one-time login challenge for a registered identity
+async fn issue_challenge(
+    req: web::Json<ChallengeRequest>,
+    identity_state: IdentityState,
+    challenge_state: ChallengeState,
+) -> Result<HttpResponse> {
+    if !identity_state.lock().unwrap().contains_key(&req.identity_hash) {
+        return Err(AuthError::UnknownSender.into());
+    }
+    let (challenge, expires_at) = challenge_state.lock().unwrap().issue(&req.identity_hash, now_secs());
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "challenge": challenge,
+        "expires_at": expires_at
+    })))
+}
+
+/// Exchange a signed challenge for a short-lived session token
+async fn login(
+    req: web::Json<LoginRequest>,
+    identity_state: IdentityState,
+    challenge_state: ChallengeState,
+    sessions: SessionState,
+) -> Result<HttpResponse> {
+    let req = req.into_inner();
+    println!("🔑 [AUTH] Login for {}", short(&req.identity_hash, 16));
+    
+    let key = {
+        let identities = identity_state.lock().unwrap();
+        let identity = identities.get(&req.identity_hash).ok_or(AuthError::UnknownSender)?;
+        signing_key(identity, req.device_key.as_deref())?
+    };
+    challenge_state
+        .lock()
+        .unwrap()
+        .take(&req.challenge, &req.identity_hash, now_secs())
+        .map_err(|_| AuthError::InvalidChallenge)?;
+    if !req.verify(&key) {
+        println!("   ❌ Invalid login signature");
+        return Err(AuthError::InvalidSignature.into());
+    }
+    let token = sessions.issue(&req.identity_hash, now_secs());
+    let claims = sessions.verify(&token, now_secs()).map_err(actix_web::error::ErrorInternalServerError)?;
+    println!("   ✅ Session issued");
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "token": token,
+        "identity_hash": claims.identity_hash,
+        "expires_at": claims.expires_at
+    })))
+}
+
+/// Register a client-held identity
+/// 
+/// Keys are generated on the client. The server checks the identity hash is
+/// derived from the public key and that the client proved possession of the
+/// private key, then stores only the public half.
+async fn register_identity(
+    req: web::Json<RegistrationRequest>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    println!("🆔 [IDENTITY] Registering identity...");
+    let identity = req.verify().map_err(|e| {
+        println!("   ❌ Registration rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    let identity_hash = identity.identity_hash.clone();
+    let public_key = hex::encode(&identity.public_key);
+    
+    {
+        let mut identities = identity_state.lock().unwrap();
+        if identities.contains_key(&identity_hash) {
+            println!("   ❌ Identity already registered");
+            return Err(actix_web::error::ErrorConflict("Identity is already registered"));
+        }
+        identities.insert(identity_hash.clone(), identity);
+    }
+    println!("   ✅ Identity registered: {}", &identity_hash[..16]);
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "public_key": public_key,
+        "status": "registered"
+    })))
+}
+
+/// Look up a registered identity's public key
+async fn get_identity(
+    path: web::Path<String>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identities = identity_state.lock().unwrap();
+    match identities.get(&path.into_inner()) {
+        Some(identity) => Ok(HttpResponse::Ok().json(identity)),
+        None => Err(actix_web::error::ErrorNotFound("Identity not registered")),
+    }
+}
+
+/// Record a key rotation for an identity
+/// 
+/// The certificate must be signed by the identity's current key and continue
+/// its chain; the stored public key then moves to the new key.
+async fn rotate_identity_key(
+    path: web::Path<String>,
+    req: web::Json<RotationCertificate>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identity_hash = path.into_inner();
+    let rotation = req.into_inner();
+    println!("🔄 [IDENTITY] Key rotation #{} for {}", rotation.sequence, short(&identity_hash, 16));
+    if rotation.identity_hash != identity_hash {
+        return Err(actix_web::error::ErrorBadRequest("Rotation is for a different identity"));
+    }
+    
+    let mut identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get_mut(&identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    identity.apply_rotation(rotation).map_err(|e| {
+        println!("   ❌ Rotation rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    println!("   ✅ Current key is now rotation #{}", identity.rotations.len());
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "public_key": hex::encode(&identity.public_key),
+        "rotations": identity.rotations.len()
+    })))
+}
+
+/// Add a device key endorsed by the identity's root key
+async fn add_device(
+    path: web::Path<String>,
+    req: web::Json<SubkeyCertificate>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identity_hash = path.into_inner();
+    println!("📱 [DEVICES] Adding device for {}", short(&identity_hash, 16));
+    
+    let mut identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get_mut(&identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    identity.add_device(req.into_inner()).map_err(|e| {
+        println!("   ❌ Device rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "active_devices": identity.active_device_keys().len()
+    })))
+}
+
+/// Revoke one of an identity's device keys
+async fn revoke_device(
+    path: web::Path<String>,
+    req: web::Json<DeviceRevocation>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identity_hash = path.into_inner();
+    println!("📱 [DEVICES] Revoking device for {}", short(&identity_hash, 16));
+    
+    let mut identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get_mut(&identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    identity.revoke_device(req.into_inner()).map_err(|e| {
+        println!("   ❌ Revocation rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "active_devices": identity.active_device_keys().len()
+    })))
+}
+
+/// Attach an attestation about this identity, signed by any registered issuer.
+/// Only the subject (bearer token) can attach, so issuers cannot pile claims onto
+/// identities that never accepted them; each identity holds at most
+/// `MAX_ATTESTATIONS_PER_IDENTITY`.
+async fn add_attestation(
+    req: HttpRequest,
     path: web::Path<String>,
+    body: web::Json<Attestation>,
+    identity_state: IdentityState,
+    sessions: SessionState,
+) -> Result<HttpResponse> {
+    let identity_hash = path.into_inner();
+    println!("📜 [ATTESTATIONS] Attaching attestation to {}", short(&identity_hash, 16));
+    
+    if authenticate(&req, &sessions)?.identity_hash != identity_hash {
+        return Err(AuthError::NotParticipant.into());
+    }
+    let attestation = body.into_inner();
+    if attestation.subject != identity_hash {
+        return Err(actix_web::error::ErrorBadRequest("Attestation subject must be the identity"));
+    }
+    let mut identities = identity_state.lock().unwrap();
+    let issuer = identities
+        .get(&attestation.issuer)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Issuer not registered"))?;
+    let issuer_key = issuer.current_key().map_err(actix_web::error::ErrorBadRequest)?;
+    AttestationVerifier::default()
+        .verify_unrevoked(&attestation, &issuer_key, None, &issuer.revocation_list())
+        .map_err(|e| {
+            println!("   ❌ Attestation rejected: {}", e);
+            actix_web::error::ErrorBadRequest(e)
+        })?;
+    let identity = identities
+        .get_mut(&identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    if identity.attestations.iter().any(|a| a.id() == attestation.id()) {
+        return Err(actix_web::error::ErrorConflict("Attestation is already attached"));
+    }
+    if identity.attestations.len() >= MAX_ATTESTATIONS_PER_IDENTITY {
+        return Err(actix_web::error::ErrorConflict("Identity holds the maximum number of attestations"));
+    }
+    println!("   ✅ Claim \"{}\" from {} attached", attestation.claim, short(&attestation.issuer, 16));
+    identity.attestations.push(attestation);
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "attestations": identity.attestations.len()
+    })))
+}
+
+/// List the attestations attached to an identity, minus any their issuer revoked
+async fn get_attestations(
+    path: web::Path<String>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    let attestations: Vec<_> = identity
+        .attestations
+        .iter()
+        .filter(|a| {
+            !identities
+                .get(&a.issuer)
+                .is_some_and(|issuer| issuer.revocation_list().is_revoked(a))
+        })
+        .collect();
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity.identity_hash,
+        "attestations": attestations
+    })))
+}
+
+/// Publish the withdrawal of an attestation issued by this identity
+async fn revoke_attestation(
+    path: web::Path<String>,
+    req: web::Json<AttestationRevocation>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identity_hash = path.into_inner();
+    println!("📜 [ATTESTATIONS] Revocation by {}", short(&identity_hash, 16));
+    
+    let mut identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get_mut(&identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    identity.revoke_attestation(req.into_inner()).map_err(|e| {
+        println!("   ❌ Revocation rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity_hash,
+        "revocations": identity.attestation_revocations.len()
+    })))
+}
+
+/// Serve the issuer's attestation revocation list
+async fn get_revocations(
+    path: web::Path<String>,
+    identity_state: IdentityState,
+) -> Result<HttpResponse> {
+    let identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    
+    Ok(HttpResponse::Ok().json(identity.revocation_list()))
+}
+
+/// Claim an unregistered handle (first come, first served). An identity holds at
+/// most `MAX_HANDLES_PER_IDENTITY` handles, and each binding attestation counts
+/// toward `MAX_ATTESTATIONS_PER_IDENTITY`.
+async fn register_handle(
+    req: web::Json<HandleClaim>,
+    identity_state: IdentityState,
+    handle_state: HandleState,
+) -> Result<HttpResponse> {
+    let claim = req.into_inner();
+    println!("🏷️  [HANDLES] Registering @{}", claim.handle);
+    
+    let mut identities = identity_state.lock().unwrap();
+    let mut handles = handle_state.lock().unwrap();
+    let owner = identities
+        .get_mut(claim.identity_hash())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    if handles.resolve(&claim.handle).is_some_and(|r| r.identity_hash != owner.identity_hash) {
+        println!("   ❌ @{} is already taken", claim.handle);
+        return Err(actix_web::error::ErrorConflict("Handle is already taken"));
+    }
+    let owner_key = owner.current_key().map_err(actix_web::error::ErrorBadRequest)?;
+    let previous = handles.resolve(&claim.handle).map(|r| r.claim.attestation.id());
+    if previous.is_none() && owner.attestations.len() >= MAX_ATTESTATIONS_PER_IDENTITY {
+        return Err(actix_web::error::ErrorConflict("Identity holds the maximum number of attestations"));
+    }
+    handles.register(claim.clone(), &owner_key).map_err(|e| {
+        println!("   ❌ Handle rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    owner.attestations.retain(|a| Some(a.id()) != previous);
+    owner.attestations.push(claim.attestation);
+    println!("   ✅ @{} -> {}", claim.handle, &owner.identity_hash[..16]);
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "handle": format!("@{}", claim.handle),
+        "identity_hash": owner.identity_hash
+    })))
+}
+
+/// Resolve a handle to its identity hash and current public key
+async fn resolve_handle(
+    path: web::Path<String>,
+    identity_state: IdentityState,
+    handle_state: HandleState,
+) -> Result<HttpResponse> {
+    let identities = identity_state.lock().unwrap();
+    let handles = handle_state.lock().unwrap();
+    let record = handles
+        .resolve(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Handle not registered"))?;
+    let identity = identities
+        .get(&record.identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "handle": format!("@{}", record.handle),
+        "identity_hash": record.identity_hash,
+        "public_key": hex::encode(&identity.public_key),
+        "attestation": record.claim.attestation,
+        "reveal": record.claim.reveal,
+        "transfers": record.transfers
+    })))
+}
+
+/// Move a handle to another identity with the current owner's signature
+async fn transfer_handle(
+    path: web::Path<String>,
+    req: web::Json<HandleTransferRequest>,
+    identity_state: IdentityState,
+    handle_state: HandleState,
+) -> Result<HttpResponse> {
+    let request = req.into_inner();
+    println!("🏷️  [HANDLES] Transferring @{}", request.transfer.handle);
+    
+    let mut identities = identity_state.lock().unwrap();
+    let mut handles = handle_state.lock().unwrap();
+    let record = handles
+        .resolve(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Handle not registered"))?;
+    if record.handle != request.transfer.handle {
+        return Err(actix_web::error::ErrorBadRequest("Transfer is for a different handle"));
+    }
+    let (from_hash, old_binding) = (record.identity_hash.clone(), record.claim.attestation.id());
+    let key_of = |hash: &str| {
+        identities
+            .get(hash)
+            .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?
+            .current_key()
+            .map_err(actix_web::error::ErrorBadRequest)
+    };
+    let from_key = key_of(&from_hash)?;
+    let to_key = key_of(&request.transfer.to_identity)?;
+    if identities[&request.transfer.to_identity].attestations.len() >= MAX_ATTESTATIONS_PER_IDENTITY {
+        return Err(actix_web::error::ErrorConflict("Identity holds the maximum number of attestations"));
+    }
+    handles.transfer(request.clone(), &from_key, &to_key, now_secs()).map_err(|e| {
+        println!("   ❌ Transfer rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    
+    if let Some(previous_owner) = identities.get_mut(&from_hash) {
+        previous_owner.attestations.retain(|a| a.id() != old_binding);
+    }
+    if let Some(new_owner) = identities.get_mut(&request.transfer.to_identity) {
+        new_owner.attestations.push(request.claim.attestation);
+    }
+    println!("   ✅ @{} now -> {}", request.transfer.handle, short(&request.transfer.to_identity, 16));
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "handle": format!("@{}", request.transfer.handle),
+        "identity_hash": request.transfer.to_identity
+    })))
+}
+
+/// Create a group from a signed Create event and the first sealed group key
+async fn create_group(
+    req: web::Json<GroupUpdate>,
     state: AppState,
+    identity_state: IdentityState,
+    group_state: GroupsState,
 ) -> Result<HttpResponse> {
+    let update = req.into_inner();
+    println!("👥 [GROUPS] Creating {}", update.event.group_id);
+    
+    let identities = identity_state.lock().unwrap();
+    if let MembershipAction::Create { members } = &update.event.action {
+        if let Some(unknown) = members.iter().find(|m| !identities.contains_key(*m)) {
+            return Err(actix_web::error::ErrorBadRequest(format!("Member {} is not registered", unknown)));
+        }
+    }
+    let actor_key = identities
+        .get(&update.event.actor)
+        .ok_or(AuthError::UnknownSender)?
+        .current_key()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    // Lock order: groups before messages
+    let mut groups = group_state.lock().unwrap();
     let mut store = state.lock().unwrap();
-    let thread_id = path.into_inner();
+    if store.get_thread(&update.event.group_id).is_some() {
+        return Err(actix_web::error::ErrorConflict("Thread id is already in use"));
+    }
+    let group = groups.create(update, &actor_key).map_err(|e| {
+        println!("   ❌ Group rejected: {}", e);
+        actix_web::error::ErrorBadRequest(e)
+    })?;
+    store
+        .create_thread(Thread::group(group, now_secs()))
+        .map_err(actix_web::error::ErrorConflict)?;
+    println!("   ✅ {} members, epoch {}", group.members.len(), group.epoch);
     
-    // Reduced logging - only log first read or when messages change
-    let key = store.get_or_create_key(&thread_id);
-    let empty_vec = Vec::new();
-    let messages = store.get_messages(&thread_id).unwrap_or(&empty_vec);
-    
-    let mut decrypted = Vec::new();
-    for msg in messages {
-        let ciphertext = general_purpose::STANDARD
-            .decode(&msg.ciphertext)
-            .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-        let nonce_bytes = general_purpose::STANDARD
-            .decode(&msg.iv)
-            .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-        let nonce = XNonce::from_slice(&nonce_bytes);
-        
-        match decrypt_message(&key, &ciphertext, nonce) {
-            Ok(plaintext) => {
-                decrypted.push(json!({
-                    "sender": msg.sender_id,
-                    "text": plaintext,
-                    "timestamp": msg.timestamp,
-                    "commitment": msg.message_commitment,
-                    "proof_present": msg.endcap.is_some()
-                }));
-            }
-            Err(e) => {
-                eprintln!("Decrypt error: {}", e);
-            }
+    Ok(HttpResponse::Ok().json(group))
+}
+
+/// Add, remove or rekey: a signed membership event plus the new epoch's sealed keys
+async fn post_group_event(
+    path: web::Path<String>,
+    req: web::Json<GroupUpdate>,
+    state: AppState,
+    identity_state: IdentityState,
+    group_state: GroupsState,
+) -> Result<HttpResponse> {
+    let group_id = path.into_inner();
+    let update = req.into_inner();
+    if update.event.group_id != group_id {
+        return Err(actix_web::error::ErrorBadRequest("Event is for a different group"));
+    }
+    println!("👥 [GROUPS] Event {} on {}", update.event.sequence, group_id);
+    
+    let identities = identity_state.lock().unwrap();
+    if let MembershipAction::Add { member } = &update.event.action {
+        if !identities.contains_key(member) {
+            return Err(actix_web::error::ErrorBadRequest("New member is not registered"));
         }
     }
+    let actor_key = identities
+        .get(&update.event.actor)
+        .ok_or(AuthError::UnknownSender)?
+        .current_key()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let mut groups = group_state.lock().unwrap();
+    if groups.get(&group_id).is_none() {
+        return Err(actix_web::error::ErrorNotFound("Group not found"));
+    }
+    let group = groups.apply(update, &actor_key).map_err(|e| {
+        println!("   ❌ Event rejected: {}", e);
+        actix_web::error::ErrorConflict(e)
+    })?;
+    state
+        .lock()
+        .unwrap()
+        .set_participants(&group_id, group.members.clone())
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    println!("   ✅ {} members, epoch {}", group.members.len(), group.epoch);
+    
+    Ok(HttpResponse::Ok().json(group))
+}
+
+/// Group membership and its signed event log (members only)
+async fn get_group(
+    req: HttpRequest,
+    path: web::Path<String>,
+    group_state: GroupsState,
+    sessions: SessionState,
+) -> Result<HttpResponse> {
+    let session = authenticate(&req, &sessions)?;
+    let groups = group_state.lock().unwrap();
+    let record = groups
+        .get(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Group not found"))?;
+    if !record.state.is_member(&session.identity_hash) {
+        return Err(AuthError::NotParticipant.into());
+    }
+    
+    Ok(HttpResponse::Ok().json(json!({
+        "state": record.state,
+        "events": record.events
+    })))
+}
+
+/// Epoch keys sealed to the logged-in member, oldest first
+async fn get_group_keys(
+    req: HttpRequest,
+    path: web::Path<String>,
+    group_state: GroupsState,
+    sessions: SessionState,
+) -> Result<HttpResponse> {
+    let session = authenticate(&req, &sessions)?;
+    let group_id = path.into_inner();
+    let groups = group_state.lock().unwrap();
+    if !groups.is_member(&group_id, &session.identity_hash) {
+        return Err(AuthError::NotParticipant.into());
+    }
     
-    Ok(HttpResponse::Ok().json(decrypted))
+    Ok(HttpResponse::Ok().json(groups.keys_for(&group_id, &session.identity_hash)))
 }
 
-/// Create a new ED25519 identity
-/// Returns identity hash and public key
-async fn create_identity(
+/// List an identity's currently valid device keys (for contacts)
+async fn get_devices(
+    path: web::Path<String>,
     identity_state: IdentityState,
 ) -> Result<HttpResponse> {
-    println!("🆔 [IDENTITY] Creating new identity...");
-    let identity = IdentityManager::new();
-    let identity_hash = identity.get_identity_hash().to_string();
-    let public_key = identity.get_public_key();
+    let identities = identity_state.lock().unwrap();
+    let identity = identities
+        .get(&path.into_inner())
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Identity not registered"))?;
+    let active: Vec<String> = identity
+        .active_device_keys()
+        .iter()
+        .map(|key| hex::encode(key.as_bytes()))
+        .collect();
+    let devices: Vec<_> = identity
+        .devices
+        .iter()
+        .filter(|d| active.contains(&d.subkey))
+        .collect();
     
-    println!("   ✅ Identity created: {}", &identity_hash[..16]);
-    println!("   🔑 Public key: {} bytes", public_key.len());
+    Ok(HttpResponse::Ok().json(json!({
+        "identity_hash": identity.identity_hash,
+        "devices": devices,
+        "revocations": identity.device_revocations
+    })))
+}
+
+/// Publish a signed prekey and one-time prekeys for a registered identity
+async fn upload_prekeys(
+    req: web::Json<PrekeyUpload>,
+    identity_state: IdentityState,
+    prekey_state: PrekeyState,
+) -> Result<HttpResponse> {
+    let upload = req.into_inner();
+    let identity_hash = upload.identity_hash.clone();
+    println!("🗝️  [PREKEYS] Upload for {}", short(&identity_hash, 16));
     
-    // Store identity (in production, this would be client-side only)
-    identity_state.lock().unwrap().insert(identity_hash.clone(), identity);
+    let identity_key = identity_state
+        .lock()
+        .unwrap()
+        .get(&identity_hash)
+        .ok_or(AuthError::UnknownSender)?
+        .current_key()
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    if now_secs().abs_diff(upload.timestamp) > MAX_CLOCK_SKEW_SECS {
+        return Err(AuthError::StaleRequest.into());
+    }
+    let remaining = prekey_state
+        .lock()
+        .unwrap()
+        .upload(upload, &identity_key)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    println!("   ✅ {} one-time prekeys available", remaining);
     
     Ok(HttpResponse::Ok().json(json!({
         "identity_hash": identity_hash,
-        "public_key": hex::encode(public_key),
-        "message": "Save your identity hash and private key securely!"
+        "one_time_prekeys": remaining
     })))
 }
 
+/// Claim a prekey bundle to start a thread with an (offline) identity.
+/// Requires a session; each caller consumes at most one one-time prekey per identity.
+async fn claim_prekey_bundle(
+    req: HttpRequest,
+    path: web::Path<String>,
+    prekey_state: PrekeyState,
+    sessions: SessionState,
+) -> Result<HttpResponse> {
+    let claimer = authenticate(&req, &sessions)?;
+    let identity_hash = path.into_inner();
+    let bundle = prekey_state
+        .lock()
+        .unwrap()
+        .claim_bundle(&identity_hash, &claimer.identity_hash)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("No prekeys published for this identity"))?;
+    
+    if bundle.one_time_prekey.is_none() {
+        println!("⚠️  [PREKEYS] {} is out of one-time prekeys", short(&identity_hash, 16));
+    }
+    Ok(HttpResponse::Ok().json(bundle))
+}
+
 /// Get CSTATE root and thread roots for an identity
 async fn get_cstate(identity_hash: web::Path<String>, state: AppState) -> Result<HttpResponse> {
     let store = state.lock().unwrap();
     let hash = identity_hash.into_inner();
-    let root = store.get_cstate_root(&hash);
-    let thread_roots = store.get_thread_roots(&hash);
+    let snapshot = store.get_cstate_snapshot(&hash);
     
     Ok(HttpResponse::Ok().json(json!({
-        "cstate_root": root,
-        "thread_count": thread_roots.len(),
-        "thread_roots": thread_roots
+        "cstate_root": snapshot.cstate_root,
+        "thread_count": snapshot.thread_roots.len(),
+        "thread_roots": snapshot.thread_roots,
+        "vaa_nonce": snapshot.vaa_nonce
     })))
 }
 
 /// Get all threads (conversations) for an identity
 /// Returns list of threads with last message info
 async fn get_threads_for_identity(
+    req: HttpRequest,
     path: web::Path<String>,
     state: AppState,
+    group_state: GroupsState,
+    sessions: SessionState,
 ) -> Result<HttpResponse> {
-    let store = state.lock().unwrap();
+    let session = authenticate(&req, &sessions)?;
     let identity_hash = path.into_inner();
+    if session.identity_hash != identity_hash {
+        return Err(AuthError::NotParticipant.into());
+    }
+    // Lock order: groups before messages
+    let groups = group_state.lock().unwrap();
+    let store = state.lock().unwrap();
     
-    // Get all threads that contain messages from or to this identity
-    let mut threads = Vec::new();
-    let thread_ids = store.get_all_thread_ids();
-    
-    for thread_id in thread_ids {
-        // Thread ID format is "hash1:hash2" (sorted)
-        let parts: Vec<&str> = thread_id.split(':').collect();
-        if parts.len() == 2 {
-            if parts[0] == identity_hash || parts[1] == identity_hash {
-                if let Some(messages) = store.get_messages(&thread_id) {
-                    if !messages.is_empty() {
-                        // Get the other participant's hash
-                        let other_hash = if parts[0] == identity_hash {
-                            parts[1].to_string()
-                        } else {
-                            parts[0].to_string()
-                        };
-                        
-                        // Get last message
-                        let last_msg = messages.last().unwrap();
-                        threads.push(json!({
-                            "thread_id": thread_id,
-                            "other_identity_hash": other_hash,
-                            "last_message_time": last_msg.timestamp,
-                            "message_count": messages.len()
-                        }));
-                    }
+    let threads: Vec<_> = store
+        .threads_for(&identity_hash)
+        .into_iter()
+        .map(|thread| {
+            let messages = store.get_messages(&thread.id);
+            let mut entry = json!({
+                "thread_id": thread.id,
+                "kind": thread.kind,
+                "participants": thread.participants,
+                "created_at": thread.created_at,
+                "metadata": thread.metadata,
+                "last_message_time": messages.and_then(|m| m.last()).map(|m| m.timestamp),
+                "message_count": messages.map(|m| m.len()).unwrap_or(0)
+            });
+            match thread.kind {
+                ThreadKind::Direct => {
+                    entry["other_identity_hash"] = json!(thread.participants.iter().find(|p| **p != identity_hash));
+                }
+                ThreadKind::Group => {
+                    entry["epoch"] = json!(groups.get(&thread.id).map(|g| g.state.epoch));
                 }
             }
-        }
-    }
+            entry
+        })
+        .collect();
     
     Ok(HttpResponse::Ok().json(threads))
 }
@@ -282,19 +984,43 @@ async fn health_check() -> Result<HttpResponse> {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let store = web::Data::new(Mutex::new(MessageStore::new()));
-    let identities = web::Data::new(Mutex::new(std::collections::HashMap::<String, IdentityManager>::new()));
+    let identities = web::Data::new(Mutex::new(std::collections::HashMap::<String, Identity>::new()));
+    let prekeys = web::Data::new(Mutex::new(PrekeyStore::new()));
+    let handles = web::Data::new(Mutex::new(HandleRegistry::new()));
+    let challenges = web::Data::new(Mutex::new(ChallengeStore::new()));
+    let sessions = web::Data::new(SessionIssuer::new());
+    let groups = web::Data::new(Mutex::new(GroupRegistry::new()));
     
     println!("🚀 ZeroTrace - End-to-End Encrypted Messaging DApp");
     println!("   Built on Psy Protocol with ZK Proofs");
     println!("   Server starting on http://127.0.0.1:8080");
     println!("\n🌐 Frontend: http://127.0.0.1:8080");
     println!("\nAPI Endpoints:");
-    println!("  POST /identity/create - Create new identity");
-    println!("  POST /send - Send encrypted message with ZK proof");
-    println!("  GET  /messages/{{thread_id}} - Get encrypted messages");
-    println!("  GET  /read/{{thread_id}} - Read decrypted messages");
+    println!("  POST /identity/register - Register client-held identity");
+    println!("  POST /auth/challenge - Get a one-time login challenge");
+    println!("  POST /auth/login - Sign the challenge, get a session token");
+    println!("  GET  /identity/{{identity_hash}} - Get registered public key and rotation chain");
+    println!("  POST /identity/{{identity_hash}}/rotate - Record a signed key rotation");
+    println!("  GET  /identity/{{identity_hash}}/devices - List active device keys");
+    println!("  POST /identity/{{identity_hash}}/devices - Add a root-endorsed device key");
+    println!("  POST /identity/{{identity_hash}}/devices/revoke - Revoke a device key");
+    println!("  GET  /identity/{{identity_hash}}/attestations - List attestations");
+    println!("  POST /identity/{{identity_hash}}/attestations - Attach an attestation from a registered issuer (subject's bearer token)");
+    println!("  GET  /identity/{{identity_hash}}/revocations - Get issuer's attestation revocation list");
+    println!("  POST /identity/{{identity_hash}}/revocations - Revoke an attestation issued by the identity");
+    println!("  POST /handles - Claim a handle (@name)");
+    println!("  GET  /handles/{{handle}} - Resolve a handle to identity hash + public key");
+    println!("  POST /handles/{{handle}}/transfer - Transfer a handle (signed by the owner)");
+    println!("  POST /groups - Create a group thread (signed event + sealed group key)");
+    println!("  GET  /groups/{{group_id}} - Get group members and event log (members only)");
+    println!("  POST /groups/{{group_id}}/events - Add, remove or rekey (new epoch)");
+    println!("  GET  /groups/{{group_id}}/keys - Get epoch keys sealed to the logged-in member");
+    println!("  POST /prekeys - Publish signed prekey bundle");
+    println!("  POST /prekeys/{{identity_hash}}/claim - Claim a prekey bundle (bearer token)");
+    println!("  POST /send - Submit client-encrypted message with ZK proof");
+    println!("  GET  /messages/{{thread_id}} - Get encrypted messages (participants only)");
     println!("  GET  /cstate/{{identity_hash}} - Get CSTATE root");
-    println!("  GET  /threads/{{identity_hash}} - Get all threads for identity");
+    println!("  GET  /threads/{{identity_hash}} - Get all threads for the logged-in identity");
     println!("  GET  /health - Health check endpoint");
     
     HttpServer::new(move || {
@@ -309,10 +1035,34 @@ async fn main() -> std::io::Result<()> {
             .wrap(Logger::default())
             .app_data(store.clone())
             .app_data(identities.clone())
-            .route("/identity/create", web::post().to(create_identity))
+            .app_data(prekeys.clone())
+            .app_data(handles.clone())
+            .app_data(challenges.clone())
+            .app_data(sessions.clone())
+            .app_data(groups.clone())
+            .route("/identity/register", web::post().to(register_identity))
+            .route("/auth/challenge", web::post().to(issue_challenge))
+            .route("/auth/login", web::post().to(login))
+            .route("/identity/{identity_hash}", web::get().to(get_identity))
+            .route("/identity/{identity_hash}/rotate", web::post().to(rotate_identity_key))
+            .route("/identity/{identity_hash}/devices", web::get().to(get_devices))
+            .route("/identity/{identity_hash}/devices", web::post().to(add_device))
+            .route("/identity/{identity_hash}/devices/revoke", web::post().to(revoke_device))
+            .route("/identity/{identity_hash}/attestations", web::get().to(get_attestations))
+            .route("/identity/{identity_hash}/attestations", web::post().to(add_attestation))
+            .route("/identity/{identity_hash}/revocations", web::get().to(get_revocations))
+            .route("/identity/{identity_hash}/revocations", web::post().to(revoke_attestation))
+            .route("/handles", web::post().to(register_handle))
+            .route("/handles/{handle}", web::get().to(resolve_handle))
+            .route("/handles/{handle}/transfer", web::post().to(transfer_handle))
+            .route("/groups", web::post().to(create_group))
+            .route("/groups/{group_id}", web::get().to(get_group))
+            .route("/groups/{group_id}/events", web::post().to(post_group_event))
+            .route("/groups/{group_id}/keys", web::get().to(get_group_keys))
+            .route("/prekeys", web::post().to(upload_prekeys))
+            .route("/prekeys/{identity_hash}/claim", web::post().to(claim_prekey_bundle))
             .route("/send", web::post().to(send_message))
             .route("/messages/{thread_id}", web::get().to(get_messages))
-            .route("/read/{thread_id}", web::get().to(decrypt_and_read))
             .route("/cstate/{identity_hash}", web::get().to(get_cstate))
             .route("/threads/{identity_hash}", web::get().to(get_threads_for_identity))
             .route("/health", web::get().to(health_check))
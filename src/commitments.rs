@@ -5,6 +5,8 @@ use sha2::{Sha256, Digest};
 use sha3::Keccak256;
 use serde::{Deserialize, Serialize};
 
+use crate::now_secs;
+
 /// Compute message commitment (simulates Poseidon hash)
 /// In production, this would use actual Poseidon2 from Psy repos
 pub fn compute_message_commitment(
@@ -24,7 +26,7 @@ pub fn compute_message_commitment(
     
     // Second round (simulates Poseidon permutation)
     let mut hasher2 = Keccak256::new();
-    hasher2.update(&first);
+    hasher2.update(first);
     hasher2.update(b"poseidon2_simulation");
     hex::encode(hasher2.finalize())
 }
@@ -32,29 +34,80 @@ pub fn compute_message_commitment(
 /// Compute CSTATE root (Merkle root of user's contract state)
 /// In production, this would be a real Merkle tree with Poseidon
 pub fn compute_cstate_root(thread_roots: &[String]) -> String {
-    if thread_roots.is_empty() {
+    merkle_root(thread_roots)
+}
+
+/// One level of a Merkle inclusion proof
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleStep {
+    pub sibling: String,           // hex node hashed with the running value
+    pub sibling_is_left: bool,
+}
+
+fn hash_pair(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [left, right] => hash_pair(left, right),
+            [odd] => odd.clone(),      // Odd node is promoted unchanged
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Binary Merkle root over hex leaves (all zeros when empty)
+pub fn merkle_root(leaves: &[String]) -> String {
+    if leaves.is_empty() {
         return "0".repeat(64);
     }
     
-    // Simple Merkle root simulation (binary tree)
-    let mut current = thread_roots.to_vec();
+    let mut current = leaves.to_vec();
+    while current.len() > 1 {
+        current = next_level(&current);
+    }
+    current[0].clone()
+}
+
+/// Inclusion proof for `leaves[index]` against `merkle_root(leaves)`
+pub fn merkle_proof(leaves: &[String], index: usize) -> Option<Vec<MerkleStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
     
+    let mut proof = Vec::new();
+    let mut current = leaves.to_vec();
+    let mut index = index;
     while current.len() > 1 {
-        let mut next = Vec::new();
-        for chunk in current.chunks(2) {
-            if chunk.len() == 2 {
-                let mut hasher = Sha256::new();
-                hasher.update(chunk[0].as_bytes());
-                hasher.update(chunk[1].as_bytes());
-                next.push(hex::encode(hasher.finalize()));
-            } else {
-                next.push(chunk[0].clone());
-            }
+        let sibling = index ^ 1;
+        if sibling < current.len() {
+            proof.push(MerkleStep {
+                sibling: current[sibling].clone(),
+                sibling_is_left: sibling < index,
+            });
         }
-        current = next;
+        current = next_level(&current);
+        index /= 2;
     }
-    
-    current[0].clone()
+    Some(proof)
+}
+
+/// Recompute the root from a leaf and its proof
+pub fn verify_merkle_proof(leaf: &str, proof: &[MerkleStep], root: &str) -> bool {
+    let computed = proof.iter().fold(leaf.to_string(), |node, step| {
+        if step.sibling_is_left {
+            hash_pair(&step.sibling, &node)
+        } else {
+            hash_pair(&node, &step.sibling)
+        }
+    });
+    computed == root
 }
 
 /// Compute plaintext hash (for commitment without revealing content)
@@ -82,10 +135,7 @@ impl StateCommitment {
             cstate_root,
             thread_id,
             message_commitment,
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            timestamp: now_secs(),
         }
     }
 }
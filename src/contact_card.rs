@@ -0,0 +1,288 @@
+// Signed contact cards for sharing identities
+// Self-signed by the identity's current key; binds identity_hash to that key
+// (directly or through its rotation chain). Compact binary and URL/QR text forms
+
+use base64::{Engine as _, engine::general_purpose};
+use ed25519_dalek::{PublicKey, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::handles::{normalize_handle, HandleClaim};
+use crate::identity::{parse_public_key, parse_signature, IdentityManager};
+use crate::rotation::{verify_rotation_chain, RotationCertificate};
+use crate::now_secs;
+
+/// Binary format version
+pub const CONTACT_CARD_VERSION: u8 = 1;
+
+/// Leading bytes of the binary encoding
+const CARD_MAGIC: &[u8; 3] = b"ZTC";
+
+/// Prefix of the text encoding (followed by base64url of the binary form)
+pub const CONTACT_CARD_URL_PREFIX: &str = "zerotrace://contact/";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactCard {
+    pub identity_hash: String,
+    pub public_key: String,        // hex current ED25519 public key
+    pub handle: Option<String>,    // canonical handle, without '@'; self-asserted until `verify_handle`
+    pub prekeys: Option<String>,   // where to claim a prekey bundle, e.g. a `/prekeys/{hash}/claim` URL
+    pub issued_at: u64,
+    pub expires_at: Option<u64>,
+    pub rotations: Vec<RotationCertificate>, // Chain from the original key, if rotated
+    pub signature: String,         // hex signature by `public_key`
+}
+
+impl ContactCard {
+    /// Bytes signed by the card's key
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_contact_card_v1:{}:{}:{}:{}:{}:{}",
+            self.identity_hash,
+            self.public_key,
+            self.handle.as_deref().unwrap_or_default(),
+            self.prekeys.as_deref().unwrap_or_default(),
+            self.issued_at,
+            self.expires_at.map(|t| t.to_string()).unwrap_or_default()
+        )
+    }
+
+    /// Check the signature, the identity_hash-to-key binding and expiry.
+    /// Returns the card's public key.
+    pub fn verify(&self) -> anyhow::Result<PublicKey> {
+        self.verify_at(now_secs())
+    }
+
+    /// Same as `verify`, at an explicit time (seconds since the epoch)
+    pub fn verify_at(&self, now: u64) -> anyhow::Result<PublicKey> {
+        let key = parse_public_key(&self.public_key)?;
+        key.verify(self.signing_payload().as_bytes(), &parse_signature(&self.signature)?)
+            .map_err(|_| anyhow::anyhow!("Contact card signature is invalid"))?;
+
+        let bound = match self.rotations.first() {
+            None => IdentityManager::compute_identity_hash(key.as_bytes()) == self.identity_hash,
+            Some(first) => {
+                let original = parse_public_key(&first.previous_key)?;
+                verify_rotation_chain(&self.identity_hash, &original, &self.rotations)? == key
+            }
+        };
+        if !bound {
+            return Err(anyhow::anyhow!("Contact card key does not belong to its identity hash"));
+        }
+        if let Some(handle) = &self.handle {
+            if normalize_handle(handle)? != *handle {
+                return Err(anyhow::anyhow!("Contact card handle is not in canonical form"));
+            }
+        }
+        if self.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            return Err(anyhow::anyhow!("Contact card has expired"));
+        }
+        Ok(key)
+    }
+
+    /// Check the card's handle against the registry's binding (`GET /handles/{handle}`).
+    /// `verify` only proves the owner chose to put the handle on the card; a handle
+    /// should not be displayed as the contact's until this succeeds.
+    pub fn verify_handle(&self, claim: &HandleClaim) -> anyhow::Result<()> {
+        let handle = self
+            .handle
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Contact card has no handle"))?;
+        if claim.handle != *handle || claim.identity_hash() != self.identity_hash {
+            return Err(anyhow::anyhow!("Handle is bound to a different identity"));
+        }
+
+        // The claim may have been signed by any key in the card's rotation chain
+        let current = self.verify()?;
+        let mut keys = vec![current];
+        for rotation in &self.rotations {
+            keys.push(parse_public_key(&rotation.previous_key)?);
+        }
+        if keys.iter().any(|key| claim.verify(key).is_ok()) {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Handle claim is not signed by the card's identity"))
+        }
+    }
+
+    /// Compact binary form:
+    /// `ZTC | version | hash(32) | key(32) | issued_at | expires_at (0 = none) |
+    ///  handle (u8 len) | prekeys (u8 len) | rotations (u8 count, 144 bytes each) | signature(64)`
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(256);
+        out.extend_from_slice(CARD_MAGIC);
+        out.push(CONTACT_CARD_VERSION);
+        out.extend_from_slice(&hex_array::<32>(&self.identity_hash)?);
+        out.extend_from_slice(&hex_array::<32>(&self.public_key)?);
+        out.extend_from_slice(&self.issued_at.to_be_bytes());
+        out.extend_from_slice(&self.expires_at.unwrap_or(0).to_be_bytes());
+        for field in [&self.handle, &self.prekeys] {
+            let bytes = field.as_deref().unwrap_or_default().as_bytes();
+            out.push(u8::try_from(bytes.len()).map_err(|_| anyhow::anyhow!("Contact card field too long"))?);
+            out.extend_from_slice(bytes);
+        }
+        out.push(u8::try_from(self.rotations.len()).map_err(|_| anyhow::anyhow!("Too many rotations"))?);
+        for rotation in &self.rotations {
+            out.extend_from_slice(&rotation.sequence.to_be_bytes());
+            out.extend_from_slice(&hex_array::<32>(&rotation.previous_key)?);
+            out.extend_from_slice(&hex_array::<32>(&rotation.new_key)?);
+            out.extend_from_slice(&rotation.timestamp.to_be_bytes());
+            out.extend_from_slice(&hex_array::<64>(&rotation.signature)?);
+        }
+        out.extend_from_slice(&hex_array::<64>(&self.signature)?);
+        Ok(out)
+    }
+
+    /// Parse the binary form. Does not verify; call `verify` before trusting it.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader(bytes);
+        if reader.take(3)? != CARD_MAGIC {
+            return Err(anyhow::anyhow!("Not a contact card"));
+        }
+        if reader.take(1)?[0] != CONTACT_CARD_VERSION {
+            return Err(anyhow::anyhow!("Unsupported contact card version"));
+        }
+        let identity_hash = hex::encode(reader.take(32)?);
+        let public_key = hex::encode(reader.take(32)?);
+        let issued_at = reader.u64()?;
+        let expires_at = Some(reader.u64()?).filter(|t| *t != 0);
+        let handle = reader.short_string()?;
+        let prekeys = reader.short_string()?;
+        let rotations = (0..reader.take(1)?[0])
+            .map(|_| {
+                Ok(RotationCertificate {
+                    identity_hash: identity_hash.clone(),
+                    sequence: reader.u64()?,
+                    previous_key: hex::encode(reader.take(32)?),
+                    new_key: hex::encode(reader.take(32)?),
+                    timestamp: reader.u64()?,
+                    signature: hex::encode(reader.take(64)?),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let signature = hex::encode(reader.take(64)?);
+        if !reader.0.is_empty() {
+            return Err(anyhow::anyhow!("Trailing bytes after contact card"));
+        }
+        Ok(Self { identity_hash, public_key, handle, prekeys, issued_at, expires_at, rotations, signature })
+    }
+
+    /// URL / QR text form: `zerotrace://contact/{base64url(binary)}`
+    pub fn to_url(&self) -> anyhow::Result<String> {
+        Ok(format!("{}{}", CONTACT_CARD_URL_PREFIX, general_purpose::URL_SAFE_NO_PAD.encode(self.to_bytes()?)))
+    }
+
+    pub fn from_url(url: &str) -> anyhow::Result<Self> {
+        let encoded = url
+            .trim()
+            .strip_prefix(CONTACT_CARD_URL_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("Not a contact card link"))?;
+        Self::from_bytes(&general_purpose::URL_SAFE_NO_PAD.decode(encoded)?)
+    }
+}
+
+fn hex_array<const N: usize>(value: &str) -> anyhow::Result<[u8; N]> {
+    hex::decode(value)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Expected {} hex-encoded bytes", N))
+}
+
+/// Cursor over the binary card encoding
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(anyhow::anyhow!("Contact card is truncated"));
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().expect("8 bytes")))
+    }
+
+    fn short_string(&mut self) -> anyhow::Result<Option<String>> {
+        let len = self.take(1)?[0] as usize;
+        let value = String::from_utf8(self.take(len)?.to_vec())?;
+        Ok(Some(value).filter(|v| !v.is_empty()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_round_trips_through_url() {
+        let identity = IdentityManager::new();
+        let card = identity.create_contact_card(Some("@Alice"), None, None).unwrap();
+        let decoded = ContactCard::from_url(&card.to_url().unwrap()).unwrap();
+        assert_eq!(decoded, card);
+        assert_eq!(decoded.verify().unwrap().as_bytes()[..], identity.get_public_key()[..]);
+    }
+
+    #[test]
+    fn tampered_card_is_rejected() {
+        let identity = IdentityManager::new();
+        let mut card = identity.create_contact_card(Some("alice"), None, None).unwrap();
+        card.handle = Some("mallory".to_string());
+        assert!(card.verify().is_err());
+    }
+
+    #[test]
+    fn handle_is_checked_against_the_registry_claim() {
+        let (alice, mallory) = (IdentityManager::new(), IdentityManager::new());
+        let card = alice.create_contact_card(Some("alice"), None, None).unwrap();
+        card.verify_handle(&alice.claim_handle("alice").unwrap()).unwrap();
+
+        // Mallory's card asserts a handle that is registered to Alice
+        let forged = mallory.create_contact_card(Some("alice"), None, None).unwrap();
+        forged.verify().unwrap();
+        assert!(forged.verify_handle(&alice.claim_handle("alice").unwrap()).is_err());
+        assert!(forged.verify_handle(&mallory.claim_handle("bob").unwrap()).is_err());
+    }
+
+    #[test]
+    fn stale_cards_cannot_roll_back_a_contact() {
+        let (mut alice, mut bob) = (IdentityManager::new(), IdentityManager::new());
+        let original = alice.create_contact_card(None, None, None).unwrap();
+        alice.rotate_key();
+        let first_rotation = alice.create_contact_card(None, None, None).unwrap();
+        alice.rotate_key();
+        let current = alice.create_contact_card(None, None, None).unwrap();
+        let hash = alice.get_identity_hash();
+
+        // First import already records the card's rotation
+        assert!(bob.import_contact_card(&current).unwrap());
+        assert_eq!(bob.get_contact_record(hash).unwrap().rotation_sequence, 2);
+        for stale in [&first_rotation, &original] {
+            assert!(bob.import_contact_card(stale).is_err());
+            assert_eq!(bob.get_contact(hash), Some(&alice.public_key()));
+        }
+        assert!(!bob.import_contact_card(&current).unwrap());
+
+        // Following the chain forward updates silently
+        let mut carol = IdentityManager::new();
+        carol.import_contact_card(&first_rotation).unwrap();
+        assert!(carol.import_contact_card(&current).unwrap());
+        assert_eq!(carol.get_contact(hash), Some(&alice.public_key()));
+    }
+
+    #[test]
+    fn broken_chains_are_rejected_and_flagged() {
+        let mut alice = IdentityManager::new();
+        let mut bob = IdentityManager::new();
+        let hash = alice.get_identity_hash().to_string();
+        let impostor = IdentityManager::new().public_key();
+        bob.add_contact(hash.clone(), impostor);
+
+        alice.rotate_key();
+        let card = alice.create_contact_card(None, None, None).unwrap();
+        assert!(bob.import_contact_card(&card).is_err());
+        let contact = bob.get_contact_record(&hash).unwrap();
+        assert!(contact.chain_broken);
+        assert_eq!(contact.public_key, impostor);
+    }
+}
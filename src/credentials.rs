@@ -0,0 +1,275 @@
+// Selective-disclosure credentials
+// All of a subject's claims sit under one Merkle root (commitments.rs) signed once by
+// the issuer; the holder proves any subset of claims without revealing the rest
+
+use ed25519_dalek::{PublicKey, Verifier};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::attestations::{commit_claim_value, AttestationPolicy, RevocationList};
+use crate::now_secs;
+use crate::commitments::{merkle_proof, merkle_root, verify_merkle_proof, MerkleStep};
+use crate::identity::{parse_signature, IdentitySigner};
+
+/// Issuer-signed Merkle root over salted claim commitments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credential {
+    pub issuer: String,            // Identity hash of issuer
+    pub subject: String,           // Identity hash the claims are about
+    pub claims_root: String,       // Merkle root of `CredentialClaim::leaf()` values
+    pub claim_count: usize,
+    pub timestamp: u64,
+    pub expires_at: Option<u64>,   // None = no expiry
+    pub signature: String,         // hex signature by the issuer
+}
+
+impl Credential {
+    /// Bytes signed by the issuer
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_credential_v1:{}:{}:{}:{}:{}:{}",
+            self.issuer,
+            self.subject,
+            self.claims_root,
+            self.claim_count,
+            self.timestamp,
+            self.expires_at.map(|t| t.to_string()).unwrap_or_default()
+        )
+    }
+
+    pub fn verify(&self, issuer_key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| issuer_key.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Stable id, used by issuers to revoke the credential
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.signing_payload().as_bytes()))
+    }
+
+    /// Whether the issuer's revocation list withdraws this credential
+    pub fn is_revoked(&self, revocations: &RevocationList) -> bool {
+        revocations.issuer == self.issuer && revocations.revocations.iter().any(|r| r.attestation_id == self.id())
+    }
+}
+
+/// One claim in a credential with its salt (secret, kept by the holder)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialClaim {
+    pub claim: String,
+    pub value: String,
+    pub salt: String,              // hex, 32 bytes
+}
+
+impl CredentialClaim {
+    /// Merkle leaf: the salted claim commitment
+    pub fn leaf(&self) -> anyhow::Result<String> {
+        let salt: [u8; 32] = hex::decode(&self.salt)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Claim salt must be 32 bytes"))?;
+        Ok(commit_claim_value(&self.claim, &self.value, &salt))
+    }
+}
+
+/// A credential together with every claim opening, as held by the subject
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldCredential {
+    pub credential: Credential,
+    pub claims: Vec<CredentialClaim>,
+}
+
+impl HeldCredential {
+    /// Check the claims hash to the signed root
+    pub fn check_claims(&self) -> anyhow::Result<()> {
+        if self.claims.len() != self.credential.claim_count || merkle_root(&self.leaves()?) != self.credential.claims_root {
+            return Err(anyhow::anyhow!("Claims do not match the credential root"));
+        }
+        Ok(())
+    }
+
+    /// Disclose the named claims with inclusion proofs; all others stay hidden.
+    /// The holder signs the verifier's `challenge`, so the presentation cannot be
+    /// replayed to another verifier or reused later.
+    pub fn present<S: IdentitySigner>(&self, claims: &[&str], challenge: &str, holder: &S) -> anyhow::Result<Presentation> {
+        if holder.identity_hash() != self.credential.subject {
+            return Err(anyhow::anyhow!("Only the subject can present a credential"));
+        }
+        let leaves = self.leaves()?;
+        let disclosed = claims
+            .iter()
+            .map(|name| {
+                let index = self
+                    .claims
+                    .iter()
+                    .position(|c| c.claim == *name)
+                    .ok_or_else(|| anyhow::anyhow!("Credential has no claim \"{}\"", name))?;
+                Ok(DisclosedClaim {
+                    claim: self.claims[index].clone(),
+                    proof: merkle_proof(&leaves, index).expect("index is in range"),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut presentation = Presentation {
+            credential: self.credential.clone(),
+            disclosed,
+            challenge: challenge.to_string(),
+            holder_signature: String::new(),
+        };
+        presentation.holder_signature = hex::encode(holder.sign(presentation.signing_payload()?.as_bytes()).to_bytes());
+        Ok(presentation)
+    }
+
+    fn leaves(&self) -> anyhow::Result<Vec<String>> {
+        self.claims.iter().map(|c| c.leaf()).collect()
+    }
+}
+
+/// A claim opening plus its Merkle inclusion proof
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisclosedClaim {
+    pub claim: CredentialClaim,
+    pub proof: Vec<MerkleStep>,
+}
+
+/// Fresh random challenge a verifier hands the holder before a presentation
+pub fn new_presentation_challenge() -> String {
+    let mut challenge = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut challenge);
+    hex::encode(challenge)
+}
+
+/// What a holder shows a verifier: the signed credential, a subset of claims and
+/// the holder's signature over the verifier's challenge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Presentation {
+    pub credential: Credential,
+    pub disclosed: Vec<DisclosedClaim>,
+    pub challenge: String,         // Verifier-supplied, see `new_presentation_challenge`
+    pub holder_signature: String,  // hex signature by the subject's current key
+}
+
+impl Presentation {
+    /// Bytes signed by the holder: credential, challenge and the disclosed leaves
+    pub fn signing_payload(&self) -> anyhow::Result<String> {
+        let mut hasher = Sha256::new();
+        for disclosed in &self.disclosed {
+            hasher.update(disclosed.claim.leaf()?.as_bytes());
+        }
+        Ok(format!(
+            "zerotrace_presentation_v1:{}:{}:{}",
+            self.credential.id(),
+            self.challenge,
+            hex::encode(hasher.finalize())
+        ))
+    }
+
+    /// Check the issuer signature, holder binding to `challenge`, revocation,
+    /// freshness and every disclosed claim's proof. `holder_key` is the subject's
+    /// current key and `revocations` the issuer's revocation list.
+    /// Returns the disclosed (claim, value) pairs.
+    pub fn verify(
+        &self,
+        issuer_key: &PublicKey,
+        holder_key: &PublicKey,
+        challenge: &str,
+        revocations: &RevocationList,
+        policy: &AttestationPolicy,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        if !self.credential.verify(issuer_key) {
+            return Err(anyhow::anyhow!("Credential signature is invalid"));
+        }
+        if self.challenge != challenge {
+            return Err(anyhow::anyhow!("Presentation answers a different challenge"));
+        }
+        let holder_signature = parse_signature(&self.holder_signature)?;
+        holder_key
+            .verify(self.signing_payload()?.as_bytes(), &holder_signature)
+            .map_err(|_| anyhow::anyhow!("Presentation is not signed by the credential subject"))?;
+        if revocations.issuer != self.credential.issuer {
+            return Err(anyhow::anyhow!("Revocation list is for a different issuer"));
+        }
+        revocations.verify(issuer_key)?;
+        if self.credential.is_revoked(revocations) {
+            return Err(anyhow::anyhow!("Credential has been revoked"));
+        }
+        policy.check_freshness(self.credential.timestamp, self.credential.expires_at, now_secs())?;
+        self.disclosed
+            .iter()
+            .map(|d| {
+                if !verify_merkle_proof(&d.claim.leaf()?, &d.proof, &self.credential.claims_root) {
+                    return Err(anyhow::anyhow!("Claim \"{}\" is not in the credential", d.claim.claim));
+                }
+                Ok((d.claim.claim.clone(), d.claim.value.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    struct Setup {
+        issuer: IdentityManager,
+        holder: IdentityManager,
+        held: HeldCredential,
+    }
+
+    fn setup() -> Setup {
+        let issuer = IdentityManager::new();
+        let holder = IdentityManager::new();
+        let held = issuer
+            .issue_credential(holder.get_identity_hash(), &[("age_over", "18"), ("country", "NZ"), ("name", "Sam")], None)
+            .unwrap();
+        Setup { issuer, holder, held }
+    }
+
+    #[test]
+    fn discloses_only_the_chosen_claims() {
+        let Setup { issuer, holder, held } = setup();
+        let challenge = new_presentation_challenge();
+        let presentation = held.present(&["country"], &challenge, &holder).unwrap();
+        let revocations = RevocationList::new(issuer.get_identity_hash());
+        let disclosed = presentation
+            .verify(&issuer.public_key(), &holder.public_key(), &challenge, &revocations, &AttestationPolicy::default())
+            .unwrap();
+        assert_eq!(disclosed, vec![("country".to_string(), "NZ".to_string())]);
+    }
+
+    #[test]
+    fn presentations_are_bound_to_holder_and_challenge() {
+        let Setup { issuer, holder, held } = setup();
+        let revocations = RevocationList::new(issuer.get_identity_hash());
+        let policy = AttestationPolicy::default();
+        let challenge = new_presentation_challenge();
+        let presentation = held.present(&["age_over"], &challenge, &holder).unwrap();
+
+        // Replayed to a verifier with a different challenge
+        let other = new_presentation_challenge();
+        assert!(presentation.verify(&issuer.public_key(), &holder.public_key(), &other, &revocations, &policy).is_err());
+        // Replayed by someone else re-signing it
+        let thief = IdentityManager::new();
+        assert!(held.present(&["age_over"], &challenge, &thief).is_err());
+        assert!(presentation.verify(&issuer.public_key(), &thief.public_key(), &challenge, &revocations, &policy).is_err());
+        // A claim swapped in after signing
+        let mut tampered = presentation.clone();
+        tampered.disclosed[0].claim.value = "21".into();
+        assert!(tampered.verify(&issuer.public_key(), &holder.public_key(), &challenge, &revocations, &policy).is_err());
+    }
+
+    #[test]
+    fn revoked_credentials_fail() {
+        let Setup { issuer, holder, held } = setup();
+        let challenge = new_presentation_challenge();
+        let presentation = held.present(&["name"], &challenge, &holder).unwrap();
+
+        let mut revocations = RevocationList::new(issuer.get_identity_hash());
+        revocations.revocations.push(issuer.revoke_credential(&held.credential).unwrap());
+        assert!(presentation
+            .verify(&issuer.public_key(), &holder.public_key(), &challenge, &revocations, &AttestationPolicy::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,264 @@
+// Hierarchical key derivation (SLIP-10 for ED25519, hardened paths only)
+// Root identity derives per-device and per-thread subkeys, endorsed by signed certificates
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::identity::{parse_public_key, parse_signature};
+
+/// Index offset marking a hardened child (ED25519 only supports hardened derivation)
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// Path branch for per-device keys: m/1'/{device}'
+pub const DEVICE_BRANCH: u32 = 1;
+/// Path branch for per-thread keys: m/2'/{thread}'
+pub const THREAD_BRANCH: u32 = 2;
+/// Path branch for rotated identity keys: m/3'/{sequence}'
+pub const ROTATION_BRANCH: u32 = 3;
+
+/// Hardened derivation path such as `m/1'/0'`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Parse `m/a'/b'/...`. Every component must be hardened.
+    pub fn parse(path: &str) -> anyhow::Result<Self> {
+        let mut parts = path.split('/');
+        if parts.next() != Some("m") {
+            return Err(anyhow::anyhow!("Derivation path must start with 'm'"));
+        }
+        let indexes = parts
+            .map(|part| {
+                let index = part
+                    .strip_suffix('\'')
+                    .ok_or_else(|| anyhow::anyhow!("ED25519 only supports hardened paths ({}')", part))?
+                    .parse::<u32>()?;
+                check_index(index)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self(indexes))
+    }
+
+    /// `m/1'/{device_index}'`. Fails if `device_index` does not fit below the hardened bit.
+    pub fn for_device(device_index: u32) -> anyhow::Result<Self> {
+        Ok(Self(vec![DEVICE_BRANCH, check_index(device_index)?]))
+    }
+
+    /// `m/3'/{sequence}'`, the key a recovery-phrase identity rotates to at `sequence`
+    pub fn for_rotation(sequence: u64) -> anyhow::Result<Self> {
+        let index = u32::try_from(sequence).map_err(|_| anyhow::anyhow!("Rotation sequence {} out of range", sequence))?;
+        Ok(Self(vec![ROTATION_BRANCH, check_index(index)?]))
+    }
+
+    /// `m/2'/{index}'` where index is the first 31 bits of SHA256(thread_id).
+    /// Distinct threads can share an index (collisions become likely after tens
+    /// of thousands of threads), and then the same subkey. Thread subkeys only
+    /// sign; they are never used to encrypt, and thread message keys come from
+    /// HKDF over the full thread_id (`keyexchange::derive_thread_key`).
+    pub fn for_thread(thread_id: &str) -> Self {
+        let hash = Sha256::digest(thread_id.as_bytes());
+        let index = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]) & !HARDENED;
+        Self(vec![THREAD_BRANCH, index])
+    }
+
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+/// Reject indexes that already carry the hardened bit instead of aliasing them
+fn check_index(index: u32) -> anyhow::Result<u32> {
+    if index >= HARDENED {
+        return Err(anyhow::anyhow!("Path index {} out of range", index));
+    }
+    Ok(index)
+}
+
+impl std::fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// SLIP-10 extended private key (secret + chain code)
+#[derive(Clone)]
+pub struct ExtendedKey {
+    secret: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl std::fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ExtendedKey(..)")
+    }
+}
+
+impl ExtendedKey {
+    /// SLIP-10 master key from a seed (e.g. a BIP39 seed)
+    pub fn from_seed(seed: &[u8]) -> Self {
+        Self::from_hmac(b"ed25519 seed", &[seed])
+    }
+
+    pub fn new(secret: [u8; 32], chain_code: [u8; 32]) -> Self {
+        Self { secret, chain_code }
+    }
+
+    /// Hardened child `index'`. `index` must be below `HARDENED`; `DerivationPath`
+    /// and `DerivedKey::derive_child` check this.
+    pub fn derive_child(&self, index: u32) -> Self {
+        let index = (index | HARDENED).to_be_bytes();
+        Self::from_hmac(&self.chain_code, &[&[0u8], &self.secret, &index])
+    }
+
+    pub fn derive_path(&self, path: &DerivationPath) -> Self {
+        path.indexes()
+            .iter()
+            .fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    pub fn secret_bytes(&self) -> &[u8; 32] {
+        &self.secret
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    pub fn keypair(&self) -> Keypair {
+        let secret = SecretKey::from_bytes(&self.secret).expect("32-byte secret");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = <Hmac<Sha512> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        for chunk in data {
+            mac.update(chunk);
+        }
+        let out = mac.finalize().into_bytes();
+        let mut secret = [0u8; 32];
+        let mut chain_code = [0u8; 32];
+        secret.copy_from_slice(&out[..32]);
+        chain_code.copy_from_slice(&out[32..]);
+        Self { secret, chain_code }
+    }
+}
+
+/// Child signing key held by a device; the root key can stay cold
+#[derive(Debug)]
+pub struct DerivedKey {
+    key: ExtendedKey,
+    keypair: Keypair,
+    path: DerivationPath,
+}
+
+impl DerivedKey {
+    pub(crate) fn new(key: ExtendedKey, path: DerivationPath) -> Self {
+        let keypair = key.keypair();
+        Self { key, keypair, path }
+    }
+
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair.public
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        self.keypair.sign(message)
+    }
+
+    /// Derive a further hardened child (e.g. a thread key under a device key)
+    pub fn derive_child(&self, index: u32) -> anyhow::Result<Self> {
+        let mut path = self.path.clone();
+        path.0.push(check_index(index)?);
+        Ok(Self::new(self.key.derive_child(index), path))
+    }
+}
+
+/// Root-signed statement that a subkey was derived at `path` for `purpose`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubkeyCertificate {
+    pub root_identity_hash: String,
+    pub subkey: String,            // hex ED25519 public key
+    pub path: String,              // e.g. "m/1'/0'"
+    pub purpose: String,           // "device", "thread", ...
+    pub issued_at: u64,
+    pub signature: String,         // hex signature by the root identity key
+}
+
+impl SubkeyCertificate {
+    /// Bytes signed by the root key
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_subkey_v1:{}:{}:{}:{}:{}",
+            self.root_identity_hash, self.subkey, self.path, self.purpose, self.issued_at
+        )
+    }
+
+    /// Verify against the root identity's current public key.
+    /// Callers resolve that key first (`Identity::current_key` follows rotations).
+    pub fn verify(&self, root_pubkey: &PublicKey) -> bool {
+        DerivationPath::parse(&self.path).is_ok()
+            && parse_public_key(&self.subkey).is_ok()
+            && parse_signature(&self.signature)
+                .map(|sig| root_pubkey.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+                .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_slip10_test_vector() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::from_seed(&seed);
+        assert_eq!(
+            hex::encode(master.secret_bytes()),
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code()),
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb"
+        );
+        let child = master.derive_path(&DerivationPath::parse("m/0'").unwrap());
+        assert_eq!(
+            hex::encode(child.secret_bytes()),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+        assert_eq!(
+            hex::encode(child.chain_code()),
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69"
+        );
+    }
+
+    #[test]
+    fn paths_round_trip_and_reject_unhardened() {
+        let path = DerivationPath::parse("m/1'/7'").unwrap();
+        assert_eq!(path.to_string(), "m/1'/7'");
+        assert_eq!(path, DerivationPath::for_device(7).unwrap());
+        assert!(DerivationPath::parse("m/1/7'").is_err());
+        assert!(DerivationPath::parse("m/2147483648'").is_err());
+    }
+
+    #[test]
+    fn rejects_indexes_with_the_hardened_bit() {
+        assert!(DerivationPath::for_device(HARDENED).is_err());
+        assert!(DerivationPath::for_device(HARDENED - 1).is_ok());
+
+        let root = ExtendedKey::from_seed(&[1u8; 32]);
+        let device = DerivedKey::new(root.clone(), DerivationPath::default());
+        assert!(device.derive_child(HARDENED | 3).is_err());
+        assert_eq!(device.derive_child(3).unwrap().path().to_string(), "m/3'");
+    }
+}
@@ -0,0 +1,175 @@
+// Multi-device identities
+// Device keys are endorsed by the root identity (SubkeyCertificate with purpose
+// "device") and can be revoked individually with a root-signed revocation
+
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::derivation::{DerivedKey, SubkeyCertificate};
+use crate::identity::{parse_signature, IdentitySigner};
+
+/// Certificate purpose marking a device key
+pub const DEVICE_PURPOSE: &str = "device";
+
+/// Root-signed withdrawal of a device key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceRevocation {
+    pub identity_hash: String,
+    pub device_key: String,        // hex ED25519 public key being revoked
+    pub revoked_at: u64,
+    pub signature: String,         // hex signature by the root identity key
+}
+
+impl DeviceRevocation {
+    /// Bytes signed by the root key
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_device_revoke_v1:{}:{}:{}",
+            self.identity_hash, self.device_key, self.revoked_at
+        )
+    }
+
+    pub fn verify(&self, root_pubkey: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| root_pubkey.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+/// A device acting for an identity with its own endorsed key
+#[derive(Debug)]
+pub struct DeviceIdentity {
+    identity_hash: String,
+    key: DerivedKey,
+    certificate: SubkeyCertificate,
+}
+
+impl DeviceIdentity {
+    /// Pair a device key with the root's certificate for it
+    pub fn new(key: DerivedKey, certificate: SubkeyCertificate) -> anyhow::Result<Self> {
+        if certificate.purpose != DEVICE_PURPOSE {
+            return Err(anyhow::anyhow!("Certificate is not for a device key"));
+        }
+        if certificate.subkey != hex::encode(key.public_key().as_bytes()) {
+            return Err(anyhow::anyhow!("Certificate does not match this device key"));
+        }
+        Ok(Self {
+            identity_hash: certificate.root_identity_hash.clone(),
+            key,
+            certificate,
+        })
+    }
+
+    pub fn certificate(&self) -> &SubkeyCertificate {
+        &self.certificate
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+}
+
+impl IdentitySigner for DeviceIdentity {
+    fn identity_hash(&self) -> &str {
+        &self.identity_hash
+    }
+
+    fn sign(&self, message: &[u8]) -> Signature {
+        self.key.sign(message)
+    }
+
+    fn device_key(&self) -> Option<PublicKey> {
+        Some(self.key.public_key())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+    use crate::sessions::LoginRequest;
+    use crate::{CStateSnapshot, SendRequest};
+
+    #[test]
+    fn add_device_checks_purpose_root_and_revocation() {
+        let root = IdentityManager::new();
+        let other = IdentityManager::new();
+        let mut identity = root.export();
+        let (key, certificate) = root.derive_device_key(0).unwrap();
+
+        assert!(identity.add_device(root.certify_subkey(&key, "thread")).is_err());
+        assert!(identity.add_device(other.certify_subkey(&key, DEVICE_PURPOSE)).is_err());
+        let mut forged = other.certify_subkey(&key, DEVICE_PURPOSE);
+        forged.root_identity_hash = root.get_identity_hash().to_string();
+        assert!(identity.add_device(forged).is_err());
+
+        identity.add_device(certificate.clone()).unwrap();
+        assert_eq!(identity.active_device_keys(), vec![key.public_key()]);
+
+        identity.revoke_device(root.revoke_device(&key.public_key())).unwrap();
+        assert!(identity.active_device_keys().is_empty());
+        assert!(identity.add_device(certificate).is_err());
+    }
+
+    #[test]
+    fn revoke_device_checks_signer_and_ignores_repeats() {
+        let root = IdentityManager::new();
+        let other = IdentityManager::new();
+        let mut identity = root.export();
+        let (key, certificate) = root.derive_device_key(0).unwrap();
+        let (unknown, _) = root.derive_device_key(1).unwrap();
+        identity.add_device(certificate).unwrap();
+
+        assert!(identity.revoke_device(root.revoke_device(&unknown.public_key())).is_err());
+        assert!(identity.revoke_device(other.revoke_device(&key.public_key())).is_err());
+        let mut forged = other.revoke_device(&key.public_key());
+        forged.identity_hash = root.get_identity_hash().to_string();
+        assert!(identity.revoke_device(forged).is_err());
+        assert_eq!(identity.active_device_keys().len(), 1);
+
+        identity.revoke_device(root.revoke_device(&key.public_key())).unwrap();
+        identity.revoke_device(root.revoke_device(&key.public_key())).unwrap();
+        assert_eq!(identity.device_revocations.len(), 1);
+    }
+
+    #[test]
+    fn root_rotation_retires_old_device_certificates() {
+        let mut root = IdentityManager::new();
+        let mut identity = root.export();
+        let (key, certificate) = root.derive_device_key(0).unwrap();
+        identity.add_device(certificate).unwrap();
+
+        identity.apply_rotation(root.rotate_key()).unwrap();
+        assert!(identity.active_device_keys().is_empty());
+
+        // Re-endorsed by the new root key, the device is active again
+        identity.add_device(root.certify_subkey(&key, DEVICE_PURPOSE)).unwrap();
+        assert_eq!(identity.active_device_keys(), vec![key.public_key()]);
+    }
+
+    #[test]
+    fn devices_sign_requests_with_their_own_key() {
+        let root = IdentityManager::new();
+        let (key, certificate) = root.derive_device_key(0).unwrap();
+        let (other_key, _) = root.derive_device_key(1).unwrap();
+        assert!(DeviceIdentity::new(other_key, certificate.clone()).is_err());
+        let (thread_key, _) = root.derive_device_key(2).unwrap();
+        let thread_certificate = root.certify_subkey(&thread_key, "thread");
+        assert!(DeviceIdentity::new(thread_key, thread_certificate).is_err());
+
+        let device = DeviceIdentity::new(key, certificate).unwrap();
+        let device_hex = hex::encode(device.public_key().as_bytes());
+        assert_eq!(device.identity_hash(), root.get_identity_hash());
+
+        let request = SendRequest::seal(&device, "bob", "alice:bob", &[7u8; 32], "hi", &CStateSnapshot::default()).unwrap();
+        assert_eq!(request.device_key.as_deref(), Some(device_hex.as_str()));
+        assert!(request.verify_signatures(&device.public_key()));
+        assert!(!request.verify_signatures(&root.public_key()));
+
+        let login = LoginRequest::sign(&device, "challenge");
+        assert_eq!(login.device_key.as_deref(), Some(device_hex.as_str()));
+        assert!(login.verify(&device.public_key()));
+        assert!(!login.verify(&root.public_key()));
+    }
+}
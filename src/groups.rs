@@ -0,0 +1,553 @@
+// Group threads with an explicit member list
+// Membership changes are signed, hash-chained events; every change starts a new
+// key epoch whose group key is sealed to each member under their pairwise key,
+// so removed members cannot read anything sent after they leave
+
+use std::collections::HashMap;
+
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use ed25519_dalek::{PublicKey, Verifier};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::identity::parse_signature;
+use crate::put_field;
+
+/// Thread ids of group threads start with this prefix ("group:{hex}")
+pub const GROUP_THREAD_PREFIX: &str = "group:";
+
+/// Whether a thread id names a group rather than a two-party thread
+pub fn is_group_thread(thread_id: &str) -> bool {
+    thread_id.starts_with(GROUP_THREAD_PREFIX)
+}
+
+/// Fresh random group thread id
+pub fn new_group_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", GROUP_THREAD_PREFIX, hex::encode(bytes))
+}
+
+/// Membership change recorded by a `MembershipEvent`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum MembershipAction {
+    Create { members: Vec<String> }, // Initial members, including the creator
+    Add { member: String },
+    Remove { member: String },       // Owner removing someone, or a member leaving
+    Rekey,                           // Fresh key, same members (e.g. after someone left)
+}
+
+impl MembershipAction {
+    /// Length-prefixed encoding, so member ids containing separators cannot be
+    /// split into several members or merged into one
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            MembershipAction::Create { members } => {
+                put_field(out, b"create");
+                out.extend_from_slice(&(members.len() as u32).to_be_bytes());
+                for member in members {
+                    put_field(out, member.as_bytes());
+                }
+            }
+            MembershipAction::Add { member } => {
+                put_field(out, b"add");
+                put_field(out, member.as_bytes());
+            }
+            MembershipAction::Remove { member } => {
+                put_field(out, b"remove");
+                put_field(out, member.as_bytes());
+            }
+            MembershipAction::Rekey => put_field(out, b"rekey"),
+        }
+    }
+}
+
+/// Signed membership change. Event `n` starts key epoch `n`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MembershipEvent {
+    pub group_id: String,
+    pub sequence: u64,             // 0 for Create, then one per change
+    pub previous: String,          // `id()` of the previous event, empty for Create
+    pub actor: String,             // Identity hash of the signer
+    pub action: MembershipAction,
+    pub timestamp: u64,
+    pub signature: String,         // hex signature by the actor's current key
+}
+
+impl MembershipEvent {
+    /// Bytes signed by the actor. Variable-length fields are length-prefixed.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut out = b"zerotrace_group_event_v2".to_vec();
+        put_field(&mut out, self.group_id.as_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        put_field(&mut out, self.previous.as_bytes());
+        put_field(&mut out, self.actor.as_bytes());
+        self.action.encode(&mut out);
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out
+    }
+
+    /// Hash the next event chains to
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.signing_payload()))
+    }
+
+    pub fn verify(&self, actor_key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| actor_key.verify(&self.signing_payload(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+/// Membership after replaying a group's events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupState {
+    pub group_id: String,
+    pub owner: String,             // Creator; the only member who may add or remove others
+    pub members: Vec<String>,      // Sorted identity hashes
+    pub epoch: u64,                // Sequence of the last event = current key epoch
+    pub head: String,              // `id()` of the last event
+}
+
+impl GroupState {
+    /// State created by a Create event (signature not checked)
+    pub fn genesis(event: &MembershipEvent) -> anyhow::Result<Self> {
+        let MembershipAction::Create { members } = &event.action else {
+            return Err(anyhow::anyhow!("A group must start with a Create event"));
+        };
+        if !is_group_thread(&event.group_id) || event.sequence != 0 || !event.previous.is_empty() {
+            return Err(anyhow::anyhow!("Malformed Create event"));
+        }
+        let mut members = members.clone();
+        members.sort();
+        members.dedup();
+        if !members.contains(&event.actor) || members.len() < 2 {
+            return Err(anyhow::anyhow!("A group needs its creator and at least one other member"));
+        }
+        Ok(Self {
+            group_id: event.group_id.clone(),
+            owner: event.actor.clone(),
+            members,
+            epoch: 0,
+            head: event.id(),
+        })
+    }
+
+    /// Apply the next event (signature not checked; see `GroupRegistry`)
+    pub fn apply(&mut self, event: &MembershipEvent) -> anyhow::Result<()> {
+        if event.group_id != self.group_id || event.sequence != self.epoch + 1 || event.previous != self.head {
+            return Err(anyhow::anyhow!("Event does not follow the current group state"));
+        }
+        match &event.action {
+            MembershipAction::Create { .. } => return Err(anyhow::anyhow!("Group already exists")),
+            MembershipAction::Add { member } => {
+                if event.actor != self.owner {
+                    return Err(anyhow::anyhow!("Only the group owner can add members"));
+                }
+                if self.is_member(member) {
+                    return Err(anyhow::anyhow!("Already a member"));
+                }
+                self.members.push(member.clone());
+                self.members.sort();
+            }
+            MembershipAction::Remove { member } => {
+                if event.actor != self.owner && event.actor != *member {
+                    return Err(anyhow::anyhow!("Only the owner can remove other members"));
+                }
+                if *member == self.owner {
+                    return Err(anyhow::anyhow!("The owner cannot leave the group"));
+                }
+                if !self.is_member(member) {
+                    return Err(anyhow::anyhow!("Not a member"));
+                }
+                self.members.retain(|m| m != member);
+            }
+            MembershipAction::Rekey => {}
+        }
+        self.epoch = event.sequence;
+        self.head = event.id();
+        Ok(())
+    }
+
+    pub fn is_member(&self, identity_hash: &str) -> bool {
+        self.members.iter().any(|m| m == identity_hash)
+    }
+}
+
+/// One epoch's group key, sealed from the actor to one member under their pairwise key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedGroupKey {
+    pub group_id: String,
+    pub epoch: u64,
+    pub sender: String,            // Actor of the event that started the epoch
+    pub recipient: String,
+    pub nonce: String,             // base64 XChaCha20 nonce
+    pub ciphertext: String,        // base64 encrypted 32-byte group key
+}
+
+impl SealedGroupKey {
+    /// Encrypt `group_key` under the sender/recipient pairwise key for this group
+    pub fn seal(
+        pairwise_key: &[u8; 32],
+        group_id: &str,
+        epoch: u64,
+        sender: &str,
+        recipient: &str,
+        group_key: &[u8; 32],
+    ) -> anyhow::Result<Self> {
+        let mut sealed = Self {
+            group_id: group_id.to_string(),
+            epoch,
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = sealed.header_aad();
+        let ciphertext = XChaCha20Poly1305::new(pairwise_key.into())
+            .encrypt(&nonce, Payload { msg: group_key, aad: aad.as_bytes() })
+            .map_err(|e| anyhow::anyhow!("Group key encryption failed: {}", e))?;
+        sealed.nonce = general_purpose::STANDARD.encode(nonce);
+        sealed.ciphertext = general_purpose::STANDARD.encode(ciphertext);
+        Ok(sealed)
+    }
+
+    pub fn open(&self, pairwise_key: &[u8; 32]) -> anyhow::Result<[u8; 32]> {
+        let nonce = general_purpose::STANDARD.decode(&self.nonce)?;
+        if nonce.len() != 24 {
+            return Err(anyhow::anyhow!("Group key nonce must be 24 bytes"));
+        }
+        let aad = self.header_aad();
+        XChaCha20Poly1305::new(pairwise_key.into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload { msg: &general_purpose::STANDARD.decode(&self.ciphertext)?, aad: aad.as_bytes() },
+            )
+            .map_err(|_| anyhow::anyhow!("Group key is not sealed to this key or was tampered with"))?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Group key must be 32 bytes"))
+    }
+
+    /// Associated data binding the readable header to the ciphertext
+    fn header_aad(&self) -> String {
+        format!(
+            "zerotrace_group_key_v1:{}:{}:{}:{}",
+            self.group_id, self.epoch, self.sender, self.recipient
+        )
+    }
+}
+
+/// A membership event plus the new epoch's key sealed to every resulting member
+/// (body of `POST /groups` and `POST /groups/{group_id}/events`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupUpdate {
+    pub event: MembershipEvent,
+    pub keys: Vec<SealedGroupKey>,
+}
+
+impl GroupUpdate {
+    /// Whether the actor is leaving. A leaver must not choose the next key, so its
+    /// event carries none and the epoch stays unkeyed until a member sends a Rekey.
+    pub fn is_leave(&self) -> bool {
+        matches!(&self.event.action, MembershipAction::Remove { member } if *member == self.event.actor)
+    }
+
+    /// Check `keys` carry this event's epoch to exactly the members of `state`
+    fn check_keys(&self, state: &GroupState) -> anyhow::Result<()> {
+        if self.is_leave() {
+            return match self.keys.is_empty() {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!("A member leaving cannot distribute the next key")),
+            };
+        }
+        let mut recipients: Vec<&str> = self.keys.iter().map(|k| k.recipient.as_str()).collect();
+        recipients.sort();
+        recipients.dedup();
+        let misaddressed = self.keys.iter().any(|k| {
+            k.group_id != state.group_id || k.epoch != state.epoch || k.sender != self.event.actor
+        });
+        if misaddressed || recipients.len() != self.keys.len() || recipients != state.members {
+            return Err(anyhow::anyhow!("Group key must be sealed once to every member of the new epoch"));
+        }
+        Ok(())
+    }
+}
+
+/// A group's event log and every sealed key it has distributed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupRecord {
+    pub state: GroupState,
+    pub events: Vec<MembershipEvent>,
+    pub keys: Vec<SealedGroupKey>,
+}
+
+/// Server-side directory of groups
+#[derive(Debug, Default)]
+pub struct GroupRegistry {
+    groups: HashMap<String, GroupRecord>,
+}
+
+impl GroupRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a group from a signed Create event. `actor_key` is the creator's current key.
+    pub fn create(&mut self, update: GroupUpdate, actor_key: &PublicKey) -> anyhow::Result<&GroupState> {
+        if self.groups.contains_key(&update.event.group_id) {
+            return Err(anyhow::anyhow!("Group {} already exists", update.event.group_id));
+        }
+        if !update.event.verify(actor_key) {
+            return Err(anyhow::anyhow!("Event is not signed by its actor"));
+        }
+        let state = GroupState::genesis(&update.event)?;
+        update.check_keys(&state)?;
+        let record = GroupRecord { state, events: vec![update.event], keys: update.keys };
+        Ok(&self.groups.entry(record.state.group_id.clone()).or_insert(record).state)
+    }
+
+    /// Append a signed Add/Remove event and the re-keyed epoch
+    pub fn apply(&mut self, update: GroupUpdate, actor_key: &PublicKey) -> anyhow::Result<&GroupState> {
+        let record = self
+            .groups
+            .get_mut(&update.event.group_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown group {}", update.event.group_id))?;
+        if !update.event.verify(actor_key) {
+            return Err(anyhow::anyhow!("Event is not signed by its actor"));
+        }
+        let mut state = record.state.clone();
+        if !state.is_member(&update.event.actor) {
+            return Err(anyhow::anyhow!("Only members can change the group"));
+        }
+        state.apply(&update.event)?;
+        update.check_keys(&state)?;
+        record.state = state;
+        record.events.push(update.event);
+        record.keys.extend(update.keys);
+        Ok(&record.state)
+    }
+
+    pub fn get(&self, group_id: &str) -> Option<&GroupRecord> {
+        self.groups.get(group_id)
+    }
+
+    pub fn is_member(&self, group_id: &str, identity_hash: &str) -> bool {
+        self.groups.get(group_id).is_some_and(|record| record.state.is_member(identity_hash))
+    }
+
+    /// Whether the current epoch's key has been distributed (false right after a leave)
+    pub fn is_keyed(&self, group_id: &str) -> bool {
+        self.groups
+            .get(group_id)
+            .is_some_and(|record| record.keys.iter().any(|k| k.epoch == record.state.epoch))
+    }
+
+    /// Every epoch key sealed to `identity_hash` in a group, oldest first
+    pub fn keys_for(&self, group_id: &str, identity_hash: &str) -> Vec<SealedGroupKey> {
+        self.groups
+            .get(group_id)
+            .map(|record| record.keys.iter().filter(|k| k.recipient == identity_hash).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Groups `identity_hash` currently belongs to
+    pub fn groups_for(&self, identity_hash: &str) -> Vec<&GroupState> {
+        self.groups
+            .values()
+            .map(|record| &record.state)
+            .filter(|state| state.is_member(identity_hash))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    fn event(action: MembershipAction) -> MembershipEvent {
+        MembershipEvent {
+            group_id: "group:00".to_string(),
+            sequence: 0,
+            previous: String::new(),
+            actor: "a".to_string(),
+            action,
+            timestamp: 1_000,
+            signature: String::new(),
+        }
+    }
+
+    #[test]
+    fn member_lists_cannot_be_reshuffled_across_separators() {
+        let members = |list: &[&str]| MembershipAction::Create { members: list.iter().map(|m| m.to_string()).collect() };
+        let split = event(members(&["a", "b,c"]));
+        let joined = event(members(&["a,b", "c"]));
+        assert_ne!(split.signing_payload(), joined.signing_payload());
+        assert_ne!(split.id(), joined.id());
+    }
+
+    fn members(list: &[&str]) -> MembershipAction {
+        MembershipAction::Create { members: list.iter().map(|m| m.to_string()).collect() }
+    }
+
+    fn next(state: &GroupState, actor: &str, action: MembershipAction) -> MembershipEvent {
+        MembershipEvent {
+            group_id: state.group_id.clone(),
+            sequence: state.epoch + 1,
+            previous: state.head.clone(),
+            actor: actor.to_string(),
+            ..event(action)
+        }
+    }
+
+    /// Alice owns a group with Bob and Carol; everyone knows everyone's key
+    fn group() -> (Vec<IdentityManager>, GroupRegistry, GroupState) {
+        let mut people = vec![IdentityManager::new(), IdentityManager::new(), IdentityManager::new(), IdentityManager::new()];
+        let keys: Vec<_> = people.iter().map(|p| (p.get_identity_hash().to_string(), p.public_key())).collect();
+        for person in &mut people {
+            for (hash, key) in &keys {
+                if hash != person.get_identity_hash() {
+                    person.add_contact(hash.clone(), *key);
+                }
+            }
+        }
+        let update = people[0].create_group(&[people[1].get_identity_hash(), people[2].get_identity_hash()]).unwrap();
+        let mut registry = GroupRegistry::new();
+        let state = registry.create(update, &people[0].public_key()).unwrap().clone();
+        (people, registry, state)
+    }
+
+    #[test]
+    fn genesis_needs_a_well_formed_create() {
+        let state = GroupState::genesis(&event(members(&["b", "a", "b"]))).unwrap();
+        assert_eq!(state.owner, "a");
+        assert_eq!(state.members, vec!["a", "b"]);
+        assert_eq!(state.epoch, 0);
+
+        assert!(GroupState::genesis(&event(MembershipAction::Rekey)).is_err());
+        assert!(GroupState::genesis(&event(members(&["a"]))).is_err());
+        assert!(GroupState::genesis(&event(members(&["b", "c"]))).is_err());
+        assert!(GroupState::genesis(&MembershipEvent { group_id: "00".to_string(), ..event(members(&["a", "b"])) }).is_err());
+        assert!(GroupState::genesis(&MembershipEvent { sequence: 1, ..event(members(&["a", "b"])) }).is_err());
+        assert!(GroupState::genesis(&MembershipEvent { previous: "x".to_string(), ..event(members(&["a", "b"])) }).is_err());
+    }
+
+    #[test]
+    fn only_the_owner_adds_and_removes_others() {
+        let mut state = GroupState::genesis(&event(members(&["a", "b", "c"]))).unwrap();
+        let add = |member: &str| MembershipAction::Add { member: member.to_string() };
+        let remove = |member: &str| MembershipAction::Remove { member: member.to_string() };
+
+        assert!(state.clone().apply(&next(&state, "b", add("d"))).is_err());
+        assert!(state.clone().apply(&next(&state, "a", add("b"))).is_err());
+        state.apply(&next(&state, "a", add("d"))).unwrap();
+        assert_eq!(state.members, vec!["a", "b", "c", "d"]);
+        assert_eq!(state.epoch, 1);
+
+        assert!(state.clone().apply(&next(&state, "c", remove("d"))).is_err());
+        assert!(state.clone().apply(&next(&state, "a", remove("a"))).is_err());
+        assert!(state.clone().apply(&next(&state, "a", remove("e"))).is_err());
+        state.apply(&next(&state, "a", remove("d"))).unwrap();
+        state.apply(&next(&state, "c", remove("c"))).unwrap();
+        assert_eq!(state.members, vec!["a", "b"]);
+        assert_eq!(state.epoch, 3);
+
+        assert!(state.clone().apply(&next(&state, "a", members(&["a", "b"]))).is_err());
+    }
+
+    #[test]
+    fn events_must_extend_the_chain() {
+        let mut state = GroupState::genesis(&event(members(&["a", "b"]))).unwrap();
+        let rekey = next(&state, "a", MembershipAction::Rekey);
+        assert!(state.clone().apply(&MembershipEvent { sequence: 2, ..rekey.clone() }).is_err());
+        assert!(state.clone().apply(&MembershipEvent { sequence: 0, ..rekey.clone() }).is_err());
+        assert!(state.clone().apply(&MembershipEvent { previous: "0".repeat(64), ..rekey.clone() }).is_err());
+        assert!(state.clone().apply(&MembershipEvent { group_id: "group:01".to_string(), ..rekey.clone() }).is_err());
+
+        state.apply(&rekey).unwrap();
+        assert_eq!(state.head, rekey.id());
+        assert!(state.apply(&rekey).is_err(), "an event cannot be replayed");
+    }
+
+    #[test]
+    fn keys_go_once_to_every_member_of_the_epoch() {
+        let (people, mut registry, state) = group();
+        let (alice, bob) = (&people[0], &people[1]);
+        let dave = people[3].get_identity_hash().to_string();
+        let update = alice.update_group(&state, MembershipAction::Add { member: dave }).unwrap();
+        let mut next_state = state.clone();
+        next_state.apply(&update.event).unwrap();
+        update.check_keys(&next_state).unwrap();
+
+        let mut missing = update.clone();
+        missing.keys.pop();
+        assert!(missing.check_keys(&next_state).is_err());
+
+        let mut duplicate = update.clone();
+        duplicate.keys[3] = duplicate.keys[0].clone();
+        assert!(duplicate.check_keys(&next_state).is_err());
+
+        let mut stale = update.clone();
+        stale.keys[0].epoch = state.epoch;
+        assert!(stale.check_keys(&next_state).is_err());
+
+        let mut impersonated = update.clone();
+        impersonated.keys[0].sender = bob.get_identity_hash().to_string();
+        assert!(impersonated.check_keys(&next_state).is_err());
+
+        assert!(registry.apply(update.clone(), &bob.public_key()).is_err(), "signed by the actor only");
+        registry.apply(update, &alice.public_key()).unwrap();
+        assert!(registry.is_keyed(&state.group_id));
+    }
+
+    #[test]
+    fn leavers_cannot_pick_the_next_key() {
+        let (people, mut registry, state) = group();
+        let (alice, bob) = (&people[0], &people[1]);
+        let leave = MembershipAction::Remove { member: bob.get_identity_hash().to_string() };
+        let update = bob.update_group(&state, leave).unwrap();
+        assert!(update.is_leave() && update.keys.is_empty());
+
+        let mut with_keys = update.clone();
+        with_keys.keys = alice.update_group(&state, MembershipAction::Rekey).unwrap().keys;
+        assert!(registry.apply(with_keys, &bob.public_key()).is_err());
+
+        let state = registry.apply(update, &bob.public_key()).unwrap().clone();
+        assert!(!state.is_member(bob.get_identity_hash()));
+        assert!(!registry.is_keyed(&state.group_id));
+
+        let outsider = &people[3];
+        let rekey = outsider.update_group(&state, MembershipAction::Rekey).unwrap();
+        assert!(registry.apply(rekey, &outsider.public_key()).is_err(), "only members can rekey");
+        registry.apply(alice.update_group(&state, MembershipAction::Rekey).unwrap(), &alice.public_key()).unwrap();
+        assert!(registry.is_keyed(&state.group_id));
+        assert!(registry.keys_for(&state.group_id, bob.get_identity_hash()).iter().all(|k| k.epoch < state.epoch));
+    }
+
+    #[test]
+    fn sealed_group_keys_open_only_with_their_header() {
+        let pairwise = [1u8; 32];
+        let group_key = [2u8; 32];
+        let sealed = SealedGroupKey::seal(&pairwise, "group:00", 3, "alice", "bob", &group_key).unwrap();
+        assert_eq!(sealed.open(&pairwise).unwrap(), group_key);
+        assert!(sealed.open(&[9u8; 32]).is_err());
+
+        let tampered = [
+            SealedGroupKey { group_id: "group:01".to_string(), ..sealed.clone() },
+            SealedGroupKey { epoch: 4, ..sealed.clone() },
+            SealedGroupKey { sender: "mallory".to_string(), ..sealed.clone() },
+            SealedGroupKey { recipient: "carol".to_string(), ..sealed.clone() },
+            SealedGroupKey { nonce: general_purpose::STANDARD.encode([0u8; 12]), ..sealed.clone() },
+        ];
+        for sealed in tampered {
+            assert!(sealed.open(&pairwise).is_err());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+// Human-readable handle registry (@alice -> identity_hash)
+// A handle is claimed with a self-issued "handle" attestation, first come first
+// served, and only moves to another identity with a transfer signed by its owner
+
+use std::collections::HashMap;
+
+use ed25519_dalek::{PublicKey, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::attestations::{AttestationVerifier, ClaimReveal};
+use crate::identity::{parse_signature, Attestation};
+
+/// Attestation claim recording a handle binding
+pub const HANDLE_CLAIM: &str = "handle";
+
+/// How long a signed transfer can wait before it is submitted
+pub const HANDLE_TRANSFER_TTL_SECS: u64 = 24 * 60 * 60;
+/// Tolerance for transfer timestamps slightly in the future
+pub const HANDLE_TRANSFER_MAX_SKEW_SECS: u64 = 300;
+/// Handles a single identity can hold, so one identity cannot squat the namespace
+pub const MAX_HANDLES_PER_IDENTITY: usize = 4;
+
+/// Canonical form of a handle: leading '@' stripped, lowercase, 3-32 of [a-z0-9_]
+pub fn normalize_handle(handle: &str) -> anyhow::Result<String> {
+    let handle = handle.strip_prefix('@').unwrap_or(handle).to_ascii_lowercase();
+    if !(3..=32).contains(&handle.len()) {
+        return Err(anyhow::anyhow!("Handles must be 3 to 32 characters"));
+    }
+    if !handle.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err(anyhow::anyhow!("Handles may only contain letters, digits and '_'"));
+    }
+    Ok(handle)
+}
+
+/// Request to bind a handle: a self-issued "handle" attestation plus its opening
+/// (the handle is public, so the salt is published with it)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleClaim {
+    pub handle: String,            // canonical handle, without '@'
+    pub attestation: Attestation,
+    pub reveal: ClaimReveal,
+}
+
+impl HandleClaim {
+    /// Identity the handle would resolve to
+    pub fn identity_hash(&self) -> &str {
+        &self.attestation.subject
+    }
+
+    /// Check the attestation binds `handle` to its issuer and is signed by `owner_key`
+    pub fn verify(&self, owner_key: &PublicKey) -> anyhow::Result<()> {
+        if normalize_handle(&self.handle)? != self.handle {
+            return Err(anyhow::anyhow!("Handle is not in canonical form"));
+        }
+        let attestation = &self.attestation;
+        if attestation.claim != HANDLE_CLAIM || attestation.issuer != attestation.subject {
+            return Err(anyhow::anyhow!("Not a self-issued handle attestation"));
+        }
+        if self.reveal.value != self.handle {
+            return Err(anyhow::anyhow!("Attestation is for a different handle"));
+        }
+        AttestationVerifier::default().verify(attestation, owner_key, Some(&self.reveal))
+    }
+}
+
+/// Owner-signed hand-over of a handle to another identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleTransfer {
+    pub handle: String,
+    pub from_identity: String,
+    pub to_identity: String,
+    pub sequence: u64,             // Transfers of the handle so far; each transfer is single use
+    pub timestamp: u64,
+    pub signature: String,         // hex signature by the current owner's key
+}
+
+impl HandleTransfer {
+    /// Bytes signed by the current owner
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_handle_transfer_v2:{}:{}:{}:{}:{}",
+            self.handle, self.from_identity, self.to_identity, self.sequence, self.timestamp
+        )
+    }
+
+    pub fn verify(&self, owner_key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| owner_key.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+/// Transfer request: the old owner's signature and the new owner's claim
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleTransferRequest {
+    pub transfer: HandleTransfer,
+    pub claim: HandleClaim,
+}
+
+/// Current binding of a handle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandleRecord {
+    pub handle: String,
+    pub identity_hash: String,
+    pub claim: HandleClaim,        // Binding attestation of the current owner
+    pub transfers: Vec<HandleTransfer>, // Ownership history
+}
+
+/// First-come-first-served handle directory
+#[derive(Debug, Default)]
+pub struct HandleRegistry {
+    records: HashMap<String, HandleRecord>,
+}
+
+impl HandleRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind an unclaimed handle. Re-registering one's own handle refreshes the attestation.
+    pub fn register(&mut self, claim: HandleClaim, owner_key: &PublicKey) -> anyhow::Result<()> {
+        claim.verify(owner_key)?;
+        if let Some(record) = self.records.get_mut(&claim.handle) {
+            if record.identity_hash != claim.identity_hash() {
+                return Err(anyhow::anyhow!("Handle @{} is already taken", claim.handle));
+            }
+            record.claim = claim;
+            return Ok(());
+        }
+        if self.count_for(claim.identity_hash()) >= MAX_HANDLES_PER_IDENTITY {
+            return Err(anyhow::anyhow!("Identity already holds {} handles", MAX_HANDLES_PER_IDENTITY));
+        }
+        self.records.insert(
+            claim.handle.clone(),
+            HandleRecord {
+                handle: claim.handle.clone(),
+                identity_hash: claim.identity_hash().to_string(),
+                claim,
+                transfers: Vec::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Move a handle: `from_key` must be the current owner's key and `to_key` must
+    /// sign the new owner's claim. The transfer must name the record's current
+    /// transfer count and be recent, so an old transfer cannot be replayed after
+    /// the handle has moved on.
+    pub fn transfer(
+        &mut self,
+        request: HandleTransferRequest,
+        from_key: &PublicKey,
+        to_key: &PublicKey,
+        now: u64,
+    ) -> anyhow::Result<()> {
+        let HandleTransferRequest { transfer, claim } = request;
+        let held = self.count_for(&transfer.to_identity);
+        let record = self
+            .records
+            .get_mut(&transfer.handle)
+            .ok_or_else(|| anyhow::anyhow!("Handle @{} is not registered", transfer.handle))?;
+        if transfer.from_identity != record.identity_hash || !transfer.verify(from_key) {
+            return Err(anyhow::anyhow!("Transfer is not signed by the current owner"));
+        }
+        if transfer.sequence != record.transfers.len() as u64 {
+            return Err(anyhow::anyhow!("Transfer {} has already been used or is out of order", transfer.sequence));
+        }
+        if transfer.timestamp > now + HANDLE_TRANSFER_MAX_SKEW_SECS
+            || now.saturating_sub(transfer.timestamp) > HANDLE_TRANSFER_TTL_SECS
+        {
+            return Err(anyhow::anyhow!("Transfer has expired"));
+        }
+        if claim.handle != transfer.handle || claim.identity_hash() != transfer.to_identity {
+            return Err(anyhow::anyhow!("Claim does not match the transfer"));
+        }
+        claim.verify(to_key)?;
+        if held >= MAX_HANDLES_PER_IDENTITY {
+            return Err(anyhow::anyhow!("Identity already holds {} handles", MAX_HANDLES_PER_IDENTITY));
+        }
+        record.identity_hash = transfer.to_identity.clone();
+        record.claim = claim;
+        record.transfers.push(transfer);
+        Ok(())
+    }
+
+    /// Number of handles bound to `identity_hash`
+    pub fn count_for(&self, identity_hash: &str) -> usize {
+        self.records.values().filter(|r| r.identity_hash == identity_hash).count()
+    }
+
+    /// Look up a handle (with or without '@')
+    pub fn resolve(&self, handle: &str) -> Option<&HandleRecord> {
+        self.records.get(&normalize_handle(handle).ok()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::now_secs;
+    use crate::identity::IdentityManager;
+
+    fn transfer(from: &IdentityManager, to: &IdentityManager, sequence: u64) -> HandleTransferRequest {
+        HandleTransferRequest {
+            transfer: from.transfer_handle("@alice", to.get_identity_hash(), sequence).unwrap(),
+            claim: to.claim_handle("@alice").unwrap(),
+        }
+    }
+
+    #[test]
+    fn first_come_first_served() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let mut registry = HandleRegistry::new();
+        registry.register(alice.claim_handle("@Alice").unwrap(), &alice.public_key()).unwrap();
+        assert!(registry.register(bob.claim_handle("alice").unwrap(), &bob.public_key()).is_err());
+        assert_eq!(registry.resolve("@ALICE").unwrap().identity_hash, alice.get_identity_hash());
+        assert!(normalize_handle("a!").is_err());
+    }
+
+    #[test]
+    fn transfers_cannot_be_replayed() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let mut registry = HandleRegistry::new();
+        registry.register(alice.claim_handle("@alice").unwrap(), &alice.public_key()).unwrap();
+
+        let to_bob = transfer(&alice, &bob, 0);
+        registry.transfer(to_bob.clone(), &alice.public_key(), &bob.public_key(), now_secs()).unwrap();
+        registry.transfer(transfer(&bob, &alice, 1), &bob.public_key(), &alice.public_key(), now_secs()).unwrap();
+
+        // A -> B -> A, then the old A -> B transfer and B's old claim are resubmitted
+        assert!(registry.transfer(to_bob, &alice.public_key(), &bob.public_key(), now_secs()).is_err());
+        assert_eq!(registry.resolve("alice").unwrap().identity_hash, alice.get_identity_hash());
+    }
+
+    #[test]
+    fn stale_transfers_are_rejected() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let mut registry = HandleRegistry::new();
+        registry.register(alice.claim_handle("@alice").unwrap(), &alice.public_key()).unwrap();
+        let later = now_secs() + HANDLE_TRANSFER_TTL_SECS + 1;
+        assert!(registry.transfer(transfer(&alice, &bob, 0), &alice.public_key(), &bob.public_key(), later).is_err());
+        assert!(registry.transfer(transfer(&alice, &bob, 1), &alice.public_key(), &bob.public_key(), now_secs()).is_err());
+    }
+
+    #[test]
+    fn identities_hold_a_limited_number_of_handles() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let mut registry = HandleRegistry::new();
+        for i in 0..MAX_HANDLES_PER_IDENTITY {
+            registry.register(bob.claim_handle(&format!("bob_{}", i)).unwrap(), &bob.public_key()).unwrap();
+        }
+        assert!(registry.register(bob.claim_handle("bob_extra").unwrap(), &bob.public_key()).is_err());
+        registry.register(bob.claim_handle("bob_0").unwrap(), &bob.public_key()).unwrap();
+        assert_eq!(registry.count_for(bob.get_identity_hash()), MAX_HANDLES_PER_IDENTITY);
+
+        registry.register(alice.claim_handle("@alice").unwrap(), &alice.public_key()).unwrap();
+        assert!(registry.transfer(transfer(&alice, &bob, 0), &alice.public_key(), &bob.public_key(), now_secs()).is_err());
+        assert_eq!(registry.resolve("alice").unwrap().identity_hash, alice.get_identity_hash());
+    }
+}
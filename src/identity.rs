@@ -6,28 +6,283 @@ use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 use std::collections::HashMap;
+use crate::keystore::{ContactRecord, Keystore, KeystoreContents};
+use crate::derivation::{DerivationPath, DerivedKey, ExtendedKey, SubkeyCertificate};
+use crate::devices::{DeviceRevocation, DEVICE_PURPOSE};
+use crate::attestations::{commit_claim_value, AttestationRevocation, ClaimReveal, RevocationList};
+use crate::credentials::{Credential, CredentialClaim, HeldCredential};
+use crate::commitments::merkle_root;
+use crate::handles::{normalize_handle, HandleClaim, HandleTransfer, HANDLE_CLAIM};
+use crate::safety::SafetyNumber;
+use crate::contact_card::ContactCard;
+use crate::groups::{new_group_id, GroupState, GroupUpdate, MembershipAction, MembershipEvent, SealedGroupKey};
+use crate::recovery::{split_secret, RecoveryKit, SealedShare, RECOVERY_HOLDER};
+use crate::mnemonic;
+use crate::rotation::{follow_rotations, verify_link, verify_rotation_chain, RotationCertificate};
+use crate::keyexchange::{derive_thread_key, ed25519_public_to_x25519, X25519Secret};
+use crate::prekeys::{self, PrekeyBundle, PrekeySecrets, PrekeyUpload, SignedPrekey, X3dhInit};
+use crate::now_secs;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Identity {
-    pub public_key: Vec<u8>,      // ED25519 public key (32 bytes)
-    pub identity_hash: String,     // Poseidon hash of public key (for privacy)
+    pub public_key: Vec<u8>,      // Current ED25519 public key (32 bytes)
+    pub identity_hash: String,     // Poseidon hash of original public key (for privacy)
     pub attestations: Vec<Attestation>,
+    #[serde(default)]
+    pub rotations: Vec<RotationCertificate>, // Key rotations since the original key
+    #[serde(default)]
+    pub devices: Vec<SubkeyCertificate>,     // Device keys endorsed by the root key
+    #[serde(default)]
+    pub device_revocations: Vec<DeviceRevocation>,
+    #[serde(default)]
+    pub attestation_revocations: Vec<AttestationRevocation>, // Attestations this identity issued and withdrew
+}
+
+impl Identity {
+    /// Verify the rotation chain from the original key and return the current key
+    pub fn current_key(&self) -> anyhow::Result<PublicKey> {
+        let current = PublicKey::from_bytes(&self.public_key)
+            .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+        let original = match self.rotations.first() {
+            Some(first) => parse_public_key(&first.previous_key)?,
+            None => current,
+        };
+        let verified = verify_rotation_chain(&self.identity_hash, &original, &self.rotations)?;
+        if verified != current {
+            return Err(anyhow::anyhow!("Rotation chain does not end at the current key"));
+        }
+        Ok(current)
+    }
+
+    /// Apply a new rotation signed by the current key
+    pub fn apply_rotation(&mut self, rotation: RotationCertificate) -> anyhow::Result<()> {
+        let current = self.current_key()?;
+        let expected_seq = self.rotations.len() as u64 + 1;
+        let new_key = verify_link(&self.identity_hash, expected_seq, &current, &rotation)?;
+        self.public_key = new_key.to_bytes().to_vec();
+        self.rotations.push(rotation);
+        Ok(())
+    }
+
+    /// Add a device certificate signed by the current root key
+    pub fn add_device(&mut self, certificate: SubkeyCertificate) -> anyhow::Result<()> {
+        if certificate.root_identity_hash != self.identity_hash || certificate.purpose != DEVICE_PURPOSE {
+            return Err(anyhow::anyhow!("Not a device certificate for this identity"));
+        }
+        if !certificate.verify(&self.current_key()?) {
+            return Err(anyhow::anyhow!("Device certificate is not signed by the root key"));
+        }
+        if self.is_revoked_device(&certificate.subkey) {
+            return Err(anyhow::anyhow!("Device key has been revoked"));
+        }
+        self.devices.retain(|d| d.subkey != certificate.subkey);
+        self.devices.push(certificate);
+        Ok(())
+    }
+
+    /// Revoke a device key with a revocation signed by the current root key
+    pub fn revoke_device(&mut self, revocation: DeviceRevocation) -> anyhow::Result<()> {
+        if revocation.identity_hash != self.identity_hash || !revocation.verify(&self.current_key()?) {
+            return Err(anyhow::anyhow!("Revocation is not signed by the root key"));
+        }
+        if !self.devices.iter().any(|d| d.subkey == revocation.device_key) {
+            return Err(anyhow::anyhow!("Unknown device key"));
+        }
+        if !self.is_revoked_device(&revocation.device_key) {
+            self.device_revocations.push(revocation);
+        }
+        Ok(())
+    }
+
+    /// Record the withdrawal of an attestation this identity issued
+    pub fn revoke_attestation(&mut self, revocation: AttestationRevocation) -> anyhow::Result<()> {
+        if revocation.issuer != self.identity_hash || !revocation.verify(&self.current_key()?) {
+            return Err(anyhow::anyhow!("Revocation is not signed by the issuer"));
+        }
+        if !self.attestation_revocations.iter().any(|r| r.attestation_id == revocation.attestation_id) {
+            self.attestation_revocations.push(revocation);
+        }
+        Ok(())
+    }
+
+    /// Attestations withdrawn by this identity, as served to verifiers
+    pub fn revocation_list(&self) -> RevocationList {
+        RevocationList {
+            issuer: self.identity_hash.clone(),
+            revocations: self.attestation_revocations.clone(),
+        }
+    }
+
+    fn is_revoked_device(&self, device_key: &str) -> bool {
+        self.device_revocations.iter().any(|r| r.device_key == device_key)
+    }
+
+    /// Device keys that are endorsed by the current root key and not revoked
+    pub fn active_device_keys(&self) -> Vec<PublicKey> {
+        let Ok(root) = self.current_key() else {
+            return vec![];
+        };
+        self.devices
+            .iter()
+            .filter(|d| d.verify(&root) && !self.is_revoked_device(&d.subkey))
+            .filter_map(|d| parse_public_key(&d.subkey).ok())
+            .collect()
+    }
+}
+
+/// Signs on behalf of an identity: the root key or an endorsed device key
+pub trait IdentitySigner {
+    fn identity_hash(&self) -> &str;
+
+    fn sign(&self, message: &[u8]) -> Signature;
+
+    /// Device key doing the signing, or None for the root key
+    fn device_key(&self) -> Option<PublicKey> {
+        None
+    }
+}
+
+/// A trusted contact's key, kept up to date across rotations
+#[derive(Debug, Clone)]
+pub struct Contact {
+    pub public_key: PublicKey,
+    pub rotation_sequence: u64,    // Last rotation followed (0 = original key)
+    pub chain_broken: bool,        // Set when a published rotation chain failed to verify
+    pub verification: VerificationState,
+}
+
+/// Whether the user has compared safety numbers with a contact
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerificationState {
+    #[default]
+    Unverified,
+    Verified,                      // Safety number compared out of band
+    Changed,                       // Key replaced without a signed rotation; re-verify
+}
+
+/// Raised when a contact's key is replaced by one not endorsed by the old key
+#[derive(Debug, Clone)]
+pub struct KeyChangeWarning {
+    pub identity_hash: String,
+    pub previous_key: PublicKey,
+    pub new_key: PublicKey,
+    pub was_verified: bool,
+}
+
+impl std::fmt::Display for KeyChangeWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let short: String = self.identity_hash.chars().take(16).collect();
+        write!(f, "Key for contact {} changed", short)?;
+        if self.was_verified {
+            write!(f, " after it was verified")?;
+        }
+        write!(f, "; compare safety numbers again")
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attestation {
     pub issuer: String,            // Identity hash of issuer
+    #[serde(default)]
+    pub subject: String,           // Identity hash the claim is about (== issuer when self-issued)
     pub claim: String,             // Claim type (e.g., "email", "handle")
-    pub value_hash: String,        // Hash of claim value (privacy-preserving)
+    pub value_hash: String,        // Salted commitment to the claim value (see `ClaimReveal`)
     pub signature: String,         // Signature by issuer
     pub timestamp: u64,
+    #[serde(default)]
+    pub expires_at: Option<u64>,   // None = no expiry
+}
+
+impl Attestation {
+    /// Bytes signed by the issuer
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_attestation_v1:{}:{}:{}:{}:{}:{}",
+            self.issuer,
+            self.subject,
+            self.claim,
+            self.value_hash,
+            self.timestamp,
+            self.expires_at.map(|t| t.to_string()).unwrap_or_default()
+        )
+    }
+
+    /// Stable identifier used by revocations: SHA256 of the signed payload
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.signing_payload().as_bytes()))
+    }
+
+    /// Check the issuer's signature
+    pub fn verify(&self, issuer_key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| issuer_key.verify(self.signing_payload().as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Check a disclosed value and salt open `value_hash`
+    pub fn opens(&self, reveal: &ClaimReveal) -> bool {
+        reveal.attestation_id == self.id()
+            && reveal.claim == self.claim
+            && reveal.commitment().is_ok_and(|c| c == self.value_hash)
+    }
+}
+
+/// Public-key registration with proof of possession (`POST /identity/register`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrationRequest {
+    pub identity_hash: String,
+    pub public_key: String,        // hex ED25519 public key
+    pub signature: String,         // hex signature over `registration_payload()`
+}
+
+/// Bytes signed to prove possession of the key being registered
+pub fn registration_payload(identity_hash: &str, public_key: &str) -> String {
+    format!("zerotrace_register_v1:{}:{}", identity_hash, public_key)
+}
+
+impl RegistrationRequest {
+    /// Check the hash is derived from the key and the signature proves possession.
+    /// Returns the public identity to store.
+    pub fn verify(&self) -> anyhow::Result<Identity> {
+        let pubkey = parse_public_key(&self.public_key)?;
+        if IdentityManager::compute_identity_hash(pubkey.as_bytes()) != self.identity_hash {
+            return Err(anyhow::anyhow!("Identity hash does not match public key"));
+        }
+        let signature = parse_signature(&self.signature)?;
+        pubkey
+            .verify(registration_payload(&self.identity_hash, &self.public_key).as_bytes(), &signature)
+            .map_err(|_| anyhow::anyhow!("Proof-of-possession signature is invalid"))?;
+
+        Ok(Identity {
+            public_key: pubkey.to_bytes().to_vec(),
+            identity_hash: self.identity_hash.clone(),
+            attestations: vec![],
+            rotations: vec![],
+            devices: vec![],
+            device_revocations: vec![],
+            attestation_revocations: vec![],
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct IdentityManager {
     keypair: Keypair,  // Store keypair directly for signing
+    chain_code: [u8; 32], // SLIP-10 chain code for subkey derivation
     identity_hash: String,
-    contacts: HashMap<String, PublicKey>, // identity_hash -> public key
+    contacts: HashMap<String, Contact>,   // identity_hash -> contact
+    attestations: Vec<Attestation>,       // Attestations held by this identity
+    claim_reveals: Vec<ClaimReveal>,      // Value + salt openings for held attestations (secret)
+    credentials: Vec<HeldCredential>,     // Selective-disclosure credentials held by this identity
+    rotations: Vec<RotationCertificate>,  // Own key rotations (identity_hash stays fixed)
+    prekeys: PrekeySecrets,               // X3DH prekey secrets (never uploaded)
+}
+
+impl Default for IdentityManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl IdentityManager {
@@ -56,30 +311,72 @@ impl IdentityManager {
             }
         };
         
-        let identity_hash = Self::compute_identity_hash(&public_key.to_bytes());
-        
-        Self {
-            keypair,
-            identity_hash,
-            contacts: HashMap::new(),
-        }
+        Self::from_keypair(keypair)
+    }
+
+    /// Recover an identity from a BIP39 recovery phrase (and optional passphrase).
+    /// The same words always yield the same identity_hash.
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> anyhow::Result<Self> {
+        let seed = mnemonic::mnemonic_to_seed(phrase, passphrase)?;
+        let master = ExtendedKey::from_seed(&seed);
+        let mut identity = Self::from_secret_bytes(master.secret_bytes())?;
+        identity.chain_code = *master.chain_code();
+        Ok(identity)
+    }
+
+    /// Recover a recovery-phrase identity whose key was rotated with
+    /// `rotate_key_from_mnemonic`, given its published rotation chain
+    pub fn from_mnemonic_rotated(phrase: &str, passphrase: &str, rotations: Vec<RotationCertificate>) -> anyhow::Result<Self> {
+        let seed = mnemonic::mnemonic_to_seed(phrase, passphrase)?;
+        let master = ExtendedKey::from_seed(&seed);
+        let identity_hash = Self::compute_identity_hash(master.keypair().public.as_bytes());
+        let current = match rotations.last() {
+            Some(last) => master.derive_path(&DerivationPath::for_rotation(last.sequence)?),
+            None => master.clone(),
+        };
+        let mut identity = Self::from_secret_bytes(current.secret_bytes())?;
+        identity.chain_code = *master.chain_code();
+        identity.adopt_identity_hash(&identity_hash, rotations)?;
+        Ok(identity)
     }
 
     /// Generate random identity
     pub fn new() -> Self {
         let mut csprng = OsRng;
-        let keypair = Keypair::generate(&mut csprng);
+        Self::from_keypair(Keypair::generate(&mut csprng))
+    }
+
+    fn from_secret_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let secret = SecretKey::from_bytes(bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid secret key: {}", e))?;
+        let public = PublicKey::from(&secret);
+        Ok(Self::from_keypair(Keypair { secret, public }))
+    }
+
+    fn from_keypair(keypair: Keypair) -> Self {
         let identity_hash = Self::compute_identity_hash(&keypair.public.to_bytes());
         
+        // Identities not recovered from a mnemonic get a chain code bound to their secret
+        let mut hasher = Sha256::new();
+        hasher.update(b"zerotrace_chain_code");
+        hasher.update(keypair.secret.as_bytes());
+        let chain_code = hasher.finalize().into();
+        
         Self {
             keypair,
+            chain_code,
             identity_hash,
             contacts: HashMap::new(),
+            attestations: Vec::new(),
+            claim_reveals: Vec::new(),
+            credentials: Vec::new(),
+            rotations: Vec::new(),
+            prekeys: PrekeySecrets::default(),
         }
     }
 
     /// Compute privacy-preserving identity hash (simulates Poseidon)
-    fn compute_identity_hash(pubkey: &[u8]) -> String {
+    pub fn compute_identity_hash(pubkey: &[u8]) -> String {
         let mut hasher = Sha256::new();
         hasher.update(b"zerotrace_identity");
         hasher.update(pubkey);
@@ -94,6 +391,140 @@ impl IdentityManager {
         self.keypair.public.to_bytes().to_vec()
     }
 
+    /// Current ED25519 public key
+    pub fn public_key(&self) -> PublicKey {
+        self.keypair.public
+    }
+
+    /// X25519 public key used for key agreement (derived from the ED25519 key)
+    pub fn get_x25519_public_key(&self) -> [u8; 32] {
+        X25519Secret::from_ed25519(&self.keypair.secret).public_key()
+    }
+
+    /// Derive the shared key for a two-party thread with `peer_pubkey`.
+    /// Both parties compute the same key independently; it never crosses the wire.
+    pub fn derive_thread_key(&self, thread_id: &str, peer_pubkey: &PublicKey) -> anyhow::Result<[u8; 32]> {
+        let secret = X25519Secret::from_ed25519(&self.keypair.secret);
+        let shared = secret.diffie_hellman(&ed25519_public_to_x25519(peer_pubkey)?)?;
+        Ok(derive_thread_key(&shared, thread_id))
+    }
+
+    /// Rotate the signed prekey and mint `one_time_count` one-time prekeys.
+    /// Returns the signed upload to publish via `POST /prekeys`.
+    pub fn generate_prekeys(&mut self, one_time_count: u32) -> PrekeyUpload {
+        let (spk_id, spk, one_time_prekeys) = self.prekeys.rotate(one_time_count);
+        let spk_public = hex::encode(spk.public_key());
+        let spk_signature = self.sign(prekeys::signed_prekey_payload(spk_id, &spk_public).as_bytes());
+        let signed_prekey = SignedPrekey {
+            id: spk_id,
+            public_key: spk_public,
+            signature: hex::encode(spk_signature.to_bytes()),
+        };
+
+        let timestamp = now_secs();
+        let payload = prekeys::upload_payload(&self.identity_hash, &signed_prekey, &one_time_prekeys, timestamp);
+        PrekeyUpload {
+            identity_hash: self.identity_hash.clone(),
+            identity_key: hex::encode(self.keypair.public.as_bytes()),
+            signed_prekey,
+            one_time_prekeys,
+            timestamp,
+            signature: hex::encode(self.sign(payload.as_bytes()).to_bytes()),
+        }
+    }
+
+    /// Start a thread with an offline identity from its prekey bundle.
+    /// The responder's key is taken from contacts (kept current across rotations);
+    /// a non-contact's bundle must carry the key its identity_hash was derived from.
+    /// Returns the header for the first message and the initial thread secret.
+    pub fn x3dh_initiate(&self, bundle: &PrekeyBundle) -> anyhow::Result<(X3dhInit, [u8; 32])> {
+        let remote_key = match self.get_contact(&bundle.identity_hash) {
+            Some(key) => *key,
+            None => {
+                let key = parse_public_key(&bundle.identity_key)?;
+                if Self::compute_identity_hash(key.as_bytes()) != bundle.identity_hash {
+                    return Err(anyhow::anyhow!("Bundle key has rotated: add the identity as a contact first"));
+                }
+                key
+            }
+        };
+        let secret = X25519Secret::from_ed25519(&self.keypair.secret);
+        prekeys::initiate(&secret, &self.keypair.public, bundle, &remote_key)
+    }
+
+    /// Derive the initial thread secret from an initiator's X3DH header.
+    /// The referenced one-time prekey is consumed and cannot be reused.
+    pub fn x3dh_respond(&mut self, init: &X3dhInit) -> anyhow::Result<[u8; 32]> {
+        let secret = X25519Secret::from_ed25519(&self.keypair.secret);
+        prekeys::respond(&secret, &self.keypair.public, &mut self.prekeys, init)
+    }
+
+    /// Derive the hardened child key at `path` from this (root) identity
+    pub fn derive_key(&self, path: &DerivationPath) -> DerivedKey {
+        let root = ExtendedKey::new(*self.keypair.secret.as_bytes(), self.chain_code);
+        DerivedKey::new(root.derive_path(path), path.clone())
+    }
+
+    /// Root-sign a certificate endorsing a derived subkey for `purpose`
+    pub fn certify_subkey(&self, key: &DerivedKey, purpose: &str) -> SubkeyCertificate {
+        let mut certificate = SubkeyCertificate {
+            root_identity_hash: self.identity_hash.clone(),
+            subkey: hex::encode(key.public_key().as_bytes()),
+            path: key.path().to_string(),
+            purpose: purpose.to_string(),
+            issued_at: now_secs(),
+            signature: String::new(),
+        };
+        certificate.signature = hex::encode(self.sign(certificate.signing_payload().as_bytes()).to_bytes());
+        certificate
+    }
+
+    /// Derive and certify the signing key for device `device_index` (`m/1'/{index}'`)
+    pub fn derive_device_key(&self, device_index: u32) -> anyhow::Result<(DerivedKey, SubkeyCertificate)> {
+        let key = self.derive_key(&DerivationPath::for_device(device_index)?);
+        let certificate = self.certify_subkey(&key, DEVICE_PURPOSE);
+        Ok((key, certificate))
+    }
+
+    /// Root-sign the revocation of a device key
+    pub fn revoke_device(&self, device_key: &PublicKey) -> DeviceRevocation {
+        let mut revocation = DeviceRevocation {
+            identity_hash: self.identity_hash.clone(),
+            device_key: hex::encode(device_key.as_bytes()),
+            revoked_at: now_secs(),
+            signature: String::new(),
+        };
+        revocation.signature = hex::encode(self.sign(revocation.signing_payload().as_bytes()).to_bytes());
+        revocation
+    }
+
+    /// Withdraw an attestation this identity issued
+    pub fn revoke_attestation(&self, attestation: &Attestation) -> anyhow::Result<AttestationRevocation> {
+        if attestation.issuer != self.identity_hash {
+            return Err(anyhow::anyhow!("Only the issuer can revoke an attestation"));
+        }
+        Ok(self.sign_revocation(attestation.id()))
+    }
+
+    /// Withdraw a credential this identity issued (listed by `Credential::id()`)
+    pub fn revoke_credential(&self, credential: &Credential) -> anyhow::Result<AttestationRevocation> {
+        if credential.issuer != self.identity_hash {
+            return Err(anyhow::anyhow!("Only the issuer can revoke a credential"));
+        }
+        Ok(self.sign_revocation(credential.id()))
+    }
+
+    fn sign_revocation(&self, attestation_id: String) -> AttestationRevocation {
+        let mut revocation = AttestationRevocation {
+            issuer: self.identity_hash.clone(),
+            attestation_id,
+            revoked_at: now_secs(),
+            signature: String::new(),
+        };
+        revocation.signature = hex::encode(self.sign(revocation.signing_payload().as_bytes()).to_bytes());
+        revocation
+    }
+
     /// Sign a message with this identity
     pub fn sign(&self, message: &[u8]) -> Signature {
         use ed25519_dalek::Signer;
@@ -105,44 +536,526 @@ impl IdentityManager {
         pubkey.verify(message, signature).is_ok()
     }
 
-    /// Add contact (trust another identity)
-    pub fn add_contact(&mut self, identity_hash: String, pubkey: PublicKey) {
-        self.contacts.insert(identity_hash, pubkey);
+    /// Add contact (trust another identity). Returns a warning if this replaces a
+    /// different key for a known contact; the contact is then marked `Changed`.
+    pub fn add_contact(&mut self, identity_hash: String, pubkey: PublicKey) -> Option<KeyChangeWarning> {
+        let warning = match self.contacts.get(&identity_hash) {
+            Some(existing) if existing.public_key == pubkey => return None,
+            Some(existing) => Some(KeyChangeWarning {
+                identity_hash: identity_hash.clone(),
+                previous_key: existing.public_key,
+                new_key: pubkey,
+                was_verified: existing.verification == VerificationState::Verified,
+            }),
+            None => None,
+        };
+        let verification = if warning.is_some() {
+            VerificationState::Changed
+        } else {
+            VerificationState::Unverified
+        };
+        self.contacts.insert(identity_hash, Contact {
+            public_key: pubkey,
+            rotation_sequence: 0,
+            chain_broken: false,
+            verification,
+        });
+        warning
+    }
+
+    /// Signed card for sharing this identity (as bytes, a link or a QR code)
+    pub fn create_contact_card(
+        &self,
+        handle: Option<&str>,
+        prekeys: Option<&str>,
+        expires_at: Option<u64>,
+    ) -> anyhow::Result<ContactCard> {
+        let mut card = ContactCard {
+            identity_hash: self.identity_hash.clone(),
+            public_key: hex::encode(self.keypair.public.as_bytes()),
+            handle: handle.map(normalize_handle).transpose()?,
+            prekeys: prekeys.map(str::to_string),
+            issued_at: now_secs(),
+            expires_at,
+            rotations: self.rotations.clone(),
+            signature: String::new(),
+        };
+        card.signature = hex::encode(self.sign(card.signing_payload().as_bytes()).to_bytes());
+        Ok(card)
+    }
+
+    /// Verify a contact card and add its identity as a contact.
+    /// A known contact is only updated when the card extends the rotation chain from
+    /// the trusted key; older cards and broken chains are rejected (the latter also
+    /// marks the contact's chain as broken). Returns whether the contact's key changed.
+    pub fn import_contact_card(&mut self, card: &ContactCard) -> anyhow::Result<bool> {
+        let key = card.verify()?;
+        let sequence = card.rotations.last().map_or(0, |r| r.sequence);
+        match self.contacts.get(&card.identity_hash) {
+            None => {
+                self.contacts.insert(card.identity_hash.clone(), Contact {
+                    public_key: key,
+                    rotation_sequence: sequence,
+                    chain_broken: false,
+                    verification: VerificationState::Unverified,
+                });
+                Ok(true)
+            }
+            Some(contact) if sequence < contact.rotation_sequence => {
+                Err(anyhow::anyhow!("Contact card predates the contact's current key"))
+            }
+            Some(_) => self.follow_contact_rotations(&card.identity_hash, &card.rotations),
+        }
+    }
+
+    /// Safety number for this identity and a contact, to compare out of band
+    pub fn safety_number(&self, identity_hash: &str) -> Option<SafetyNumber> {
+        let contact = self.contacts.get(identity_hash)?;
+        Some(SafetyNumber::compute(
+            &self.identity_hash,
+            &self.keypair.public,
+            identity_hash,
+            &contact.public_key,
+        ))
+    }
+
+    /// Record that the user compared safety numbers with a contact and they matched
+    pub fn mark_contact_verified(&mut self, identity_hash: &str) -> anyhow::Result<()> {
+        let contact = self
+            .contacts
+            .get_mut(identity_hash)
+            .ok_or_else(|| anyhow::anyhow!("Unknown contact"))?;
+        contact.verification = VerificationState::Verified;
+        Ok(())
+    }
+
+    /// Verify a contact by scanning their safety number QR code
+    pub fn verify_contact_qr(&mut self, identity_hash: &str, scanned: &str) -> anyhow::Result<()> {
+        let safety_number = self
+            .safety_number(identity_hash)
+            .ok_or_else(|| anyhow::anyhow!("Unknown contact"))?;
+        if !safety_number.matches_qr(scanned)? {
+            return Err(anyhow::anyhow!("Safety numbers do not match"));
+        }
+        self.mark_contact_verified(identity_hash)
     }
 
     /// Get contact's public key
     pub fn get_contact(&self, identity_hash: &str) -> Option<&PublicKey> {
+        self.contacts.get(identity_hash).map(|contact| &contact.public_key)
+    }
+
+    /// Get contact with its rotation state
+    pub fn get_contact_record(&self, identity_hash: &str) -> Option<&Contact> {
         self.contacts.get(identity_hash)
     }
 
-    /// Create attestation (claim about this identity)
-    pub fn create_attestation(&self, claim: &str, value: &str) -> Attestation {
-        let mut hasher = Sha256::new();
-        hasher.update(value.as_bytes());
-        let value_hash = hex::encode(hasher.finalize());
-        
-        let message = format!("{}:{}:{}", claim, value_hash, self.identity_hash);
-        let signature = self.sign(message.as_bytes());
-        
-        Attestation {
+    /// Follow a contact's published rotations from the key we already trust.
+    /// Returns true if the contact's key changed. On a broken chain the old key
+    /// is kept and the contact is flagged. Verification state carries over, since
+    /// each new key is endorsed by the one that was verified.
+    pub fn follow_contact_rotations(&mut self, identity_hash: &str, rotations: &[RotationCertificate]) -> anyhow::Result<bool> {
+        let contact = self
+            .contacts
+            .get_mut(identity_hash)
+            .ok_or_else(|| anyhow::anyhow!("Unknown contact"))?;
+        match follow_rotations(identity_hash, &contact.public_key, rotations) {
+            Ok(current) => {
+                let changed = current != contact.public_key;
+                contact.public_key = current;
+                contact.rotation_sequence = rotations.last().map_or(0, |r| r.sequence);
+                contact.chain_broken = false;
+                Ok(changed)
+            }
+            Err(e) => {
+                contact.chain_broken = true;
+                Err(e)
+            }
+        }
+    }
+
+    /// Replace this identity's key with a random one. The old key signs a
+    /// certificate endorsing the new one; the identity_hash stays bound to the
+    /// original key. A random key cannot be re-derived from a recovery phrase, so
+    /// phrase-backed identities should use `rotate_key_from_mnemonic` instead.
+    pub fn rotate_key(&mut self) -> RotationCertificate {
+        let mut csprng = OsRng;
+        self.rotate_to(Keypair::generate(&mut csprng))
+    }
+
+    /// Rotate to the key at `m/3'/{sequence}'` under the recovery phrase's master
+    /// key, so `from_mnemonic_rotated` can still restore the identity
+    pub fn rotate_key_from_mnemonic(&mut self, phrase: &str, passphrase: &str) -> anyhow::Result<RotationCertificate> {
+        let master = ExtendedKey::from_seed(&mnemonic::mnemonic_to_seed(phrase, passphrase)?);
+        if Self::compute_identity_hash(master.keypair().public.as_bytes()) != self.identity_hash {
+            return Err(anyhow::anyhow!("Recovery phrase does not belong to this identity"));
+        }
+        let path = DerivationPath::for_rotation(self.rotations.len() as u64 + 1)?;
+        Ok(self.rotate_to(master.derive_path(&path).keypair()))
+    }
+
+    fn rotate_to(&mut self, new_keypair: Keypair) -> RotationCertificate {
+        let mut rotation = RotationCertificate {
+            identity_hash: self.identity_hash.clone(),
+            sequence: self.rotations.len() as u64 + 1,
+            previous_key: hex::encode(self.keypair.public.as_bytes()),
+            new_key: hex::encode(new_keypair.public.as_bytes()),
+            timestamp: now_secs(),
+            signature: String::new(),
+        };
+        rotation.signature = hex::encode(self.sign(rotation.signing_payload().as_bytes()).to_bytes());
+
+        self.keypair = new_keypair;
+        self.rotations.push(rotation.clone());
+        rotation
+    }
+
+    pub fn get_rotations(&self) -> &[RotationCertificate] {
+        &self.rotations
+    }
+
+    /// Create attestation (claim about this identity). Keep the reveal to disclose the value later.
+    pub fn create_attestation(&self, claim: &str, value: &str) -> (Attestation, ClaimReveal) {
+        self.attest(&self.identity_hash, claim, value, None)
+    }
+
+    /// Issue an attestation about another identity (e.g. an org vouching "employee").
+    /// The value is committed under a fresh random salt; hand the reveal to the subject.
+    pub fn attest(&self, subject: &str, claim: &str, value: &str, expires_at: Option<u64>) -> (Attestation, ClaimReveal) {
+        let mut salt = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut OsRng, &mut salt);
+        let mut attestation = Attestation {
             issuer: self.identity_hash.clone(),
+            subject: subject.to_string(),
             claim: claim.to_string(),
-            value_hash,
+            value_hash: commit_claim_value(claim, value, &salt),
+            signature: String::new(),
+            timestamp: now_secs(),
+            expires_at,
+        };
+        attestation.signature = hex::encode(self.sign(attestation.signing_payload().as_bytes()).to_bytes());
+        let reveal = ClaimReveal {
+            attestation_id: attestation.id(),
+            claim: claim.to_string(),
+            value: value.to_string(),
+            salt: hex::encode(salt),
+        };
+        (attestation, reveal)
+    }
+
+    /// Claim a handle such as "@alice" (publish with `/handles`)
+    pub fn claim_handle(&self, handle: &str) -> anyhow::Result<HandleClaim> {
+        let handle = normalize_handle(handle)?;
+        let (attestation, reveal) = self.create_attestation(HANDLE_CLAIM, &handle);
+        Ok(HandleClaim { handle, attestation, reveal })
+    }
+
+    /// Hand a handle this identity owns to another identity. `sequence` is the
+    /// number of transfers in the handle's current record (`GET /handles/{handle}`).
+    pub fn transfer_handle(&self, handle: &str, to_identity: &str, sequence: u64) -> anyhow::Result<HandleTransfer> {
+        let mut transfer = HandleTransfer {
+            handle: normalize_handle(handle)?,
+            from_identity: self.identity_hash.clone(),
+            to_identity: to_identity.to_string(),
+            sequence,
+            timestamp: now_secs(),
+            signature: String::new(),
+        };
+        transfer.signature = hex::encode(self.sign(transfer.signing_payload().as_bytes()).to_bytes());
+        Ok(transfer)
+    }
+
+    /// Issue one credential covering several claims about `subject` (one signature).
+    /// Hand the result to the subject; it holds every claim's salt.
+    pub fn issue_credential(&self, subject: &str, claims: &[(&str, &str)], expires_at: Option<u64>) -> anyhow::Result<HeldCredential> {
+        let claims: Vec<CredentialClaim> = claims
+            .iter()
+            .map(|(claim, value)| {
+                let mut salt = [0u8; 32];
+                rand::RngCore::fill_bytes(&mut OsRng, &mut salt);
+                CredentialClaim { claim: claim.to_string(), value: value.to_string(), salt: hex::encode(salt) }
+            })
+            .collect();
+        let leaves = claims.iter().map(|c| c.leaf()).collect::<anyhow::Result<Vec<_>>>()?;
+        let mut credential = Credential {
+            issuer: self.identity_hash.clone(),
+            subject: subject.to_string(),
+            claims_root: merkle_root(&leaves),
+            claim_count: claims.len(),
+            timestamp: now_secs(),
+            expires_at,
+            signature: String::new(),
+        };
+        credential.signature = hex::encode(self.sign(credential.signing_payload().as_bytes()).to_bytes());
+        Ok(HeldCredential { credential, claims })
+    }
+
+    /// Start a group with some contacts (this identity is added as owner) and seal
+    /// the first group key to everyone. Post the result to `/groups`.
+    pub fn create_group(&self, members: &[&str]) -> anyhow::Result<GroupUpdate> {
+        let mut members: Vec<String> = members.iter().map(|m| m.to_string()).collect();
+        members.push(self.identity_hash.clone());
+        let event = self.sign_group_event(new_group_id(), 0, String::new(), MembershipAction::Create { members });
+        let state = GroupState::genesis(&event)?;
+        let keys = self.seal_group_key(&state)?;
+        Ok(GroupUpdate { event, keys })
+    }
+
+    /// Next membership change for a group (add, remove, leave or rekey). Unless this
+    /// identity is leaving, a fresh key is sealed to every remaining member, all of
+    /// whom must be contacts. Post the result to `/groups/{group_id}/events`.
+    pub fn update_group(&self, state: &GroupState, action: MembershipAction) -> anyhow::Result<GroupUpdate> {
+        let event = self.sign_group_event(state.group_id.clone(), state.epoch + 1, state.head.clone(), action);
+        let mut next = state.clone();
+        next.apply(&event)?;
+        let mut update = GroupUpdate { event, keys: Vec::new() };
+        if !update.is_leave() {
+            update.keys = self.seal_group_key(&next)?;
+        }
+        Ok(update)
+    }
+
+    /// Open an epoch key sealed to this identity (the sender must be a contact, or self)
+    pub fn open_group_key(&self, sealed: &SealedGroupKey) -> anyhow::Result<[u8; 32]> {
+        if sealed.recipient != self.identity_hash {
+            return Err(anyhow::anyhow!("Group key is sealed to another identity"));
+        }
+        let sender_key = self.member_key(&sealed.sender)?;
+        sealed.open(&self.derive_thread_key(&sealed.group_id, &sender_key)?)
+    }
+
+    fn sign_group_event(&self, group_id: String, sequence: u64, previous: String, action: MembershipAction) -> MembershipEvent {
+        let mut event = MembershipEvent {
+            group_id,
+            sequence,
+            previous,
+            actor: self.identity_hash.clone(),
+            action,
+            timestamp: now_secs(),
+            signature: String::new(),
+        };
+        event.signature = hex::encode(self.sign(&event.signing_payload()).to_bytes());
+        event
+    }
+
+    /// Fresh key for `state.epoch`, sealed to each member under the pairwise key
+    fn seal_group_key(&self, state: &GroupState) -> anyhow::Result<Vec<SealedGroupKey>> {
+        let mut group_key = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut OsRng, &mut group_key);
+        state
+            .members
+            .iter()
+            .map(|member| {
+                let pairwise = self.derive_thread_key(&state.group_id, &self.member_key(member)?)?;
+                SealedGroupKey::seal(&pairwise, &state.group_id, state.epoch, &self.identity_hash, member, &group_key)
+            })
+            .collect()
+    }
+
+    /// Current key of a group member: a contact, or this identity itself
+    fn member_key(&self, identity_hash: &str) -> anyhow::Result<PublicKey> {
+        if identity_hash == self.identity_hash {
+            return Ok(self.keypair.public);
+        }
+        self.get_contact(identity_hash)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Group member {} is not a contact", identity_hash))
+    }
+
+    /// Build a proof-of-possession registration for this identity's public key
+    pub fn registration_request(&self) -> RegistrationRequest {
+        let public_key = hex::encode(self.keypair.public.as_bytes());
+        let signature = self.sign(registration_payload(&self.identity_hash, &public_key).as_bytes());
+        RegistrationRequest {
+            identity_hash: self.identity_hash.clone(),
+            public_key,
             signature: hex::encode(signature.to_bytes()),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
         }
     }
 
-    /// Export identity (for storage/backup)
+    /// Keep an attestation with this identity (included in exports and keystores)
+    pub fn add_attestation(&mut self, attestation: Attestation) {
+        self.attestations.push(attestation);
+    }
+
+    pub fn get_attestations(&self) -> &[Attestation] {
+        &self.attestations
+    }
+
+    /// Keep the opening for a held attestation so its value can be disclosed later
+    pub fn add_claim_reveal(&mut self, reveal: ClaimReveal) -> anyhow::Result<()> {
+        if !self.attestations.iter().any(|a| a.opens(&reveal)) {
+            return Err(anyhow::anyhow!("Reveal does not open any held attestation"));
+        }
+        self.claim_reveals.retain(|r| r.attestation_id != reveal.attestation_id);
+        self.claim_reveals.push(reveal);
+        Ok(())
+    }
+
+    /// Keep a credential issued to this identity (checked against its signed root)
+    pub fn add_credential(&mut self, held: HeldCredential) -> anyhow::Result<()> {
+        if held.credential.subject != self.identity_hash {
+            return Err(anyhow::anyhow!("Credential was issued to a different identity"));
+        }
+        held.check_claims()?;
+        self.credentials.push(held);
+        Ok(())
+    }
+
+    pub fn get_credentials(&self) -> &[HeldCredential] {
+        &self.credentials
+    }
+
+    /// Selectively disclose one claim: the attestation plus its value and salt
+    pub fn reveal_claim(&self, claim: &str) -> Option<(&Attestation, &ClaimReveal)> {
+        self.claim_reveals.iter().filter(|r| r.claim == claim).find_map(|reveal| {
+            self.attestations
+                .iter()
+                .find(|a| a.id() == reveal.attestation_id)
+                .map(|attestation| (attestation, reveal))
+        })
+    }
+
+    /// Export public identity (for sharing)
     pub fn export(&self) -> Identity {
         Identity {
             public_key: self.keypair.public.to_bytes().to_vec(),
             identity_hash: self.identity_hash.clone(),
-            attestations: vec![],
+            attestations: self.attestations.clone(),
+            rotations: self.rotations.clone(),
+            devices: vec![],
+            device_revocations: vec![],
+            attestation_revocations: vec![],
         }
     }
+
+    /// Export the full identity (secret key, contacts, attestations) encrypted under a passphrase
+    pub fn export_keystore(&self, passphrase: &str) -> anyhow::Result<Keystore> {
+        let contents = KeystoreContents {
+            secret_key: hex::encode(self.keypair.secret.as_bytes()),
+            chain_code: Some(hex::encode(self.chain_code)),
+            contacts: self
+                .contacts
+                .iter()
+                .map(|(hash, contact)| ContactRecord {
+                    identity_hash: hash.clone(),
+                    public_key: hex::encode(contact.public_key.as_bytes()),
+                    verification: contact.verification,
+                    rotation_sequence: contact.rotation_sequence,
+                    chain_broken: contact.chain_broken,
+                })
+                .collect(),
+            attestations: self.attestations.clone(),
+            claim_reveals: self.claim_reveals.clone(),
+            credentials: self.credentials.clone(),
+            rotations: self.rotations.clone(),
+            prekeys: Some(self.prekeys.clone()),
+        };
+        Keystore::seal(&self.identity_hash, &contents, passphrase)
+    }
+
+    /// Split this identity's secret among guardian contacts; any `threshold` of them
+    /// can later release their shares to restore it. Each share is sealed to the
+    /// guardian's current key.
+    pub fn create_recovery_shares(&self, threshold: u8, guardians: &[&str]) -> anyhow::Result<Vec<SealedShare>> {
+        let share_count = u8::try_from(guardians.len()).map_err(|_| anyhow::anyhow!("Too many guardians"))?;
+        let mut secret = self.keypair.secret.to_bytes().to_vec();
+        secret.extend_from_slice(&self.chain_code);
+        split_secret(&secret, threshold, share_count)?
+            .into_iter()
+            .zip(guardians)
+            .map(|((index, share), guardian)| {
+                let key = self
+                    .get_contact(guardian)
+                    .ok_or_else(|| anyhow::anyhow!("Guardian {} is not a contact", guardian))?;
+                SealedShare::seal(&self.identity_hash, guardian, index, threshold, &share, &ed25519_public_to_x25519(key)?)
+            })
+            .collect()
+    }
+
+    /// As a guardian: open a share held for someone and re-seal it to their recovering device
+    pub fn release_recovery_share(&self, share: &SealedShare, recovery_key: &[u8; 32]) -> anyhow::Result<SealedShare> {
+        if share.holder != self.identity_hash {
+            return Err(anyhow::anyhow!("Share is not held by this identity"));
+        }
+        let plaintext = share.open(&X25519Secret::from_ed25519(&self.keypair.secret))?;
+        SealedShare::seal(&share.identity_hash, RECOVERY_HOLDER, share.index, share.threshold, &plaintext, recovery_key)
+    }
+
+    /// Restore an identity from shares released to `kit`. Pass the identity's
+    /// published rotation chain if its key was ever rotated.
+    pub fn recover(kit: &RecoveryKit, released: &[SealedShare], rotations: Vec<RotationCertificate>) -> anyhow::Result<Self> {
+        let (identity_hash, secret) = kit.combine(released)?;
+        let mut identity = Self::from_secret_bytes(&secret[..32])?;
+        identity.chain_code.copy_from_slice(&secret[32..]);
+        identity.adopt_identity_hash(&identity_hash, rotations)?;
+        Ok(identity)
+    }
+
+    /// Take on an identity_hash bound to an earlier key through a rotation chain
+    fn adopt_identity_hash(&mut self, identity_hash: &str, rotations: Vec<RotationCertificate>) -> anyhow::Result<()> {
+        if !rotations.is_empty() {
+            let original = parse_public_key(&rotations[0].previous_key)?;
+            let current = verify_rotation_chain(identity_hash, &original, &rotations)?;
+            if current != self.keypair.public {
+                return Err(anyhow::anyhow!("Rotation chain does not end at the recovered secret key"));
+            }
+            self.identity_hash = identity_hash.to_string();
+            self.rotations = rotations;
+        }
+        if self.identity_hash != identity_hash {
+            return Err(anyhow::anyhow!("Identity hash does not match the secret key"));
+        }
+        Ok(())
+    }
+
+    /// Restore an identity from a keystore created by `export_keystore`
+    pub fn import(keystore: &Keystore, passphrase: &str) -> anyhow::Result<Self> {
+        let contents = keystore.open(passphrase)?;
+        let mut identity = Self::from_secret_bytes(&hex::decode(&contents.secret_key)?)?;
+        if let Some(chain_code) = contents.chain_code {
+            identity.chain_code = hex::decode(chain_code)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Chain code must be 32 bytes"))?;
+        }
+        identity.adopt_identity_hash(&keystore.identity_hash, contents.rotations)?;
+
+        for record in contents.contacts {
+            identity.contacts.insert(record.identity_hash, Contact {
+                public_key: parse_public_key(&record.public_key)?,
+                rotation_sequence: record.rotation_sequence,
+                chain_broken: record.chain_broken,
+                verification: record.verification,
+            });
+        }
+        if let Some(prekeys) = contents.prekeys {
+            identity.prekeys = prekeys;
+        }
+        identity.attestations = contents.attestations;
+        identity.claim_reveals = contents.claim_reveals;
+        identity.credentials = contents.credentials;
+        Ok(identity)
+    }
 }
 
+impl IdentitySigner for IdentityManager {
+    fn identity_hash(&self) -> &str {
+        &self.identity_hash
+    }
+
+    fn sign(&self, message: &[u8]) -> Signature {
+        IdentityManager::sign(self, message)
+    }
+}
+
+/// Parse a hex-encoded ED25519 public key
+pub fn parse_public_key(hex_key: &str) -> anyhow::Result<PublicKey> {
+    let bytes = hex::decode(hex_key)?;
+    PublicKey::from_bytes(&bytes).map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))
+}
+
+/// Parse a hex-encoded ED25519 signature
+pub fn parse_signature(hex_sig: &str) -> anyhow::Result<Signature> {
+    let bytes = hex::decode(hex_sig)?;
+    Signature::from_bytes(&bytes).map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))
+}
@@ -0,0 +1,145 @@
+// X25519 key agreement between identities
+// Converts ED25519 identity keys to X25519 and derives per-thread keys with HKDF
+
+use curve25519_dalek::{
+    constants::X25519_BASEPOINT,
+    edwards::CompressedEdwardsY,
+    montgomery::MontgomeryPoint,
+    scalar::Scalar,
+};
+use ed25519_dalek::{PublicKey, SecretKey};
+use hkdf::Hkdf;
+use rand::RngCore;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256, Sha512};
+
+/// X25519 static or ephemeral secret
+#[derive(Clone)]
+pub struct X25519Secret(Scalar);
+
+impl std::fmt::Debug for X25519Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("X25519Secret(..)")
+    }
+}
+
+impl X25519Secret {
+    /// Generate a fresh random secret
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        Self::from_bytes(bytes)
+    }
+
+    /// Build a secret from 32 raw bytes (clamped per RFC 7748)
+    pub fn from_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes[0] &= 248;
+        bytes[31] &= 127;
+        bytes[31] |= 64;
+        Self(Scalar::from_bits(bytes))
+    }
+
+    /// Convert an ED25519 secret key to its X25519 counterpart
+    /// (the same scalar ED25519 uses for signing)
+    pub fn from_ed25519(secret: &SecretKey) -> Self {
+        let hash = Sha512::digest(secret.as_bytes());
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hash[..32]);
+        Self::from_bytes(bytes)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        (X25519_BASEPOINT * self.0).to_bytes()
+    }
+
+    /// Raw X25519 Diffie-Hellman. Fails on low-order peer keys.
+    pub fn diffie_hellman(&self, peer_public: &[u8; 32]) -> anyhow::Result<[u8; 32]> {
+        let shared = (MontgomeryPoint(*peer_public) * self.0).to_bytes();
+        if shared == [0u8; 32] {
+            return Err(anyhow::anyhow!("Key agreement produced a low-order point"));
+        }
+        Ok(shared)
+    }
+}
+
+/// Hex encoding, for encrypted stores such as the keystore only
+impl Serialize for X25519Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.to_bytes()))
+    }
+}
+
+impl<'de> Deserialize<'de> for X25519Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes: [u8; 32] = hex::decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)?
+            .try_into()
+            .map_err(|_| serde::de::Error::custom("X25519 secret must be 32 bytes"))?;
+        Ok(Self::from_bytes(bytes))
+    }
+}
+
+/// Convert an ED25519 public key to its X25519 (Montgomery) form
+pub fn ed25519_public_to_x25519(pubkey: &PublicKey) -> anyhow::Result<[u8; 32]> {
+    CompressedEdwardsY(pubkey.to_bytes())
+        .decompress()
+        .map(|point| point.to_montgomery().to_bytes())
+        .ok_or_else(|| anyhow::anyhow!("Invalid ED25519 public key"))
+}
+
+/// HKDF-SHA256 expansion of key agreement output into a 32-byte key
+pub fn hkdf_expand(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut okm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(salt), ikm)
+        .expand(info, &mut okm)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    okm
+}
+
+/// Derive the symmetric key for a thread from a DH shared secret.
+/// The thread_id is bound as HKDF context so every thread gets its own key.
+pub fn derive_thread_key(shared_secret: &[u8; 32], thread_id: &str) -> [u8; 32] {
+    hkdf_expand(b"zerotrace_thread_key_v1", shared_secret, thread_id.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Keypair;
+
+    fn keypair() -> Keypair {
+        Keypair::generate(&mut rand::rngs::OsRng)
+    }
+
+    #[test]
+    fn converted_identity_keys_agree() {
+        let (alice, bob) = (keypair(), keypair());
+        let alice_x = X25519Secret::from_ed25519(&alice.secret);
+        let bob_x = X25519Secret::from_ed25519(&bob.secret);
+
+        assert_eq!(alice_x.public_key(), ed25519_public_to_x25519(&alice.public).unwrap());
+        let ab = alice_x.diffie_hellman(&ed25519_public_to_x25519(&bob.public).unwrap()).unwrap();
+        let ba = bob_x.diffie_hellman(&ed25519_public_to_x25519(&alice.public).unwrap()).unwrap();
+        assert_eq!(ab, ba);
+    }
+
+    #[test]
+    fn thread_keys_differ_per_thread() {
+        let shared = [7u8; 32];
+        assert_eq!(derive_thread_key(&shared, "a:b"), derive_thread_key(&shared, "a:b"));
+        assert_ne!(derive_thread_key(&shared, "a:b"), derive_thread_key(&shared, "a:c"));
+    }
+
+    #[test]
+    fn rejects_low_order_peer_keys() {
+        let secret = X25519Secret::generate();
+        assert!(secret.diffie_hellman(&[0u8; 32]).is_err());
+        let mut one = [0u8; 32];
+        one[0] = 1;
+        assert!(secret.diffie_hellman(&one).is_err());
+    }
+}
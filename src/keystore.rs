@@ -0,0 +1,221 @@
+// Passphrase-encrypted identity keystore
+// Argon2id key derivation + XChaCha20-Poly1305, for backup and device transfer
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::attestations::ClaimReveal;
+use crate::credentials::HeldCredential;
+use crate::identity::{Attestation, VerificationState};
+use crate::prekeys::PrekeySecrets;
+use crate::rotation::RotationCertificate;
+
+/// Current keystore format version
+pub const KEYSTORE_VERSION: u32 = 1;
+
+/// Upper bounds on Argon2id costs accepted from a keystore file, so a crafted
+/// keystore cannot make import allocate or spin without limit
+pub const MAX_M_COST: u32 = 256 * 1024;  // 256 MiB
+pub const MAX_T_COST: u32 = 16;
+pub const MAX_P_COST: u32 = 8;
+
+/// Argon2id parameters used to derive the wrapping key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,         // "argon2id"
+    pub salt: String,              // hex, 16 bytes
+    pub m_cost: u32,               // memory in KiB
+    pub t_cost: u32,               // iterations
+    pub p_cost: u32,               // parallelism
+}
+
+/// Encrypted identity backup. Only `version`, `identity_hash` and the KDF
+/// parameters are readable without the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub identity_hash: String,
+    pub kdf: KdfParams,
+    pub nonce: String,             // base64 XChaCha20 nonce
+    pub ciphertext: String,        // base64 encrypted `KeystoreContents`
+}
+
+/// A trusted contact as saved in a keystore (see `identity::Contact`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContactRecord {
+    pub identity_hash: String,
+    pub public_key: String,        // hex ED25519 public key
+    #[serde(default)]
+    pub verification: VerificationState,
+    #[serde(default)]
+    pub rotation_sequence: u64,
+    #[serde(default)]
+    pub chain_broken: bool,
+}
+
+/// Plaintext contents of a keystore
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeystoreContents {
+    pub secret_key: String,                // hex ED25519 secret key
+    #[serde(default)]
+    pub chain_code: Option<String>,        // hex SLIP-10 chain code
+    pub contacts: Vec<ContactRecord>,
+    pub attestations: Vec<Attestation>,
+    #[serde(default)]
+    pub claim_reveals: Vec<ClaimReveal>,
+    #[serde(default)]
+    pub credentials: Vec<HeldCredential>,
+    #[serde(default)]
+    pub rotations: Vec<RotationCertificate>,
+    #[serde(default)]
+    pub prekeys: Option<PrekeySecrets>,    // X3DH prekey secrets, so pending handshakes survive import
+}
+
+impl KdfParams {
+    /// Fresh parameters with a random salt and OWASP-recommended Argon2id costs
+    fn generate() -> Self {
+        let mut salt = [0u8; 16];
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        Self {
+            algorithm: "argon2id".to_string(),
+            salt: hex::encode(salt),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> anyhow::Result<[u8; 32]> {
+        if self.algorithm != "argon2id" {
+            return Err(anyhow::anyhow!("Unsupported keystore KDF: {}", self.algorithm));
+        }
+        if self.m_cost > MAX_M_COST || self.t_cost > MAX_T_COST || self.p_cost > MAX_P_COST {
+            return Err(anyhow::anyhow!("Keystore KDF costs exceed the supported maximum"));
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| anyhow::anyhow!("Invalid KDF parameters: {}", e))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &hex::decode(&self.salt)?, &mut key)
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(key)
+    }
+}
+
+/// Associated data binding the readable header to the ciphertext
+fn header_aad(version: u32, identity_hash: &str) -> String {
+    format!("zerotrace_keystore_v{}:{}", version, identity_hash)
+}
+
+impl Keystore {
+    /// Encrypt `contents` under a key derived from `passphrase`
+    pub fn seal(identity_hash: &str, contents: &KeystoreContents, passphrase: &str) -> anyhow::Result<Self> {
+        let kdf = KdfParams::generate();
+        let cipher = XChaCha20Poly1305::new(&kdf.derive_key(passphrase)?.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = header_aad(KEYSTORE_VERSION, identity_hash);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: &serde_json::to_vec(contents)?, aad: aad.as_bytes() })
+            .map_err(|e| anyhow::anyhow!("Keystore encryption failed: {}", e))?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            identity_hash: identity_hash.to_string(),
+            kdf,
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        })
+    }
+
+    /// Decrypt the keystore. Fails on a wrong passphrase or any tampering.
+    pub fn open(&self, passphrase: &str) -> anyhow::Result<KeystoreContents> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(anyhow::anyhow!("Unsupported keystore version {}", self.version));
+        }
+        let nonce_bytes = general_purpose::STANDARD.decode(&self.nonce)?;
+        if nonce_bytes.len() != 24 {
+            return Err(anyhow::anyhow!("Keystore nonce must be 24 bytes"));
+        }
+        let ciphertext = general_purpose::STANDARD.decode(&self.ciphertext)?;
+        let cipher = XChaCha20Poly1305::new(&self.kdf.derive_key(passphrase)?.into());
+        let aad = header_aad(self.version, &self.identity_hash);
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce_bytes), Payload { msg: &ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("Wrong passphrase or corrupted keystore"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+    use crate::prekeys::PrekeyStore;
+
+    #[test]
+    fn import_restores_contacts_and_prekeys() {
+        let mut alice = IdentityManager::new();
+        let mut bob = IdentityManager::new();
+        let mut carol = IdentityManager::new();
+        let carol_hash = carol.get_identity_hash().to_string();
+        let original_key = carol.public_key();
+        alice.add_contact(carol_hash.clone(), original_key);
+        let rotation = carol.rotate_key();
+        assert!(alice.follow_contact_rotations(&carol_hash, std::slice::from_ref(&rotation)).unwrap());
+        alice.mark_contact_verified(&carol_hash).unwrap();
+
+        // Carol's rotation does not chain from Dave's key
+        let dave = IdentityManager::new();
+        let dave_hash = dave.get_identity_hash().to_string();
+        alice.add_contact(dave_hash.clone(), dave.public_key());
+        assert!(alice.follow_contact_rotations(&dave_hash, &[rotation]).is_err());
+
+        let mut store = PrekeyStore::new();
+        let bob_key = bob.public_key();
+        store.upload(bob.generate_prekeys(1), &bob_key).unwrap();
+        let (init, secret) = alice
+            .x3dh_initiate(&store.claim_bundle(bob.get_identity_hash(), "alice").unwrap())
+            .unwrap();
+
+        let alice = IdentityManager::import(&alice.export_keystore("pw").unwrap(), "pw").unwrap();
+        let contact = alice.get_contact_record(&carol_hash).unwrap();
+        assert_eq!(contact.public_key, carol.public_key());
+        assert_eq!(contact.rotation_sequence, 1);
+        assert!(!contact.chain_broken);
+        assert_eq!(contact.verification, VerificationState::Verified);
+        let contact = alice.get_contact_record(&dave_hash).unwrap();
+        assert_eq!(contact.public_key, dave.public_key());
+        assert!(contact.chain_broken);
+        assert_eq!(contact.verification, VerificationState::Unverified);
+
+        let mut bob = IdentityManager::import(&bob.export_keystore("pw").unwrap(), "pw").unwrap();
+        assert_eq!(bob.x3dh_respond(&init).unwrap(), secret);
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase_and_tampering() {
+        let alice = IdentityManager::new();
+        let keystore = alice.export_keystore("correct horse").unwrap();
+        assert!(keystore.open("wrong").is_err());
+
+        let mut tampered = keystore.clone();
+        tampered.identity_hash = IdentityManager::new().get_identity_hash().to_string();
+        assert!(tampered.open("correct horse").is_err());
+    }
+
+    #[test]
+    fn rejects_excessive_kdf_costs() {
+        let mut keystore = IdentityManager::new().export_keystore("pw").unwrap();
+        keystore.kdf.m_cost = u32::MAX;
+        assert!(keystore.open("pw").is_err());
+        keystore.kdf.m_cost = Params::DEFAULT_M_COST;
+        keystore.kdf.t_cost = MAX_T_COST + 1;
+        assert!(keystore.open("pw").is_err());
+    }
+}
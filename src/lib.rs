@@ -1,14 +1,37 @@
 pub mod identity;
 pub mod commitments;
 pub mod proofs;
+pub mod keyexchange;
+pub mod ratchet;
+pub mod prekeys;
+pub mod keystore;
+pub mod mnemonic;
+pub mod derivation;
+pub mod rotation;
+pub mod devices;
+pub mod attestations;
+pub mod credentials;
+pub mod handles;
+pub mod safety;
+pub mod contact_card;
+pub mod recovery;
+pub mod groups;
+pub mod treekem;
+pub mod sessions;
 
 use chacha20poly1305::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     XChaCha20Poly1305, XNonce,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use proofs::EndCap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use base64::{Engine as _, engine::general_purpose};
+use commitments::{compute_message_commitment, hash_plaintext, StateCommitment};
+use ed25519_dalek::{PublicKey, Verifier};
+use sha2::{Digest, Sha256};
+use identity::{parse_signature, IdentitySigner};
+use proofs::{create_endcap, CFCProof, EndCap};
+use groups::GroupState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -19,20 +42,288 @@ pub struct Message {
     pub timestamp: u64,
     pub message_commitment: String,  // Poseidon commitment
     pub endcap: Option<EndCap>,      // ZK proof + submission data
+    #[serde(default)]
+    pub epoch: u64,                  // Group key epoch (0 for two-party threads)
+    #[serde(default)]
+    pub sequence: u64,               // Sender's VAA nonce, bound into the AEAD associated data
+    #[serde(default)]
+    pub version: u32,                // Wire protocol version the ciphertext was sealed under
 }
 
+impl Message {
+    /// Associated data the ciphertext was sealed with
+    pub fn aad(&self) -> MessageAad {
+        MessageAad {
+            version: self.version,
+            thread_id: self.thread_id.clone(),
+            sender_id: self.sender_id.clone(),
+            sequence: self.sequence,
+        }
+    }
+
+    /// Decrypt under `key`, failing if the thread, sender, sequence or version
+    /// differs from what the sender sealed
+    pub fn decrypt(&self, key: &[u8; 32]) -> anyhow::Result<String> {
+        let nonce = general_purpose::STANDARD.decode(&self.iv)?;
+        if nonce.len() != 24 {
+            return Err(anyhow::anyhow!("iv must be a 24-byte XChaCha20 nonce"));
+        }
+        let ciphertext = general_purpose::STANDARD.decode(&self.ciphertext)?;
+        decrypt_message_with_aad(key, &ciphertext, XNonce::from_slice(&nonce), &self.aad())
+    }
+}
+
+/// Wire protocol version bound into every message's associated data
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Context a message ciphertext is bound to. Moving a ciphertext to another
+/// thread, sender or sequence number makes decryption fail.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageAad {
+    pub version: u32,
+    pub thread_id: String,
+    pub sender_id: String,
+    pub sequence: u64,
+}
+
+impl MessageAad {
+    pub fn new(thread_id: &str, sender_id: &str, sequence: u64) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            thread_id: thread_id.to_string(),
+            sender_id: sender_id.to_string(),
+            sequence,
+        }
+    }
+
+    /// Unambiguous encoding: length-prefixed strings (thread ids may contain ':')
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"zerotrace_message_aad".to_vec();
+        out.extend_from_slice(&self.version.to_be_bytes());
+        put_field(&mut out, self.thread_id.as_bytes());
+        put_field(&mut out, self.sender_id.as_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out
+    }
+}
+
+/// Append a u32 length-prefixed field
+fn put_field(out: &mut Vec<u8>, field: &[u8]) {
+    out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    out.extend_from_slice(field);
+}
+
+/// Client-sealed message submission. The server only ever sees ciphertext;
+/// encryption, commitment and proof generation happen on the sender's machine.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendRequest {
     pub thread_id: String,
     pub recipient_id: String,        // Identity hash of recipient
-    pub plaintext: String,
     pub sender_identity_hash: String, // Sender's identity hash
-    pub sender_signature: String,     // Signature proving ownership
+    pub ciphertext: String,          // base64 encoded
+    pub iv: String,                  // base64 encoded nonce
+    pub message_commitment: String,  // Poseidon commitment
+    pub endcap: EndCap,              // ZK proof + submission data, signed by sender
+    #[serde(default)]
+    pub epoch: u64,                  // Group key epoch the message is encrypted under
+    #[serde(default)]
+    pub version: u32,                // Protocol version of the ciphertext's associated data
+    pub timestamp: u64,              // Client clock at signing time (seconds)
+    #[serde(default)]
+    pub device_key: Option<String>,  // hex device key that signed, None for the root key
+    pub sender_signature: String,     // Signature over `signing_payload()`
+}
+
+/// Sender's view of its CSTATE, as served by `GET /cstate/{identity_hash}`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CStateSnapshot {
+    pub cstate_root: String,
+    pub thread_roots: Vec<String>,
+    pub vaa_nonce: u64,              // Last VAA nonce accepted for this identity
+}
+
+impl SendRequest {
+    /// Encrypt `plaintext` locally and build the full submission
+    /// (commitment, CFC proof and signed EndCap) against `cstate`.
+    pub fn seal<S: IdentitySigner>(
+        sender: &S,
+        recipient_id: &str,
+        thread_id: &str,
+        key: &[u8; 32],
+        plaintext: &str,
+        cstate: &CStateSnapshot,
+    ) -> anyhow::Result<Self> {
+        Self::seal_at_epoch(sender, recipient_id, thread_id, 0, key, plaintext, cstate)
+    }
+
+    /// Same as `seal`, for a group thread under the key of the group's current epoch
+    pub fn seal_group<S: IdentitySigner>(
+        sender: &S,
+        group: &GroupState,
+        group_key: &[u8; 32],
+        plaintext: &str,
+        cstate: &CStateSnapshot,
+    ) -> anyhow::Result<Self> {
+        Self::seal_at_epoch(sender, &group.group_id, &group.group_id, group.epoch, group_key, plaintext, cstate)
+    }
+
+    /// Same as `seal`, under an explicit key epoch (group threads, see `treekem`)
+    pub fn seal_at_epoch<S: IdentitySigner>(
+        sender: &S,
+        recipient_id: &str,
+        thread_id: &str,
+        epoch: u64,
+        key: &[u8; 32],
+        plaintext: &str,
+        cstate: &CStateSnapshot,
+    ) -> anyhow::Result<Self> {
+        let sender_hash = sender.identity_hash();
+        let vaa_nonce = cstate.vaa_nonce + 1;
+        let aad = MessageAad::new(thread_id, sender_hash, vaa_nonce);
+        let (ciphertext, nonce) = encrypt_message_with_aad(key, plaintext, &aad)?;
+
+        let message_commitment = compute_message_commitment(
+            sender_hash,
+            thread_id,
+            nonce.as_slice(),
+            &hash_plaintext(plaintext),
+        );
+        let end_root = StateCommitment::new(
+            thread_id.to_string(),
+            message_commitment.clone(),
+            &cstate.thread_roots,
+        )
+        .cstate_root;
+        let proof = CFCProof::for_send_message(&cstate.cstate_root, &end_root, &message_commitment);
+
+        let signature = hex::encode(
+            sender
+                .sign(endcap_signing_payload(&message_commitment, vaa_nonce).as_bytes())
+                .to_bytes(),
+        );
+        let encrypted_blob_address = format!("da://encrypted/{}", uuid::Uuid::new_v4());
+        let endcap = create_endcap(proof, encrypted_blob_address, vaa_nonce, signature);
+
+        let mut request = Self {
+            thread_id: thread_id.to_string(),
+            recipient_id: recipient_id.to_string(),
+            sender_identity_hash: sender_hash.to_string(),
+            ciphertext: general_purpose::STANDARD.encode(&ciphertext),
+            iv: general_purpose::STANDARD.encode(nonce.as_slice()),
+            message_commitment,
+            endcap,
+            epoch,
+            version: aad.version,
+            timestamp: now_secs(),
+            device_key: sender.device_key().map(|key| hex::encode(key.as_bytes())),
+            sender_signature: String::new(),
+        };
+        request.sender_signature = hex::encode(sender.sign(&request.signing_payload()).to_bytes());
+        Ok(request)
+    }
+
+    /// Canonical encoding of the request covered by `sender_signature`.
+    /// Variable-length fields are length-prefixed, so ids containing ':' cannot
+    /// be shifted between fields.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let mut out = b"zerotrace_send_v2".to_vec();
+        put_field(&mut out, self.sender_identity_hash.as_bytes());
+        put_field(&mut out, self.thread_id.as_bytes());
+        put_field(&mut out, self.recipient_id.as_bytes());
+        out.extend_from_slice(&Sha256::digest(self.ciphertext.as_bytes()));
+        put_field(&mut out, self.iv.as_bytes());
+        out.extend_from_slice(&self.epoch.to_be_bytes());
+        out.extend_from_slice(&self.version.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.extend_from_slice(&self.endcap.vaa_nonce.to_be_bytes());
+        out
+    }
+
+    /// Check `sender_signature` and the EndCap signature against the signing key
+    /// (the sender's root key, or `device_key` once the caller has checked it is endorsed)
+    pub fn verify_signatures(&self, sender_pubkey: &PublicKey) -> bool {
+        let verify = |message: &[u8], hex_sig: &str| {
+            parse_signature(hex_sig)
+                .map(|sig| sender_pubkey.verify(message, &sig).is_ok())
+                .unwrap_or(false)
+        };
+        verify(&self.signing_payload(), &self.sender_signature)
+            && verify(
+                endcap_signing_payload(&self.message_commitment, self.endcap.vaa_nonce).as_bytes(),
+                &self.endcap.signature,
+            )
+    }
 }
 
+/// Current Unix time in seconds
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Canonical id of the two-party thread between `a` and `b` ("hash1:hash2", sorted)
+pub fn thread_id_for(a: &str, b: &str) -> String {
+    if a <= b {
+        format!("{}:{}", a, b)
+    } else {
+        format!("{}:{}", b, a)
+    }
+}
+
+/// Bytes signed by the sender over an EndCap
+pub fn endcap_signing_payload(message_commitment: &str, vaa_nonce: u64) -> String {
+    format!("{}:{}", message_commitment, vaa_nonce)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadKind {
+    Direct,                          // Two parties, id from `thread_id_for`
+    Group,                           // Members managed by `groups::GroupRegistry`
+}
+
+/// A conversation and who may read and post in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Thread {
+    pub id: String,
+    pub participants: Vec<String>,   // Sorted identity hashes
+    pub created_at: u64,
+    pub kind: ThreadKind,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Thread {
+    /// Two-party thread between `a` and `b`
+    pub fn direct(a: &str, b: &str, created_at: u64) -> Self {
+        Self::new(thread_id_for(a, b), vec![a.to_string(), b.to_string()], created_at, ThreadKind::Direct)
+    }
+
+    /// Group thread with its current members
+    pub fn group(group: &GroupState, created_at: u64) -> Self {
+        let mut thread = Self::new(group.group_id.clone(), group.members.clone(), created_at, ThreadKind::Group);
+        thread.metadata.insert("owner".to_string(), group.owner.clone());
+        thread
+    }
+
+    fn new(id: String, mut participants: Vec<String>, created_at: u64, kind: ThreadKind) -> Self {
+        participants.sort();
+        participants.dedup();
+        Self { id, participants, created_at, kind, metadata: BTreeMap::new() }
+    }
+
+    pub fn is_participant(&self, identity_hash: &str) -> bool {
+        self.participants.binary_search_by(|p| p.as_str().cmp(identity_hash)).is_ok()
+    }
+}
+
+#[derive(Default)]
 pub struct MessageStore {
+    threads: HashMap<String, Thread>,
+    threads_by_identity: HashMap<String, BTreeSet<String>>, // identity_hash -> thread ids it takes part in
     messages: HashMap<String, Vec<Message>>,
-    keys: HashMap<String, [u8; 32]>,           // thread_id -> encryption key
     cstate_roots: HashMap<String, String>,     // identity_hash -> current CSTATE root
     thread_roots: HashMap<String, Vec<String>>, // identity_hash -> list of thread roots
     vaa_nonces: HashMap<String, u64>,          // identity_hash -> last VAA nonce (replay protection)
@@ -40,21 +331,7 @@ pub struct MessageStore {
 
 impl MessageStore {
     pub fn new() -> Self {
-        Self {
-            messages: HashMap::new(),
-            keys: HashMap::new(),
-            cstate_roots: HashMap::new(),
-            thread_roots: HashMap::new(),
-            vaa_nonces: HashMap::new(),
-        }
-    }
-
-    pub fn get_or_create_key(&mut self, thread_id: &str) -> [u8; 32] {
-        *self.keys.entry(thread_id.to_string()).or_insert_with(|| {
-            let mut key = [0u8; 32];
-            rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut key);
-            key
-        })
+        Self::default()
     }
 
     pub fn get_cstate_root(&self, identity_hash: &str) -> String {
@@ -70,7 +347,7 @@ impl MessageStore {
     pub fn add_thread_root(&mut self, identity_hash: &str, thread_root: String) {
         self.thread_roots
             .entry(identity_hash.to_string())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(thread_root);
     }
 
@@ -81,27 +358,98 @@ impl MessageStore {
     }
 
     pub fn get_next_vaa_nonce(&mut self, identity_hash: &str) -> u64 {
-        let nonce = self.vaa_nonces.get(identity_hash).copied().unwrap_or(0) + 1;
+        let nonce = self.get_vaa_nonce(identity_hash) + 1;
         self.vaa_nonces.insert(identity_hash.to_string(), nonce);
         nonce
     }
 
+    /// Last VAA nonce accepted for an identity (0 if none)
+    pub fn get_vaa_nonce(&self, identity_hash: &str) -> u64 {
+        self.vaa_nonces.get(identity_hash).copied().unwrap_or(0)
+    }
+
+    pub fn get_cstate_snapshot(&self, identity_hash: &str) -> CStateSnapshot {
+        CStateSnapshot {
+            cstate_root: self.get_cstate_root(identity_hash),
+            thread_roots: self.get_thread_roots(identity_hash),
+            vaa_nonce: self.get_vaa_nonce(identity_hash),
+        }
+    }
+
+    /// Register a new thread; fails if the id is taken
+    pub fn create_thread(&mut self, thread: Thread) -> anyhow::Result<&Thread> {
+        if self.threads.contains_key(&thread.id) {
+            return Err(anyhow::anyhow!("Thread {} already exists", thread.id));
+        }
+        for participant in &thread.participants {
+            self.threads_by_identity
+                .entry(participant.clone())
+                .or_default()
+                .insert(thread.id.clone());
+        }
+        Ok(self.threads.entry(thread.id.clone()).or_insert(thread))
+    }
+
+    /// The direct thread between `a` and `b`, created on first use
+    pub fn direct_thread(&mut self, a: &str, b: &str, now: u64) -> &Thread {
+        let id = thread_id_for(a, b);
+        if !self.threads.contains_key(&id) {
+            self.create_thread(Thread::direct(a, b, now)).expect("thread id is free");
+        }
+        &self.threads[&id]
+    }
+
+    /// Replace a thread's participants (group membership changes)
+    pub fn set_participants(&mut self, thread_id: &str, participants: Vec<String>) -> anyhow::Result<()> {
+        let thread = self
+            .threads
+            .get_mut(thread_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown thread {}", thread_id))?;
+        for participant in &thread.participants {
+            if let Some(ids) = self.threads_by_identity.get_mut(participant) {
+                ids.remove(thread_id);
+            }
+        }
+        *thread = Thread::new(thread.id.clone(), participants, thread.created_at, thread.kind);
+        for participant in &thread.participants {
+            self.threads_by_identity
+                .entry(participant.clone())
+                .or_default()
+                .insert(thread_id.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn get_thread(&self, thread_id: &str) -> Option<&Thread> {
+        self.threads.get(thread_id)
+    }
+
+    /// Threads an identity takes part in (index lookup, no scan)
+    pub fn threads_for(&self, identity_hash: &str) -> Vec<&Thread> {
+        self.threads_by_identity
+            .get(identity_hash)
+            .map(|ids| ids.iter().filter_map(|id| self.threads.get(id)).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether `identity_hash` may read and post in `thread_id`
+    pub fn is_participant(&self, thread_id: &str, identity_hash: &str) -> bool {
+        self.threads.get(thread_id).is_some_and(|t| t.is_participant(identity_hash))
+    }
+
     pub fn add_message(&mut self, message: Message) {
         self.messages
             .entry(message.thread_id.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(message);
     }
 
     pub fn get_messages(&self, thread_id: &str) -> Option<&Vec<Message>> {
         self.messages.get(thread_id)
     }
-    
-    pub fn get_all_thread_ids(&self) -> Vec<String> {
-        self.messages.keys().cloned().collect()
-    }
 }
 
+#[deprecated(note = "binds no context; use `encrypt_message_with_aad`")]
 pub fn encrypt_message(key: &[u8; 32], plaintext: &str) -> anyhow::Result<(Vec<u8>, XNonce)> {
     let cipher = XChaCha20Poly1305::new(key.into());
     let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
@@ -111,6 +459,7 @@ pub fn encrypt_message(key: &[u8; 32], plaintext: &str) -> anyhow::Result<(Vec<u
     Ok((ciphertext, nonce))
 }
 
+#[deprecated(note = "binds no context; use `decrypt_message_with_aad`")]
 pub fn decrypt_message(key: &[u8; 32], ciphertext: &[u8], nonce: &XNonce) -> anyhow::Result<String> {
     let cipher = XChaCha20Poly1305::new(key.into());
     let plaintext = cipher
@@ -119,3 +468,151 @@ pub fn decrypt_message(key: &[u8; 32], ciphertext: &[u8], nonce: &XNonce) -> any
     String::from_utf8(plaintext).map_err(|e| anyhow::anyhow!("Invalid UTF-8: {}", e))
 }
 
+/// `encrypt_message` with the message context authenticated as associated data
+pub fn encrypt_message_with_aad(
+    key: &[u8; 32],
+    plaintext: &str,
+    aad: &MessageAad,
+) -> anyhow::Result<(Vec<u8>, XNonce)> {
+    encrypt_with_ad(key, plaintext, &aad.encode())
+}
+
+/// Counterpart of `encrypt_message_with_aad`; fails unless `aad` matches exactly
+pub fn decrypt_message_with_aad(
+    key: &[u8; 32],
+    ciphertext: &[u8],
+    nonce: &XNonce,
+    aad: &MessageAad,
+) -> anyhow::Result<String> {
+    decrypt_with_ad(key, ciphertext, nonce, &aad.encode())
+}
+
+/// XChaCha20-Poly1305 over raw associated data (callers add their own framing)
+pub(crate) fn encrypt_with_ad(key: &[u8; 32], plaintext: &str, ad: &[u8]) -> anyhow::Result<(Vec<u8>, XNonce)> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext.as_bytes(), aad: ad })
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    Ok((ciphertext, nonce))
+}
+
+pub(crate) fn decrypt_with_ad(key: &[u8; 32], ciphertext: &[u8], nonce: &XNonce, ad: &[u8]) -> anyhow::Result<String> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let plaintext = cipher
+        .decrypt(nonce, Payload { msg: ciphertext, aad: ad })
+        .map_err(|e| anyhow::anyhow!("Decryption failed: {}", e))?;
+    String::from_utf8(plaintext).map_err(|e| anyhow::anyhow!("Invalid UTF-8: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use identity::IdentityManager;
+
+    fn sealed(sender: &IdentityManager) -> SendRequest {
+        SendRequest::seal(sender, "b:c", "a", &[7u8; 32], "hello", &CStateSnapshot::default()).unwrap()
+    }
+
+    #[test]
+    fn send_signature_covers_every_field() {
+        let alice = IdentityManager::new();
+        let request = sealed(&alice);
+        let key = alice.public_key();
+        assert!(request.verify_signatures(&key));
+
+        let mut tampered = request.clone();
+        tampered.epoch += 1;
+        assert!(!tampered.verify_signatures(&key));
+        let mut tampered = request.clone();
+        tampered.ciphertext.push('A');
+        assert!(!tampered.verify_signatures(&key));
+    }
+
+    #[test]
+    fn send_payload_is_unambiguous() {
+        let alice = IdentityManager::new();
+        let request = sealed(&alice);
+        // Moving the ':' boundary between thread and recipient changes the payload
+        let mut shifted = request.clone();
+        shifted.thread_id = "a:b".into();
+        shifted.recipient_id = "c".into();
+        assert_ne!(request.signing_payload(), shifted.signing_payload());
+    }
+
+    #[test]
+    fn stored_messages_only_decrypt_in_their_original_context() {
+        let alice = IdentityManager::new();
+        let request = sealed(&alice);
+        let message = Message {
+            thread_id: request.thread_id.clone(),
+            sender_id: request.sender_identity_hash.clone(),
+            ciphertext: request.ciphertext.clone(),
+            iv: request.iv.clone(),
+            timestamp: request.timestamp,
+            message_commitment: request.message_commitment.clone(),
+            sequence: request.endcap.vaa_nonce,
+            endcap: Some(request.endcap.clone()),
+            epoch: request.epoch,
+            version: request.version,
+        };
+        let key = [7u8; 32];
+        assert_eq!(message.decrypt(&key).unwrap(), "hello");
+        assert!(message.decrypt(&[8u8; 32]).is_err());
+
+        let moved = [
+            Message { thread_id: "b:d".into(), ..message.clone() },
+            Message { sender_id: IdentityManager::new().get_identity_hash().into(), ..message.clone() },
+            Message { sequence: message.sequence + 1, ..message.clone() },
+            Message { version: message.version + 1, ..message.clone() },
+        ];
+        for message in moved {
+            assert!(message.decrypt(&key).is_err());
+        }
+    }
+
+    #[test]
+    fn direct_threads_are_indexed_per_identity() {
+        let mut store = MessageStore::new();
+        let thread = store.direct_thread("bob", "alice", 10).clone();
+        assert_eq!(thread.id, thread_id_for("alice", "bob"));
+        assert_eq!(thread.participants, ["alice", "bob"]);
+        assert_eq!(thread.kind, ThreadKind::Direct);
+
+        // Lookups in either order find the same thread
+        assert_eq!(store.direct_thread("alice", "bob", 20).created_at, 10);
+        store.direct_thread("alice", "carol", 30);
+        assert_eq!(store.threads_for("alice").len(), 2);
+        assert_eq!(store.threads_for("bob").len(), 1);
+        assert!(store.threads_for("mallory").is_empty());
+        assert!(store.is_participant(&thread.id, "bob"));
+        assert!(!store.is_participant(&thread.id, "carol"));
+        assert!(store.create_thread(Thread::direct("alice", "bob", 40)).is_err());
+    }
+
+    #[test]
+    fn participant_changes_update_the_index() {
+        let mut store = MessageStore::new();
+        let id = store.direct_thread("alice", "bob", 0).id.clone();
+        store.set_participants(&id, vec!["carol".into(), "alice".into(), "carol".into()]).unwrap();
+        assert_eq!(store.get_thread(&id).unwrap().participants, ["alice", "carol"]);
+        assert!(store.threads_for("bob").is_empty());
+        assert_eq!(store.threads_for("carol")[0].id, id);
+        assert!(store.set_participants("missing", Vec::new()).is_err());
+
+        assert!(store.get_messages(&id).is_none());
+        store.add_message(Message {
+            thread_id: id.clone(),
+            sender_id: "alice".into(),
+            ciphertext: String::new(),
+            iv: String::new(),
+            timestamp: 0,
+            message_commitment: String::new(),
+            endcap: None,
+            epoch: 0,
+            sequence: 1,
+            version: PROTOCOL_VERSION,
+        });
+        assert_eq!(store.get_messages(&id).unwrap().len(), 1);
+    }
+}
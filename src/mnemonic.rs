@@ -0,0 +1,89 @@
+// BIP39 mnemonic recovery phrases
+// Words -> PBKDF2-HMAC-SHA512 seed -> SLIP-10 ED25519 master key (derivation.rs) -> identity
+
+use bip39::Mnemonic;
+use rand::RngCore;
+
+/// Generate a new English recovery phrase of 12 or 24 words (with checksum)
+pub fn generate_mnemonic(word_count: usize) -> anyhow::Result<String> {
+    let entropy_len = match word_count {
+        12 => 16,
+        24 => 32,
+        _ => return Err(anyhow::anyhow!("Recovery phrases must be 12 or 24 words")),
+    };
+    let mut entropy = vec![0u8; entropy_len];
+    rand::rngs::OsRng.fill_bytes(&mut entropy);
+    let mnemonic = Mnemonic::from_entropy(&entropy)
+        .map_err(|e| anyhow::anyhow!("Mnemonic generation failed: {}", e))?;
+    Ok(mnemonic.to_string())
+}
+
+/// Check word list membership, word count and checksum
+pub fn validate_mnemonic(phrase: &str) -> anyhow::Result<()> {
+    parse(phrase).map(|_| ())
+}
+
+/// Derive the 64-byte BIP39 seed (PBKDF2-HMAC-SHA512, 2048 rounds).
+/// The optional passphrase acts as an extra "25th word".
+pub fn mnemonic_to_seed(phrase: &str, passphrase: &str) -> anyhow::Result<[u8; 64]> {
+    Ok(parse(phrase)?.to_seed(passphrase))
+}
+
+fn parse(phrase: &str) -> anyhow::Result<Mnemonic> {
+    let mnemonic = Mnemonic::parse(phrase).map_err(|e| anyhow::anyhow!("Invalid recovery phrase: {}", e))?;
+    if !matches!(mnemonic.word_count(), 12 | 24) {
+        return Err(anyhow::anyhow!("Recovery phrases must be 12 or 24 words"));
+    }
+    Ok(mnemonic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    #[test]
+    fn matches_bip39_test_vector() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        assert_eq!(
+            hex::encode(mnemonic_to_seed(phrase, "TREZOR").unwrap()),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+    }
+
+    #[test]
+    fn generated_phrases_recover_the_same_identity() {
+        for words in [12, 24] {
+            let phrase = generate_mnemonic(words).unwrap();
+            assert_eq!(phrase.split_whitespace().count(), words);
+            validate_mnemonic(&phrase).unwrap();
+            let first = IdentityManager::from_mnemonic(&phrase, "").unwrap();
+            let second = IdentityManager::from_mnemonic(&phrase, "").unwrap();
+            assert_eq!(first.get_identity_hash(), second.get_identity_hash());
+            let other = IdentityManager::from_mnemonic(&phrase, "extra").unwrap();
+            assert_ne!(first.get_identity_hash(), other.get_identity_hash());
+        }
+    }
+
+    #[test]
+    fn rotated_identities_recover_from_the_phrase() {
+        let phrase = generate_mnemonic(12).unwrap();
+        let mut identity = IdentityManager::from_mnemonic(&phrase, "").unwrap();
+        identity.rotate_key_from_mnemonic(&phrase, "").unwrap();
+        identity.rotate_key_from_mnemonic(&phrase, "").unwrap();
+        assert!(identity.rotate_key_from_mnemonic(&generate_mnemonic(12).unwrap(), "").is_err());
+
+        let rotations = identity.get_rotations().to_vec();
+        let recovered = IdentityManager::from_mnemonic_rotated(&phrase, "", rotations).unwrap();
+        assert_eq!(recovered.get_identity_hash(), identity.get_identity_hash());
+        assert_eq!(recovered.get_public_key(), identity.get_public_key());
+    }
+
+    #[test]
+    fn rejects_bad_phrases() {
+        assert!(generate_mnemonic(15).is_err());
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(validate_mnemonic(phrase).is_err());
+        assert!(validate_mnemonic("not a real recovery phrase").is_err());
+    }
+}
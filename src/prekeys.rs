@@ -0,0 +1,439 @@
+// X3DH-style prekey bundles
+// Lets an initiator derive a thread secret with an offline responder
+
+use ed25519_dalek::{PublicKey, Verifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::now_secs;
+use crate::identity::{parse_public_key, parse_signature};
+use crate::keyexchange::{ed25519_public_to_x25519, X25519Secret};
+
+/// How long a replaced signed prekey still answers X3DH headers, so initiators
+/// holding a bundle fetched before the rotation can still reach the responder
+pub const SIGNED_PREKEY_GRACE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Medium-term X25519 prekey, signed by the identity key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedPrekey {
+    pub id: u32,
+    pub public_key: String,        // hex X25519 public key
+    pub signature: String,         // hex ED25519 signature by the identity key
+}
+
+/// Single-use X25519 prekey
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OneTimePrekey {
+    pub id: u32,
+    pub public_key: String,        // hex X25519 public key
+}
+
+/// Prekeys published by an identity (`POST /prekeys`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrekeyUpload {
+    pub identity_hash: String,
+    pub identity_key: String,      // hex ED25519 public key
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekeys: Vec<OneTimePrekey>,
+    pub timestamp: u64,            // Client clock at signing time (seconds)
+    pub signature: String,         // hex signature over the whole upload
+}
+
+/// What an initiator fetches to start a thread (`POST /prekeys/{identity_hash}/claim`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrekeyBundle {
+    pub identity_hash: String,
+    pub identity_key: String,
+    pub signed_prekey: SignedPrekey,
+    pub one_time_prekey: Option<OneTimePrekey>, // None once the supply runs out
+}
+
+/// First-message header the initiator sends so the responder can derive the same secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct X3dhInit {
+    pub initiator_identity_key: String, // hex ED25519 public key
+    pub ephemeral_key: String,          // hex X25519 public key
+    pub signed_prekey_id: u32,
+    pub one_time_prekey_id: Option<u32>,
+}
+
+/// Prekey secrets held by the identity owner (exported only inside the keystore)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrekeySecrets {
+    signed_prekey: Option<(u32, X25519Secret)>,
+    previous_signed_prekey: Option<(u32, X25519Secret, u64)>, // (id, secret, retired_at)
+    one_time_prekeys: HashMap<u32, X25519Secret>,
+    next_id: u32,
+}
+
+impl PrekeySecrets {
+    /// Replace the signed prekey and add `one_time_count` fresh one-time prekeys.
+    /// The replaced signed prekey stays usable for `SIGNED_PREKEY_GRACE_SECS`.
+    /// Returns the secret of the new signed prekey and the new one-time public keys.
+    pub(crate) fn rotate(&mut self, one_time_count: u32) -> (u32, X25519Secret, Vec<OneTimePrekey>) {
+        self.next_id += 1;
+        let spk_id = self.next_id;
+        let spk = X25519Secret::generate();
+        if let Some((id, secret)) = self.signed_prekey.replace((spk_id, spk.clone())) {
+            self.previous_signed_prekey = Some((id, secret, now_secs()));
+        }
+
+        let mut one_time = Vec::new();
+        for _ in 0..one_time_count {
+            self.next_id += 1;
+            let secret = X25519Secret::generate();
+            one_time.push(OneTimePrekey {
+                id: self.next_id,
+                public_key: hex::encode(secret.public_key()),
+            });
+            self.one_time_prekeys.insert(self.next_id, secret);
+        }
+        (spk_id, spk, one_time)
+    }
+
+    /// Current signed prekey, or the previous one while it is within its grace period
+    pub(crate) fn signed_prekey(&self, id: u32, now: u64) -> Option<&X25519Secret> {
+        match (&self.signed_prekey, &self.previous_signed_prekey) {
+            (Some((spk_id, secret)), _) if *spk_id == id => Some(secret),
+            (_, Some((spk_id, secret, retired_at)))
+                if *spk_id == id && now.saturating_sub(*retired_at) <= SIGNED_PREKEY_GRACE_SECS =>
+            {
+                Some(secret)
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn take_one_time_prekey(&mut self, id: u32) -> Option<X25519Secret> {
+        self.one_time_prekeys.remove(&id)
+    }
+}
+
+/// Bytes the identity key signs for a signed prekey
+pub fn signed_prekey_payload(id: u32, public_key: &str) -> String {
+    format!("zerotrace_spk_v1:{}:{}", id, public_key)
+}
+
+/// Bytes the identity key signs for a prekey upload
+pub fn upload_payload(
+    identity_hash: &str,
+    signed_prekey: &SignedPrekey,
+    one_time: &[OneTimePrekey],
+    timestamp: u64,
+) -> String {
+    let mut hasher = Sha256::new();
+    for prekey in one_time {
+        hasher.update(prekey.id.to_be_bytes());
+        hasher.update(prekey.public_key.as_bytes());
+    }
+    format!(
+        "zerotrace_prekeys_v1:{}:{}:{}:{}",
+        identity_hash,
+        signed_prekey.signature,
+        hex::encode(hasher.finalize()),
+        timestamp
+    )
+}
+
+fn parse_x25519(hex_key: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(hex_key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("X25519 key must be 32 bytes"))
+}
+
+fn verify_hex(pubkey: &PublicKey, message: &str, hex_sig: &str) -> anyhow::Result<()> {
+    pubkey
+        .verify(message.as_bytes(), &parse_signature(hex_sig)?)
+        .map_err(|_| anyhow::anyhow!("Signature verification failed"))
+}
+
+/// Check the presented identity key is the expected (current) key and signed the prekey.
+/// The expected key comes from the caller: the registered identity's `current_key()`
+/// or a contact's key, since rotated keys no longer hash to the identity_hash.
+fn verify_identity_and_spk(identity_key: &str, expected_key: &PublicKey, spk: &SignedPrekey) -> anyhow::Result<()> {
+    if parse_public_key(identity_key)? != *expected_key {
+        return Err(anyhow::anyhow!("Identity key is not the identity's current key"));
+    }
+    verify_hex(expected_key, &signed_prekey_payload(spk.id, &spk.public_key), &spk.signature)?;
+    parse_x25519(&spk.public_key)?;
+    Ok(())
+}
+
+impl PrekeyUpload {
+    /// Verify all signatures against the identity's current key
+    pub fn verify(&self, identity_key: &PublicKey) -> anyhow::Result<()> {
+        verify_identity_and_spk(&self.identity_key, identity_key, &self.signed_prekey)?;
+        for prekey in &self.one_time_prekeys {
+            parse_x25519(&prekey.public_key)?;
+        }
+        let payload = upload_payload(&self.identity_hash, &self.signed_prekey, &self.one_time_prekeys, self.timestamp);
+        verify_hex(identity_key, &payload, &self.signature)
+    }
+}
+
+impl PrekeyBundle {
+    /// Verify the bundle carries the identity's current key and its signed prekey
+    pub fn verify(&self, identity_key: &PublicKey) -> anyhow::Result<()> {
+        verify_identity_and_spk(&self.identity_key, identity_key, &self.signed_prekey)
+    }
+}
+
+/// Initiator side: X3DH against a bundle, checked against the responder's trusted key.
+/// Returns the header to send and the initial thread secret, which is bound to
+/// both identity keys (IK_A || IK_B) as associated data.
+pub(crate) fn initiate(
+    identity_secret: &X25519Secret,
+    identity_key: &PublicKey,
+    bundle: &PrekeyBundle,
+    remote_identity_key: &PublicKey,
+) -> anyhow::Result<(X3dhInit, [u8; 32])> {
+    bundle.verify(remote_identity_key)?;
+    let remote_identity = ed25519_public_to_x25519(remote_identity_key)?;
+    let remote_spk = parse_x25519(&bundle.signed_prekey.public_key)?;
+    let ephemeral = X25519Secret::generate();
+
+    let mut ikm = vec![0xFFu8; 32];
+    ikm.extend_from_slice(&identity_secret.diffie_hellman(&remote_spk)?);
+    ikm.extend_from_slice(&ephemeral.diffie_hellman(&remote_identity)?);
+    ikm.extend_from_slice(&ephemeral.diffie_hellman(&remote_spk)?);
+    if let Some(opk) = &bundle.one_time_prekey {
+        ikm.extend_from_slice(&ephemeral.diffie_hellman(&parse_x25519(&opk.public_key)?)?);
+    }
+
+    let init = X3dhInit {
+        initiator_identity_key: hex::encode(identity_key.as_bytes()),
+        ephemeral_key: hex::encode(ephemeral.public_key()),
+        signed_prekey_id: bundle.signed_prekey.id,
+        one_time_prekey_id: bundle.one_time_prekey.as_ref().map(|opk| opk.id),
+    };
+    Ok((init, kdf(&ikm, identity_key, remote_identity_key)))
+}
+
+/// Responder side: recompute the initiator's secret, consuming the one-time prekey
+pub(crate) fn respond(
+    identity_secret: &X25519Secret,
+    identity_key: &PublicKey,
+    secrets: &mut PrekeySecrets,
+    init: &X3dhInit,
+) -> anyhow::Result<[u8; 32]> {
+    let initiator_key = parse_public_key(&init.initiator_identity_key)?;
+    let remote_identity = ed25519_public_to_x25519(&initiator_key)?;
+    let ephemeral = parse_x25519(&init.ephemeral_key)?;
+    let spk = secrets
+        .signed_prekey(init.signed_prekey_id, now_secs())
+        .ok_or_else(|| anyhow::anyhow!("Unknown signed prekey {}", init.signed_prekey_id))?;
+
+    let mut ikm = vec![0xFFu8; 32];
+    ikm.extend_from_slice(&spk.diffie_hellman(&remote_identity)?);
+    ikm.extend_from_slice(&identity_secret.diffie_hellman(&ephemeral)?);
+    ikm.extend_from_slice(&spk.diffie_hellman(&ephemeral)?);
+    if let Some(id) = init.one_time_prekey_id {
+        let opk = secrets
+            .take_one_time_prekey(id)
+            .ok_or_else(|| anyhow::anyhow!("One-time prekey {} already used", id))?;
+        ikm.extend_from_slice(&opk.diffie_hellman(&ephemeral)?);
+    }
+    Ok(kdf(&ikm, &initiator_key, identity_key))
+}
+
+/// X3DH KDF with the associated data IK_A || IK_B (initiator, responder) in the info
+fn kdf(ikm: &[u8], initiator_key: &PublicKey, responder_key: &PublicKey) -> [u8; 32] {
+    let mut info = b"zerotrace_x3dh_v1".to_vec();
+    info.extend_from_slice(initiator_key.as_bytes());
+    info.extend_from_slice(responder_key.as_bytes());
+    crate::keyexchange::hkdf_expand(&[0u8; 32], ikm, &info)
+}
+
+#[derive(Debug)]
+struct StoredPrekeys {
+    identity_key: String,
+    signed_prekey: SignedPrekey,
+    one_time_prekeys: VecDeque<OneTimePrekey>,
+    claimed_by: HashSet<String>,   // Claimers already handed a one-time prekey under this signed prekey
+}
+
+/// Server-side prekey directory (identity_hash -> published prekeys)
+#[derive(Debug, Default)]
+pub struct PrekeyStore {
+    entries: HashMap<String, StoredPrekeys>,
+}
+
+impl PrekeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store an upload signed by `identity_key`, the identity's current key.
+    /// A new signed prekey replaces the old one and resets who has claimed a
+    /// one-time prekey; one-time prekeys are appended to the remaining supply.
+    /// Signed prekey ids must increase, so a replayed upload cannot restore an
+    /// older prekey set.
+    pub fn upload(&mut self, upload: PrekeyUpload, identity_key: &PublicKey) -> anyhow::Result<usize> {
+        upload.verify(identity_key)?;
+        if let Some(entry) = self.entries.get(&upload.identity_hash) {
+            if upload.signed_prekey.id <= entry.signed_prekey.id {
+                return Err(anyhow::anyhow!("Upload does not replace the current signed prekey"));
+            }
+        }
+        let entry = self
+            .entries
+            .entry(upload.identity_hash)
+            .or_insert_with(|| StoredPrekeys {
+                identity_key: upload.identity_key.clone(),
+                signed_prekey: upload.signed_prekey.clone(),
+                one_time_prekeys: VecDeque::new(),
+                claimed_by: HashSet::new(),
+            });
+        entry.identity_key = upload.identity_key;
+        entry.signed_prekey = upload.signed_prekey;
+        entry.one_time_prekeys.extend(upload.one_time_prekeys);
+        entry.claimed_by.clear();
+        Ok(entry.one_time_prekeys.len())
+    }
+
+    /// Hand out a bundle, removing the one-time prekey it contains. Each claimer
+    /// gets at most one one-time prekey per signed prekey; later claims fall back
+    /// to the signed prekey alone until the next upload, so one caller cannot
+    /// drain the supply.
+    pub fn claim_bundle(&mut self, identity_hash: &str, claimer: &str) -> Option<PrekeyBundle> {
+        let entry = self.entries.get_mut(identity_hash)?;
+        let one_time_prekey = if entry.claimed_by.insert(claimer.to_string()) {
+            entry.one_time_prekeys.pop_front()
+        } else {
+            None
+        };
+        Some(PrekeyBundle {
+            identity_hash: identity_hash.to_string(),
+            identity_key: entry.identity_key.clone(),
+            signed_prekey: entry.signed_prekey.clone(),
+            one_time_prekey,
+        })
+    }
+
+    /// Number of one-time prekeys left for an identity
+    pub fn one_time_count(&self, identity_hash: &str) -> usize {
+        self.entries
+            .get(identity_hash)
+            .map(|e| e.one_time_prekeys.len())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    fn published(bob: &mut IdentityManager, one_time: u32) -> PrekeyStore {
+        let mut store = PrekeyStore::new();
+        let upload = bob.generate_prekeys(one_time);
+        store.upload(upload, &bob.public_key()).unwrap();
+        store
+    }
+
+    #[test]
+    fn x3dh_agrees_with_and_without_one_time_prekeys() {
+        let alice = IdentityManager::new();
+        let mut bob = IdentityManager::new();
+        let mut store = published(&mut bob, 1);
+
+        let bundle = store.claim_bundle(bob.get_identity_hash(), "carol").unwrap();
+        assert!(bundle.one_time_prekey.is_some());
+        let (init, secret) = alice.x3dh_initiate(&bundle).unwrap();
+        assert_eq!(bob.x3dh_respond(&init).unwrap(), secret);
+        // The one-time prekey is consumed
+        assert!(bob.x3dh_respond(&init).is_err());
+
+        let bundle = store.claim_bundle(bob.get_identity_hash(), "dave").unwrap();
+        assert!(bundle.one_time_prekey.is_none());
+        let (init, secret) = alice.x3dh_initiate(&bundle).unwrap();
+        assert_eq!(bob.x3dh_respond(&init).unwrap(), secret);
+    }
+
+    #[test]
+    fn secret_is_bound_to_both_identity_keys() {
+        let alice = IdentityManager::new();
+        let mallory = IdentityManager::new();
+        let mut bob = IdentityManager::new();
+        let mut store = published(&mut bob, 0);
+
+        let bundle = store.claim_bundle(bob.get_identity_hash(), "alice").unwrap();
+        let (mut init, secret) = alice.x3dh_initiate(&bundle).unwrap();
+        init.initiator_identity_key = hex::encode(mallory.get_public_key());
+        assert_ne!(bob.x3dh_respond(&init).unwrap(), secret);
+    }
+
+    #[test]
+    fn claims_do_not_drain_one_time_prekeys() {
+        let mut bob = IdentityManager::new();
+        let mut store = published(&mut bob, 3);
+        assert!(store.claim_bundle(bob.get_identity_hash(), "mallory").unwrap().one_time_prekey.is_some());
+        for _ in 0..5 {
+            assert!(store.claim_bundle(bob.get_identity_hash(), "mallory").unwrap().one_time_prekey.is_none());
+        }
+        assert_eq!(store.one_time_count(bob.get_identity_hash()), 2);
+
+        // A new upload (new signed prekey) lets earlier claimers have one again
+        store.upload(bob.generate_prekeys(1), &bob.public_key()).unwrap();
+        assert!(store.claim_bundle(bob.get_identity_hash(), "mallory").unwrap().one_time_prekey.is_some());
+        assert!(store.claim_bundle(bob.get_identity_hash(), "mallory").unwrap().one_time_prekey.is_none());
+        assert_eq!(store.one_time_count(bob.get_identity_hash()), 2);
+    }
+
+    #[test]
+    fn rejects_replayed_and_tampered_uploads() {
+        let mut bob = IdentityManager::new();
+        let old = bob.generate_prekeys(2);
+        let mut store = PrekeyStore::new();
+        let key = bob.public_key();
+        store.upload(old.clone(), &key).unwrap();
+        store.upload(bob.generate_prekeys(2), &key).unwrap();
+        assert!(store.upload(old, &key).is_err());
+
+        let mut tampered = bob.generate_prekeys(1);
+        tampered.timestamp += 1;
+        assert!(tampered.verify(&key).is_err());
+        let mut bundle = store.claim_bundle(bob.get_identity_hash(), "alice").unwrap();
+        bundle.signed_prekey.public_key = hex::encode(X25519Secret::generate().public_key());
+        assert!(bundle.verify(&key).is_err());
+    }
+
+    #[test]
+    fn rotated_identities_keep_publishing_prekeys() {
+        let mut alice = IdentityManager::new();
+        let mut bob = IdentityManager::new();
+        let bob_hash = bob.get_identity_hash().to_string();
+        alice.add_contact(bob_hash.clone(), bob.public_key());
+        let rotation = bob.rotate_key();
+
+        // Verified against the current key, not hash(identity_key)
+        let mut store = PrekeyStore::new();
+        store.upload(bob.generate_prekeys(1), &bob.public_key()).unwrap();
+        let bundle = store.claim_bundle(&bob_hash, "alice").unwrap();
+
+        // The contact still holds the old key until it follows the rotation
+        assert!(alice.x3dh_initiate(&bundle).is_err());
+        alice.follow_contact_rotations(&bob_hash, &[rotation]).unwrap();
+        let (init, secret) = alice.x3dh_initiate(&bundle).unwrap();
+        assert_eq!(bob.x3dh_respond(&init).unwrap(), secret);
+
+        // Without a contact entry a rotated bundle cannot be checked
+        assert!(IdentityManager::new().x3dh_initiate(&bundle).is_err());
+    }
+
+    #[test]
+    fn previous_signed_prekey_has_a_grace_period() {
+        let mut secrets = PrekeySecrets::default();
+        let (first, _, _) = secrets.rotate(0);
+        let (second, _, _) = secrets.rotate(0);
+        let now = now_secs();
+        assert!(secrets.signed_prekey(second, now).is_some());
+        assert!(secrets.signed_prekey(first, now).is_some());
+        assert!(secrets.signed_prekey(first, now + SIGNED_PREKEY_GRACE_SECS + 1).is_none());
+
+        let (third, _, _) = secrets.rotate(0);
+        assert!(secrets.signed_prekey(third, now).is_some());
+        assert!(secrets.signed_prekey(first, now).is_none());
+    }
+}
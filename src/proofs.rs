@@ -2,6 +2,8 @@
 // Simulates plonky2-hwa proof generation and verification
 
 use serde::{Deserialize, Serialize};
+
+use crate::now_secs;
 use sha2::{Sha256, Digest};
 use base64::{Engine as _, engine::general_purpose};
 
@@ -48,10 +50,7 @@ pub fn generate_cfc_proof(
         end_cstate_root: end_root.to_string(),
         proof_bytes: general_purpose::STANDARD.encode(proof_hash), // Stub proof
         public_inputs: public_inputs.to_vec(),
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs(),
+        timestamp: now_secs(),
     }
 }
 
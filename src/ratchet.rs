@@ -0,0 +1,347 @@
+// Double Ratchet sessions (Signal-style) for per-thread forward secrecy
+// Layers DH and symmetric-key ratchets on top of XChaCha20-Poly1305, with the
+// header and the message context (`MessageAad`) as associated data
+
+use chacha20poly1305::XNonce;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::VecDeque;
+
+use crate::keyexchange::X25519Secret;
+use crate::{decrypt_with_ad, encrypt_with_ad, MessageAad};
+
+/// Max message keys skipped in a single chain (bounds work per received message)
+pub const MAX_SKIP: u32 = 1000;
+/// Max skipped message keys kept across chains; oldest are evicted first
+pub const MAX_SKIPPED_KEYS: usize = 2000;
+
+/// Header sent in clear alongside each ratchet-encrypted message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatchetHeader {
+    pub dh_public: [u8; 32],  // Sender's current ratchet public key
+    pub prev_chain_len: u32,  // Messages in sender's previous sending chain
+    pub n: u32,               // Message number in current sending chain
+}
+
+impl RatchetHeader {
+    /// Associated data for a message: the message context followed by this header
+    fn associated_data(&self, aad: &MessageAad) -> Vec<u8> {
+        let mut out = aad.encode();
+        out.extend_from_slice(b"zerotrace_ratchet_header_v1");
+        out.extend_from_slice(&self.dh_public);
+        out.extend_from_slice(&self.prev_chain_len.to_be_bytes());
+        out.extend_from_slice(&self.n.to_be_bytes());
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SkippedKey {
+    dh_public: [u8; 32],
+    n: u32,
+    message_key: [u8; 32],
+}
+
+/// Keys and counters of a session. Small and `Copy`, so `decrypt` can stage
+/// changes on a copy and commit them only once the message authenticates.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct ChainState {
+    dh_self: [u8; 32],            // Own ratchet secret
+    dh_remote: Option<[u8; 32]>,  // Peer's current ratchet public key
+    root_key: [u8; 32],
+    send_chain: Option<[u8; 32]>,
+    recv_chain: Option<[u8; 32]>,
+    send_n: u32,
+    recv_n: u32,
+    prev_send_n: u32,
+}
+
+/// Persistable Double Ratchet state for one side of a thread
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RatchetSession {
+    #[serde(flatten)]
+    state: ChainState,
+    skipped: VecDeque<SkippedKey>,
+}
+
+impl RatchetSession {
+    /// Start a session as the initiator, knowing the responder's ratchet public key.
+    /// `shared_secret` is the initial thread secret (e.g. from key agreement).
+    pub fn initiate(shared_secret: &[u8; 32], remote_ratchet_public: &[u8; 32]) -> anyhow::Result<Self> {
+        let dh_self = X25519Secret::generate();
+        let dh_out = dh_self.diffie_hellman(remote_ratchet_public)?;
+        let (root_key, send_chain) = kdf_root(shared_secret, &dh_out);
+
+        Ok(Self {
+            state: ChainState {
+                dh_self: dh_self.to_bytes(),
+                dh_remote: Some(*remote_ratchet_public),
+                root_key,
+                send_chain: Some(send_chain),
+                recv_chain: None,
+                send_n: 0,
+                recv_n: 0,
+                prev_send_n: 0,
+            },
+            skipped: VecDeque::new(),
+        })
+    }
+
+    /// Start a session as the responder, using the ratchet keypair the initiator targeted
+    pub fn respond(shared_secret: &[u8; 32], own_ratchet_secret: &X25519Secret) -> Self {
+        Self {
+            state: ChainState {
+                dh_self: own_ratchet_secret.to_bytes(),
+                dh_remote: None,
+                root_key: *shared_secret,
+                send_chain: None,
+                recv_chain: None,
+                send_n: 0,
+                recv_n: 0,
+                prev_send_n: 0,
+            },
+            skipped: VecDeque::new(),
+        }
+    }
+
+    /// Encrypt the next outgoing message, advancing the sending chain. The header
+    /// and `aad` (thread, sender, sequence) are authenticated with the ciphertext.
+    pub fn encrypt(&mut self, plaintext: &str, aad: &MessageAad) -> anyhow::Result<(RatchetHeader, Vec<u8>, XNonce)> {
+        let state = &mut self.state;
+        let chain = state
+            .send_chain
+            .ok_or_else(|| anyhow::anyhow!("No sending chain yet: wait for the peer's first message"))?;
+        let (next_chain, message_key) = kdf_chain(&chain);
+
+        let header = RatchetHeader {
+            dh_public: X25519Secret::from_bytes(state.dh_self).public_key(),
+            prev_chain_len: state.prev_send_n,
+            n: state.send_n,
+        };
+        let (ciphertext, nonce) = encrypt_with_ad(&message_key, plaintext, &header.associated_data(aad))?;
+
+        state.send_chain = Some(next_chain);
+        state.send_n += 1;
+        Ok((header, ciphertext, nonce))
+    }
+
+    /// Decrypt an incoming message; fails unless the header and `aad` match what the
+    /// sender sealed. State only advances if decryption succeeds.
+    pub fn decrypt(
+        &mut self,
+        header: &RatchetHeader,
+        ciphertext: &[u8],
+        nonce: &XNonce,
+        aad: &MessageAad,
+    ) -> anyhow::Result<String> {
+        let ad = header.associated_data(aad);
+        if let Some(pos) = self
+            .skipped
+            .iter()
+            .position(|k| k.dh_public == header.dh_public && k.n == header.n)
+        {
+            let plaintext = decrypt_with_ad(&self.skipped[pos].message_key, ciphertext, nonce, &ad)?;
+            self.skipped.remove(pos);
+            return Ok(plaintext);
+        }
+
+        let mut next = self.state;
+        let mut skipped = Vec::new();
+        if next.dh_remote != Some(header.dh_public) {
+            next.skip_message_keys(header.prev_chain_len, &mut skipped)?;
+            next.dh_ratchet(&header.dh_public)?;
+        }
+        next.skip_message_keys(header.n, &mut skipped)?;
+
+        let chain = next
+            .recv_chain
+            .ok_or_else(|| anyhow::anyhow!("No receiving chain"))?;
+        let (next_chain, message_key) = kdf_chain(&chain);
+        let plaintext = decrypt_with_ad(&message_key, ciphertext, nonce, &ad)?;
+
+        next.recv_chain = Some(next_chain);
+        next.recv_n += 1;
+        self.state = next;
+        for key in skipped {
+            if self.skipped.len() == MAX_SKIPPED_KEYS {
+                self.skipped.pop_front();
+            }
+            self.skipped.push_back(key);
+        }
+        Ok(plaintext)
+    }
+
+    /// Number of cached keys for out-of-order messages
+    pub fn skipped_key_count(&self) -> usize {
+        self.skipped.len()
+    }
+}
+
+impl ChainState {
+    /// Derive the receiving chain's keys up to message `until`, collecting them in `skipped`
+    fn skip_message_keys(&mut self, until: u32, skipped: &mut Vec<SkippedKey>) -> anyhow::Result<()> {
+        let (Some(mut chain), Some(dh_public)) = (self.recv_chain, self.dh_remote) else {
+            return Ok(());
+        };
+        if until > self.recv_n + MAX_SKIP {
+            return Err(anyhow::anyhow!("Too many skipped messages"));
+        }
+        while self.recv_n < until {
+            let (next_chain, message_key) = kdf_chain(&chain);
+            skipped.push(SkippedKey { dh_public, n: self.recv_n, message_key });
+            chain = next_chain;
+            self.recv_n += 1;
+        }
+        self.recv_chain = Some(chain);
+        Ok(())
+    }
+
+    fn dh_ratchet(&mut self, remote_public: &[u8; 32]) -> anyhow::Result<()> {
+        self.prev_send_n = self.send_n;
+        self.send_n = 0;
+        self.recv_n = 0;
+        self.dh_remote = Some(*remote_public);
+
+        let dh_out = X25519Secret::from_bytes(self.dh_self).diffie_hellman(remote_public)?;
+        let (root_key, recv_chain) = kdf_root(&self.root_key, &dh_out);
+        self.recv_chain = Some(recv_chain);
+
+        let dh_self = X25519Secret::generate();
+        let dh_out = dh_self.diffie_hellman(remote_public)?;
+        let (root_key, send_chain) = kdf_root(&root_key, &dh_out);
+        self.dh_self = dh_self.to_bytes();
+        self.root_key = root_key;
+        self.send_chain = Some(send_chain);
+        Ok(())
+    }
+}
+
+/// Root KDF: (root_key, dh_out) -> (new root_key, chain_key)
+fn kdf_root(root_key: &[u8; 32], dh_out: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut okm = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(root_key), dh_out)
+        .expand(b"zerotrace_ratchet_root_v1", &mut okm)
+        .expect("64 bytes is a valid HKDF-SHA256 output length");
+    let mut new_root = [0u8; 32];
+    let mut chain = [0u8; 32];
+    new_root.copy_from_slice(&okm[..32]);
+    chain.copy_from_slice(&okm[32..]);
+    (new_root, chain)
+}
+
+/// Chain KDF: chain_key -> (next chain_key, message_key)
+fn kdf_chain(chain_key: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let derive = |constant: u8| {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(chain_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(&[constant]);
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&mac.finalize().into_bytes());
+        out
+    };
+    (derive(0x02), derive(0x01))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alice initiates towards Bob's ratchet key; both start from the same secret
+    fn pair() -> (RatchetSession, RatchetSession) {
+        let shared = [42u8; 32];
+        let bob_ratchet = X25519Secret::generate();
+        let alice = RatchetSession::initiate(&shared, &bob_ratchet.public_key()).unwrap();
+        let bob = RatchetSession::respond(&shared, &bob_ratchet);
+        (alice, bob)
+    }
+
+    fn aad() -> MessageAad {
+        MessageAad::new("alice:bob", "alice", 1)
+    }
+
+    #[test]
+    fn in_order_round_trip() {
+        let (mut alice, mut bob) = pair();
+        for i in 0..3 {
+            let (header, ct, nonce) = alice.encrypt(&format!("a{}", i), &aad()).unwrap();
+            assert_eq!(bob.decrypt(&header, &ct, &nonce, &aad()).unwrap(), format!("a{}", i));
+        }
+        let (header, ct, nonce) = bob.encrypt("reply", &aad()).unwrap();
+        assert_eq!(alice.decrypt(&header, &ct, &nonce, &aad()).unwrap(), "reply");
+        let (header, ct, nonce) = alice.encrypt("again", &aad()).unwrap();
+        assert_eq!(bob.decrypt(&header, &ct, &nonce, &aad()).unwrap(), "again");
+        assert_eq!(bob.skipped_key_count(), 0);
+    }
+
+    #[test]
+    fn out_of_order_across_ratchet_steps() {
+        let (mut alice, mut bob) = pair();
+        let first = alice.encrypt("first", &aad()).unwrap();
+        let second = alice.encrypt("second", &aad()).unwrap();
+        let third = alice.encrypt("third", &aad()).unwrap();
+
+        assert_eq!(bob.decrypt(&third.0, &third.1, &third.2, &aad()).unwrap(), "third");
+        assert_eq!(bob.skipped_key_count(), 2);
+
+        // Bob replies, Alice ratchets, and the old chain's messages still open
+        let reply = bob.encrypt("reply", &aad()).unwrap();
+        assert_eq!(alice.decrypt(&reply.0, &reply.1, &reply.2, &aad()).unwrap(), "reply");
+        let next = alice.encrypt("next", &aad()).unwrap();
+        assert_eq!(bob.decrypt(&next.0, &next.1, &next.2, &aad()).unwrap(), "next");
+
+        assert_eq!(bob.decrypt(&first.0, &first.1, &first.2, &aad()).unwrap(), "first");
+        assert_eq!(bob.decrypt(&second.0, &second.1, &second.2, &aad()).unwrap(), "second");
+        assert_eq!(bob.skipped_key_count(), 0);
+        // Skipped keys are single use
+        assert!(bob.decrypt(&first.0, &first.1, &first.2, &aad()).is_err());
+    }
+
+    #[test]
+    fn tampered_header_fails_without_advancing() {
+        let (mut alice, mut bob) = pair();
+        let (header, ct, nonce) = alice.encrypt("hello", &aad()).unwrap();
+
+        let mut wrong_n = header.clone();
+        wrong_n.n += 1;
+        assert!(bob.decrypt(&wrong_n, &ct, &nonce, &aad()).is_err());
+        let mut wrong_dh = header.clone();
+        wrong_dh.dh_public = X25519Secret::generate().public_key();
+        assert!(bob.decrypt(&wrong_dh, &ct, &nonce, &aad()).is_err());
+        assert_eq!(bob.skipped_key_count(), 0);
+
+        assert_eq!(bob.decrypt(&header, &ct, &nonce, &aad()).unwrap(), "hello");
+    }
+
+    #[test]
+    fn rejects_excessive_skips() {
+        let (mut alice, mut bob) = pair();
+        let (mut header, ct, nonce) = alice.encrypt("hello", &aad()).unwrap();
+        header.n = MAX_SKIP + 1;
+        assert!(bob.decrypt(&header, &ct, &nonce, &aad()).is_err());
+    }
+
+    #[test]
+    fn context_is_authenticated() {
+        let (mut alice, mut bob) = pair();
+        let (header, ct, nonce) = alice.encrypt("hello", &aad()).unwrap();
+        let later = alice.encrypt("later", &aad()).unwrap();
+
+        for wrong in [
+            MessageAad::new("alice:carol", "alice", 1),
+            MessageAad::new("alice:bob", "bob", 1),
+            MessageAad::new("alice:bob", "alice", 2),
+        ] {
+            assert!(bob.decrypt(&header, &ct, &nonce, &wrong).is_err());
+        }
+        let mut wrong_prev = header.clone();
+        wrong_prev.prev_chain_len += 1;
+        assert!(bob.decrypt(&wrong_prev, &ct, &nonce, &aad()).is_err());
+
+        // Skipped keys are checked the same way
+        assert_eq!(bob.decrypt(&later.0, &later.1, &later.2, &aad()).unwrap(), "later");
+        assert!(bob.decrypt(&header, &ct, &nonce, &MessageAad::new("alice:bob", "bob", 1)).is_err());
+        assert_eq!(bob.decrypt(&header, &ct, &nonce, &aad()).unwrap(), "hello");
+    }
+}
@@ -0,0 +1,301 @@
+// Social recovery via Shamir secret sharing
+// The identity secret and chain code are split into N shares (threshold K) over
+// GF(256); each share is sealed to a guardian's X25519 key. Guardians re-seal
+// their share to a recovering device, which reassembles the identity from K shares
+
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::keyexchange::{hkdf_expand, X25519Secret};
+
+/// Recovered secret: ED25519 secret key followed by the SLIP-10 chain code
+pub const RECOVERY_SECRET_LEN: usize = 64;
+
+/// Split `secret` into `shares` shares, any `threshold` of which reconstruct it.
+/// Returns (x, y) pairs with x in 1..=shares.
+pub fn split_secret(secret: &[u8], threshold: u8, shares: u8) -> anyhow::Result<Vec<(u8, Vec<u8>)>> {
+    if threshold < 2 || threshold > shares {
+        return Err(anyhow::anyhow!("Threshold must be between 2 and the number of shares"));
+    }
+    let mut coefficients = vec![0u8; secret.len() * (threshold as usize - 1)];
+    rand::rngs::OsRng.fill_bytes(&mut coefficients);
+
+    let degree = threshold as usize - 1;
+    Ok((1..=shares)
+        .map(|x| {
+            let y = secret
+                .iter()
+                .zip(coefficients.chunks(degree))
+                .map(|(byte, poly)| {
+                    // Horner evaluation of byte + c1*x + ... + c(k-1)*x^(k-1)
+                    poly.iter().rev().fold(0u8, |acc, c| gf_mul(acc ^ c, x)) ^ byte
+                })
+                .collect();
+            (x, y)
+        })
+        .collect())
+}
+
+/// Reconstruct a secret from at least `threshold` distinct shares (Lagrange at x = 0)
+pub fn combine_shares(shares: &[(u8, Vec<u8>)]) -> anyhow::Result<Vec<u8>> {
+    let len = shares.first().map(|(_, y)| y.len()).ok_or_else(|| anyhow::anyhow!("No shares"))?;
+    for (i, (x, y)) in shares.iter().enumerate() {
+        if *x == 0 || y.len() != len {
+            return Err(anyhow::anyhow!("Malformed share"));
+        }
+        if shares[..i].iter().any(|(other, _)| other == x) {
+            return Err(anyhow::anyhow!("Duplicate share {}", x));
+        }
+    }
+
+    let mut secret = vec![0u8; len];
+    for (i, (xi, yi)) in shares.iter().enumerate() {
+        // Basis polynomial l_i(0) = prod_{j != i} xj / (xj - xi); subtraction is XOR
+        let basis = shares
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1u8, |acc, (_, (xj, _))| gf_mul(acc, gf_mul(*xj, gf_inv(xj ^ xi))));
+        for (out, y) in secret.iter_mut().zip(yi) {
+            *out ^= gf_mul(basis, *y);
+        }
+    }
+    Ok(secret)
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1 (no secret-dependent branches)
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Inverse in GF(2^8) as a^254
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// One recovery share sealed to a holder's X25519 key (a guardian, or a recovering device)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedShare {
+    pub identity_hash: String,     // Identity being protected
+    pub holder: String,            // Guardian identity hash, or "recovery" when re-sealed to a device
+    pub index: u8,                 // Share x-coordinate (1..=N)
+    pub threshold: u8,             // K shares needed
+    pub ephemeral_public: String,  // hex X25519 ephemeral public key
+    pub nonce: String,             // base64 XChaCha20 nonce
+    pub ciphertext: String,        // base64 encrypted share bytes
+}
+
+/// Holder name used when a guardian releases a share to a recovering device
+pub const RECOVERY_HOLDER: &str = "recovery";
+
+impl SealedShare {
+    /// Encrypt share bytes to `recipient` with an ephemeral X25519 key
+    pub fn seal(
+        identity_hash: &str,
+        holder: &str,
+        index: u8,
+        threshold: u8,
+        share: &[u8],
+        recipient: &[u8; 32],
+    ) -> anyhow::Result<Self> {
+        let ephemeral = X25519Secret::generate();
+        let mut sealed = Self {
+            identity_hash: identity_hash.to_string(),
+            holder: holder.to_string(),
+            index,
+            threshold,
+            ephemeral_public: hex::encode(ephemeral.public_key()),
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let cipher = XChaCha20Poly1305::new(&sealed.share_key(&ephemeral.diffie_hellman(recipient)?, recipient)?.into());
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = sealed.header_aad();
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: share, aad: aad.as_bytes() })
+            .map_err(|e| anyhow::anyhow!("Share encryption failed: {}", e))?;
+        sealed.nonce = general_purpose::STANDARD.encode(nonce);
+        sealed.ciphertext = general_purpose::STANDARD.encode(ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypt with the holder's X25519 secret
+    pub fn open(&self, secret: &X25519Secret) -> anyhow::Result<Vec<u8>> {
+        let ephemeral: [u8; 32] = hex::decode(&self.ephemeral_public)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Ephemeral key must be 32 bytes"))?;
+        let nonce = general_purpose::STANDARD.decode(&self.nonce)?;
+        if nonce.len() != 24 {
+            return Err(anyhow::anyhow!("Share nonce must be 24 bytes"));
+        }
+        let cipher = XChaCha20Poly1305::new(&self.share_key(&secret.diffie_hellman(&ephemeral)?, &secret.public_key())?.into());
+        let aad = self.header_aad();
+        cipher
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload { msg: &general_purpose::STANDARD.decode(&self.ciphertext)?, aad: aad.as_bytes() },
+            )
+            .map_err(|_| anyhow::anyhow!("Share is not sealed to this key or was tampered with"))
+    }
+
+    fn share_key(&self, shared: &[u8; 32], recipient: &[u8; 32]) -> anyhow::Result<[u8; 32]> {
+        let mut info = hex::decode(&self.ephemeral_public)?;
+        info.extend_from_slice(recipient);
+        Ok(hkdf_expand(b"zerotrace_recovery_share_v1", shared, &info))
+    }
+
+    /// Associated data binding the readable header to the ciphertext
+    fn header_aad(&self) -> String {
+        format!(
+            "zerotrace_recovery_v1:{}:{}:{}:{}",
+            self.identity_hash, self.holder, self.index, self.threshold
+        )
+    }
+}
+
+/// Temporary key a recovering device asks guardians to release shares to
+#[derive(Debug)]
+pub struct RecoveryKit {
+    secret: X25519Secret,
+}
+
+impl Default for RecoveryKit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecoveryKit {
+    pub fn new() -> Self {
+        Self { secret: X25519Secret::generate() }
+    }
+
+    /// Give this to guardians (see `IdentityManager::release_recovery_share`)
+    pub fn public_key(&self) -> [u8; 32] {
+        self.secret.public_key()
+    }
+
+    /// Open released shares and reconstruct the 64-byte recovery secret
+    pub fn combine(&self, released: &[SealedShare]) -> anyhow::Result<(String, [u8; RECOVERY_SECRET_LEN])> {
+        let first = released.first().ok_or_else(|| anyhow::anyhow!("No shares"))?;
+        if released.len() < first.threshold as usize {
+            return Err(anyhow::anyhow!("Need {} shares, have {}", first.threshold, released.len()));
+        }
+        let shares = released
+            .iter()
+            .map(|share| {
+                if share.identity_hash != first.identity_hash || share.threshold != first.threshold {
+                    return Err(anyhow::anyhow!("Shares belong to different recovery sets"));
+                }
+                if share.holder != RECOVERY_HOLDER {
+                    return Err(anyhow::anyhow!("Share has not been released for recovery"));
+                }
+                Ok((share.index, share.open(&self.secret)?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let secret = combine_shares(&shares)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Recovered secret has the wrong length"))?;
+        Ok((first.identity_hash.clone(), secret))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    #[test]
+    fn any_threshold_shares_reconstruct_the_secret() {
+        let secret: Vec<u8> = (0..RECOVERY_SECRET_LEN as u8).collect();
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        for subset in [[0, 1, 2], [0, 2, 4], [4, 3, 1], [1, 2, 3]] {
+            let picked: Vec<_> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine_shares(&picked).unwrap(), secret);
+        }
+    }
+
+    #[test]
+    fn fewer_than_threshold_shares_do_not_reconstruct() {
+        let secret = [0x42u8; RECOVERY_SECRET_LEN];
+        let shares = split_secret(&secret, 3, 5).unwrap();
+        assert_ne!(combine_shares(&shares[..2]).unwrap(), secret);
+        assert!(combine_shares(&[shares[0].clone(), shares[0].clone()]).is_err());
+        assert!(split_secret(&secret, 1, 5).is_err());
+        assert!(split_secret(&secret, 6, 5).is_err());
+    }
+
+    #[test]
+    fn guardians_restore_the_identity() {
+        let mut owner = IdentityManager::new();
+        let guardians: Vec<_> = (0..3).map(|_| IdentityManager::new()).collect();
+        for guardian in &guardians {
+            owner.add_contact(
+                guardian.get_identity_hash().to_string(),
+                guardian.public_key(),
+            );
+        }
+        let hashes: Vec<_> = guardians.iter().map(|g| g.get_identity_hash().to_string()).collect();
+        let hashes: Vec<&str> = hashes.iter().map(String::as_str).collect();
+        let sealed = owner.create_recovery_shares(2, &hashes).unwrap();
+
+        let kit = RecoveryKit::new();
+        let released: Vec<_> = guardians[1..]
+            .iter()
+            .zip(&sealed[1..])
+            .map(|(guardian, share)| guardian.release_recovery_share(share, &kit.public_key()).unwrap())
+            .collect();
+        assert!(kit.combine(&released[..1]).is_err());
+        let recovered = IdentityManager::recover(&kit, &released, Vec::new()).unwrap();
+        assert_eq!(recovered.get_identity_hash(), owner.get_identity_hash());
+        assert_eq!(recovered.get_public_key(), owner.get_public_key());
+
+        // A guardian cannot release someone else's share
+        assert!(guardians[0].release_recovery_share(&sealed[1], &kit.public_key()).is_err());
+    }
+
+    #[test]
+    fn tampered_shares_are_rejected() {
+        let kit = RecoveryKit::new();
+        let share = SealedShare::seal("owner", RECOVERY_HOLDER, 1, 2, b"share bytes", &kit.public_key()).unwrap();
+
+        let mut relabelled = share.clone();
+        relabelled.index = 2;
+        assert!(relabelled.open(&kit.secret).is_err());
+
+        let mut corrupted = share.clone();
+        let mut ciphertext = general_purpose::STANDARD.decode(&corrupted.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        corrupted.ciphertext = general_purpose::STANDARD.encode(ciphertext);
+        assert!(corrupted.open(&kit.secret).is_err());
+
+        let mut bad_key = share.clone();
+        bad_key.ephemeral_public = "not hex".to_string();
+        assert!(bad_key.open(&kit.secret).is_err());
+
+        assert!(share.open(&RecoveryKit::new().secret).is_err());
+        assert_eq!(share.open(&kit.secret).unwrap(), b"share bytes");
+    }
+}
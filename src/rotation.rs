@@ -0,0 +1,93 @@
+// Identity key rotation
+// The old key signs a certificate endorsing the new one; the identity_hash stays
+// bound to the original key and verifiers walk the chain to the current key
+
+use ed25519_dalek::{PublicKey, Verifier};
+use serde::{Deserialize, Serialize};
+
+use crate::identity::{parse_public_key, parse_signature, IdentityManager};
+
+/// Statement by `previous_key` endorsing `new_key` for an identity
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RotationCertificate {
+    pub identity_hash: String,     // Original identity hash (never changes)
+    pub sequence: u64,             // 1 for the first rotation, then +1
+    pub previous_key: String,      // hex ED25519 public key being retired
+    pub new_key: String,           // hex ED25519 public key taking over
+    pub timestamp: u64,
+    pub signature: String,         // hex signature by `previous_key`
+}
+
+impl RotationCertificate {
+    /// Bytes signed by the previous key
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_rotation_v1:{}:{}:{}:{}:{}",
+            self.identity_hash, self.sequence, self.previous_key, self.new_key, self.timestamp
+        )
+    }
+
+    /// Check the certificate is signed by `previous_key` and names a valid new key
+    pub fn verify(&self) -> anyhow::Result<PublicKey> {
+        let previous = parse_public_key(&self.previous_key)?;
+        let new_key = parse_public_key(&self.new_key)?;
+        previous
+            .verify(self.signing_payload().as_bytes(), &parse_signature(&self.signature)?)
+            .map_err(|_| anyhow::anyhow!("Rotation {} is not signed by the previous key", self.sequence))?;
+        Ok(new_key)
+    }
+}
+
+/// Walk a rotation chain from the key the identity_hash was derived from.
+/// Returns the current key, or an error describing where the chain breaks.
+pub fn verify_rotation_chain(
+    identity_hash: &str,
+    original_key: &PublicKey,
+    rotations: &[RotationCertificate],
+) -> anyhow::Result<PublicKey> {
+    if IdentityManager::compute_identity_hash(original_key.as_bytes()) != identity_hash {
+        return Err(anyhow::anyhow!("Original key does not match identity hash"));
+    }
+    follow_rotations(identity_hash, original_key, rotations)
+}
+
+/// Walk rotations forward from an already-trusted key (e.g. a stored contact key).
+/// Certificates before the one retiring `trusted_key` are skipped.
+pub fn follow_rotations(
+    identity_hash: &str,
+    trusted_key: &PublicKey,
+    rotations: &[RotationCertificate],
+) -> anyhow::Result<PublicKey> {
+    let trusted_hex = hex::encode(trusted_key.as_bytes());
+    let start = match rotations.iter().position(|c| c.previous_key == trusted_hex) {
+        Some(start) => start,
+        None if rotations.last().is_none_or(|c| c.new_key == trusted_hex) => return Ok(*trusted_key),
+        None => return Err(anyhow::anyhow!("Trusted key does not appear in the rotation chain")),
+    };
+
+    let mut current = *trusted_key;
+    for (expected_seq, cert) in (start as u64 + 1..).zip(&rotations[start..]) {
+        current = verify_link(identity_hash, expected_seq, &current, cert)?;
+    }
+    Ok(current)
+}
+
+/// Check `cert` is rotation number `expected_seq` of `identity_hash` and retires
+/// `current`. Returns the key it endorses.
+pub fn verify_link(
+    identity_hash: &str,
+    expected_seq: u64,
+    current: &PublicKey,
+    cert: &RotationCertificate,
+) -> anyhow::Result<PublicKey> {
+    if cert.identity_hash != identity_hash {
+        return Err(anyhow::anyhow!("Rotation {} is for a different identity", cert.sequence));
+    }
+    if cert.sequence != expected_seq {
+        return Err(anyhow::anyhow!("Expected rotation {}, got {}", expected_seq, cert.sequence));
+    }
+    if cert.previous_key != hex::encode(current.as_bytes()) {
+        return Err(anyhow::anyhow!("Rotation {} does not follow the previous key", cert.sequence));
+    }
+    cert.verify()
+}
@@ -0,0 +1,170 @@
+// Safety numbers for out-of-band contact verification
+// Both parties derive the same number from their identity hashes and current
+// keys; comparing it (read aloud or scanned as a QR code) rules out a swapped key
+
+use ed25519_dalek::PublicKey;
+use sha2::{Digest, Sha512};
+
+/// Safety number format version (bump if the derivation changes)
+pub const SAFETY_NUMBER_VERSION: u8 = 1;
+
+/// Hash iterations per fingerprint, to slow down searching for colliding keys
+const FINGERPRINT_ITERATIONS: usize = 5200;
+
+/// Bytes of each party's fingerprint (6 chunks of 5 bytes -> 30 digits)
+const FINGERPRINT_LEN: usize = 30;
+
+/// Prefix of the QR text encoding (QR alphanumeric mode: digits, A-Z, ':')
+const QR_PREFIX: &str = "ZTSN";
+
+/// Safety number for a pair of identities. Independent of which side computes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafetyNumber {
+    fingerprints: [[u8; FINGERPRINT_LEN]; 2], // sorted, so both sides agree
+}
+
+impl SafetyNumber {
+    pub fn compute(local_hash: &str, local_key: &PublicKey, remote_hash: &str, remote_key: &PublicKey) -> Self {
+        let mut fingerprints = [fingerprint(local_hash, local_key), fingerprint(remote_hash, remote_key)];
+        fingerprints.sort();
+        Self { fingerprints }
+    }
+
+    /// 60 digits in twelve groups of five, for reading aloud
+    pub fn numeric(&self) -> String {
+        self.fingerprints
+            .iter()
+            .flat_map(|fp| fp.chunks(5))
+            .map(|chunk| {
+                let value = chunk.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+                format!("{:05}", value % 100_000)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Text for a QR code: `ZTSN:{version}:{HEX}`, using only QR alphanumeric characters
+    pub fn qr_text(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            QR_PREFIX,
+            SAFETY_NUMBER_VERSION,
+            hex::encode_upper(self.fingerprints.concat())
+        )
+    }
+
+    /// Compare against the other side's scanned `qr_text`
+    pub fn matches_qr(&self, scanned: &str) -> anyhow::Result<bool> {
+        let mut parts = scanned.trim().splitn(3, ':');
+        if parts.next() != Some(QR_PREFIX) {
+            return Err(anyhow::anyhow!("Not a safety number code"));
+        }
+        if parts.next() != Some(&SAFETY_NUMBER_VERSION.to_string()) {
+            return Err(anyhow::anyhow!("Unsupported safety number version"));
+        }
+        let bytes = hex::decode(parts.next().unwrap_or_default())?;
+        Ok(bytes == self.fingerprints.concat())
+    }
+}
+
+impl std::fmt::Display for SafetyNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.numeric())
+    }
+}
+
+/// Iterated SHA512 over the identity hash and public key
+fn fingerprint(identity_hash: &str, key: &PublicKey) -> [u8; FINGERPRINT_LEN] {
+    let mut hasher = Sha512::new();
+    hasher.update([0, SAFETY_NUMBER_VERSION]);
+    hasher.update(key.as_bytes());
+    hasher.update(identity_hash.as_bytes());
+    let mut digest = hasher.finalize();
+    for _ in 0..FINGERPRINT_ITERATIONS {
+        let mut hasher = Sha512::new();
+        hasher.update(digest);
+        hasher.update(key.as_bytes());
+        digest = hasher.finalize();
+    }
+    let mut out = [0u8; FINGERPRINT_LEN];
+    out.copy_from_slice(&digest[..FINGERPRINT_LEN]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{IdentityManager, VerificationState};
+
+    fn add_each_other(alice: &mut IdentityManager, bob: &mut IdentityManager) {
+        alice.add_contact(bob.get_identity_hash().to_string(), bob.public_key());
+        bob.add_contact(alice.get_identity_hash().to_string(), alice.public_key());
+    }
+
+    #[test]
+    fn both_sides_compute_the_same_number() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let ours = SafetyNumber::compute(alice.get_identity_hash(), &alice.public_key(), bob.get_identity_hash(), &bob.public_key());
+        let theirs = SafetyNumber::compute(bob.get_identity_hash(), &bob.public_key(), alice.get_identity_hash(), &alice.public_key());
+        assert_eq!(ours, theirs);
+        assert_eq!(ours.numeric(), theirs.numeric());
+
+        let numeric = ours.numeric();
+        let groups: Vec<&str> = numeric.split(' ').collect();
+        assert_eq!(groups.len(), 12);
+        assert!(groups.iter().all(|g| g.len() == 5 && g.chars().all(|c| c.is_ascii_digit())));
+
+        let carol = IdentityManager::new();
+        let other = SafetyNumber::compute(alice.get_identity_hash(), &alice.public_key(), carol.get_identity_hash(), &carol.public_key());
+        assert_ne!(ours, other);
+    }
+
+    #[test]
+    fn qr_text_round_trips_and_rejects_other_formats() {
+        let (alice, bob) = (IdentityManager::new(), IdentityManager::new());
+        let number = SafetyNumber::compute(alice.get_identity_hash(), &alice.public_key(), bob.get_identity_hash(), &bob.public_key());
+        let qr = number.qr_text();
+        assert!(number.matches_qr(&qr).unwrap());
+
+        let hex = qr.rsplit(':').next().unwrap();
+        assert!(number.matches_qr(&format!("XXXX:{}:{}", SAFETY_NUMBER_VERSION, hex)).is_err());
+        assert!(number.matches_qr(&format!("{}:{}:{}", QR_PREFIX, SAFETY_NUMBER_VERSION + 1, hex)).is_err());
+        assert!(!number.matches_qr(&format!("{}:{}:{}", QR_PREFIX, SAFETY_NUMBER_VERSION, "00".repeat(60))).unwrap());
+    }
+
+    #[test]
+    fn scanning_the_contacts_code_verifies_them() {
+        let (mut alice, mut bob) = (IdentityManager::new(), IdentityManager::new());
+        add_each_other(&mut alice, &mut bob);
+        let bob_hash = bob.get_identity_hash().to_string();
+
+        // A code computed over a substituted key for Bob
+        let mallory = IdentityManager::new();
+        let wrong = SafetyNumber::compute(&bob_hash, &mallory.public_key(), alice.get_identity_hash(), &alice.public_key()).qr_text();
+        assert!(alice.verify_contact_qr(&bob_hash, &wrong).is_err());
+        assert_eq!(alice.get_contact_record(&bob_hash).unwrap().verification, VerificationState::Unverified);
+
+        let scanned = bob.safety_number(alice.get_identity_hash()).unwrap().qr_text();
+        alice.verify_contact_qr(&bob_hash, &scanned).unwrap();
+        assert_eq!(alice.get_contact_record(&bob_hash).unwrap().verification, VerificationState::Verified);
+    }
+
+    #[test]
+    fn replacing_a_contacts_key_warns_and_resets_verification() {
+        let (mut alice, mut bob) = (IdentityManager::new(), IdentityManager::new());
+        add_each_other(&mut alice, &mut bob);
+        let bob_hash = bob.get_identity_hash().to_string();
+        assert!(alice.add_contact(bob_hash.clone(), bob.public_key()).is_none());
+        alice.mark_contact_verified(&bob_hash).unwrap();
+
+        let new_key = IdentityManager::new().public_key();
+        let warning = alice.add_contact(bob_hash.clone(), new_key).unwrap();
+        assert!(warning.was_verified);
+        assert_eq!(warning.previous_key, bob.public_key());
+        assert_eq!(warning.new_key, new_key);
+        assert_eq!(alice.get_contact_record(&bob_hash).unwrap().verification, VerificationState::Changed);
+
+        let warning = alice.add_contact(bob_hash.clone(), bob.public_key()).unwrap();
+        assert!(!warning.was_verified);
+    }
+}
@@ -0,0 +1,199 @@
+// Challenge-response login and session tokens
+// The server issues a random challenge, the client signs it with its identity
+// (or device) key, and the server returns a short-lived HMAC-signed session token
+
+use std::collections::HashMap;
+
+use base64::{Engine as _, engine::general_purpose};
+use ed25519_dalek::{PublicKey, Verifier};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::identity::{parse_signature, IdentitySigner};
+
+/// Lifetime of a session token
+pub const SESSION_TTL_SECS: u64 = 15 * 60;
+
+/// Lifetime of an unanswered login challenge
+pub const CHALLENGE_TTL_SECS: u64 = 2 * 60;
+
+/// Bytes signed to answer a login challenge
+pub fn login_payload(identity_hash: &str, challenge: &str) -> String {
+    format!("zerotrace_login_v1:{}:{}", identity_hash, challenge)
+}
+
+/// Signed answer to a challenge (`POST /auth/login`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub identity_hash: String,
+    pub challenge: String,         // hex, as issued by `/auth/challenge`
+    #[serde(default)]
+    pub device_key: Option<String>, // hex device key when a device logs in
+    pub signature: String,         // hex signature over `login_payload()`
+}
+
+impl LoginRequest {
+    /// Answer a challenge with the root key or an endorsed device key
+    pub fn sign<S: IdentitySigner>(signer: &S, challenge: &str) -> Self {
+        let signature = signer.sign(login_payload(signer.identity_hash(), challenge).as_bytes());
+        Self {
+            identity_hash: signer.identity_hash().to_string(),
+            challenge: challenge.to_string(),
+            device_key: signer.device_key().map(|key| hex::encode(key.as_bytes())),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+
+    pub fn verify(&self, key: &PublicKey) -> bool {
+        parse_signature(&self.signature)
+            .map(|sig| key.verify(login_payload(&self.identity_hash, &self.challenge).as_bytes(), &sig).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+/// Outstanding challenges, each usable once before it expires
+#[derive(Debug, Default)]
+pub struct ChallengeStore {
+    pending: HashMap<String, (String, u64)>, // challenge -> (identity_hash, expires_at)
+}
+
+impl ChallengeStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// New random challenge for `identity_hash`. Returns (challenge, expires_at).
+    pub fn issue(&mut self, identity_hash: &str, now: u64) -> (String, u64) {
+        self.pending.retain(|_, (_, expires_at)| *expires_at > now);
+        let mut bytes = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut bytes);
+        let challenge = hex::encode(bytes);
+        let expires_at = now + CHALLENGE_TTL_SECS;
+        self.pending.insert(challenge.clone(), (identity_hash.to_string(), expires_at));
+        (challenge, expires_at)
+    }
+
+    /// Consume a challenge; fails if unknown, expired or issued to someone else
+    pub fn take(&mut self, challenge: &str, identity_hash: &str, now: u64) -> anyhow::Result<()> {
+        match self.pending.remove(challenge) {
+            Some((owner, expires_at)) if owner == identity_hash && expires_at > now => Ok(()),
+            _ => Err(anyhow::anyhow!("Unknown or expired challenge")),
+        }
+    }
+}
+
+/// Contents of a session token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionClaims {
+    pub identity_hash: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+}
+
+/// Issues and checks `{base64url(claims)}.{base64url(HMAC-SHA256)}` tokens.
+/// The key never leaves the server; restarting it invalidates all sessions.
+pub struct SessionIssuer {
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for SessionIssuer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionIssuer(..)")
+    }
+}
+
+impl Default for SessionIssuer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionIssuer {
+    /// Issuer with a fresh random key
+    pub fn new() -> Self {
+        let mut key = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut key);
+        Self { key }
+    }
+
+    pub fn issue(&self, identity_hash: &str, now: u64) -> String {
+        let claims = SessionClaims {
+            identity_hash: identity_hash.to_string(),
+            issued_at: now,
+            expires_at: now + SESSION_TTL_SECS,
+        };
+        let body = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).expect("claims serialize"));
+        let tag = general_purpose::URL_SAFE_NO_PAD.encode(self.mac(&body).finalize().into_bytes());
+        format!("{}.{}", body, tag)
+    }
+
+    /// Check the token's MAC and expiry and return its claims
+    pub fn verify(&self, token: &str, now: u64) -> anyhow::Result<SessionClaims> {
+        let (body, tag) = token.split_once('.').ok_or_else(|| anyhow::anyhow!("Malformed session token"))?;
+        self.mac(body)
+            .verify_slice(&general_purpose::URL_SAFE_NO_PAD.decode(tag)?)
+            .map_err(|_| anyhow::anyhow!("Session token signature is invalid"))?;
+        let claims: SessionClaims = serde_json::from_slice(&general_purpose::URL_SAFE_NO_PAD.decode(body)?)?;
+        if now >= claims.expires_at {
+            return Err(anyhow::anyhow!("Session has expired"));
+        }
+        Ok(claims)
+    }
+
+    fn mac(&self, body: &str) -> Hmac<Sha256> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        mac.update(b"zerotrace_session_v1:");
+        mac.update(body.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::IdentityManager;
+
+    #[test]
+    fn signed_challenge_logs_in_once() {
+        let identity = IdentityManager::new();
+        let key = identity.public_key();
+        let mut challenges = ChallengeStore::new();
+        let (challenge, expires_at) = challenges.issue(identity.get_identity_hash(), 1_000);
+        assert_eq!(expires_at, 1_000 + CHALLENGE_TTL_SECS);
+
+        let login = LoginRequest::sign(&identity, &challenge);
+        assert!(login.verify(&key));
+        assert!(!LoginRequest::sign(&IdentityManager::new(), &challenge).verify(&key));
+
+        assert!(challenges.take(&challenge, "someone else", 1_001).is_err());
+        let (challenge, _) = challenges.issue(identity.get_identity_hash(), 1_000);
+        challenges.take(&challenge, identity.get_identity_hash(), 1_001).unwrap();
+        assert!(challenges.take(&challenge, identity.get_identity_hash(), 1_002).is_err());
+    }
+
+    #[test]
+    fn expired_challenges_are_rejected() {
+        let mut challenges = ChallengeStore::new();
+        let (challenge, expires_at) = challenges.issue("alice", 1_000);
+        assert!(challenges.take(&challenge, "alice", expires_at).is_err());
+    }
+
+    #[test]
+    fn session_tokens_are_authenticated_and_expire() {
+        let issuer = SessionIssuer::new();
+        let token = issuer.issue("alice", 1_000);
+        let claims = issuer.verify(&token, 1_001).unwrap();
+        assert_eq!(claims.identity_hash, "alice");
+        assert!(issuer.verify(&token, claims.expires_at).is_err());
+
+        // Another server's key, a forged body and garbage are all rejected
+        assert!(SessionIssuer::new().verify(&token, 1_001).is_err());
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = SessionClaims { identity_hash: "mallory".to_string(), issued_at: 1_000, expires_at: u64::MAX };
+        let body = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert!(issuer.verify(&format!("{}.{}", body, tag), 1_001).is_err());
+        assert!(issuer.verify("not a token", 1_001).is_err());
+    }
+}
@@ -0,0 +1,822 @@
+// TreeKEM-style group key agreement for large groups (MLS ratchet tree)
+// Members sit at the leaves of a binary tree of X25519 keys. A commit applies
+// proposals (add/remove) and refreshes the committer's path to the root, sealing
+// each new path secret to one sibling subtree, so a membership change costs
+// O(log n) ciphertexts instead of one per member. Each commit starts a new epoch.
+
+use std::collections::HashMap;
+
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use ed25519_dalek::{PublicKey, Verifier};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::identity::{parse_public_key, parse_signature, IdentityManager, IdentitySigner};
+use crate::keyexchange::{hkdf_expand, X25519Secret};
+use crate::{CStateSnapshot, Message, SendRequest};
+
+/// Largest supported group
+pub const MAX_TREE_LEAVES: usize = 1 << 15;
+
+/// A member's signed offer to join: who it is, the key that signs its commits
+/// and its initial leaf key. Callers check `signing_key` belongs to `identity_hash`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPackage {
+    pub identity_hash: String,
+    pub signing_key: String,       // hex ED25519 key
+    pub encryption_key: [u8; 32],  // X25519 leaf key
+    pub signature: String,         // hex signature by `signing_key`
+}
+
+impl KeyPackage {
+    /// New key package and the leaf secret to keep for `TreeKemGroup::create`/`join`
+    pub fn generate(identity: &IdentityManager) -> (Self, X25519Secret) {
+        let leaf_secret = X25519Secret::generate();
+        let mut package = Self {
+            identity_hash: identity.get_identity_hash().to_string(),
+            signing_key: hex::encode(identity.get_public_key()),
+            encryption_key: leaf_secret.public_key(),
+            signature: String::new(),
+        };
+        package.signature = hex::encode(identity.sign(package.signing_payload().as_bytes()).to_bytes());
+        (package, leaf_secret)
+    }
+
+    /// Bytes signed by `signing_key`
+    pub fn signing_payload(&self) -> String {
+        format!(
+            "zerotrace_key_package_v1:{}:{}:{}",
+            self.identity_hash,
+            self.signing_key,
+            hex::encode(self.encryption_key)
+        )
+    }
+
+    pub fn verify(&self) -> anyhow::Result<PublicKey> {
+        let key = parse_public_key(&self.signing_key)?;
+        key.verify(self.signing_payload().as_bytes(), &parse_signature(&self.signature)?)
+            .map_err(|_| anyhow::anyhow!("Key package signature is invalid"))?;
+        Ok(key)
+    }
+}
+
+/// A tree node's public half
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub public_key: [u8; 32],      // Current X25519 key
+    #[serde(default)]
+    pub key_package: Option<KeyPackage>, // Leaves only: the member's key package
+    #[serde(default)]
+    pub unmerged_leaves: Vec<u32>, // Parents only: leaves added below since the key was set
+}
+
+/// Public ratchet tree in the MLS array layout: leaf `i` is node `2i`, the tree is
+/// padded to a power-of-two number of leaves and `None` marks a blank node
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatchetTree {
+    nodes: Vec<Option<TreeNode>>,
+}
+
+impl RatchetTree {
+    /// Leaf slots (occupied or blank)
+    pub fn leaf_capacity(&self) -> usize {
+        self.nodes.len().div_ceil(2)
+    }
+
+    pub fn leaf(&self, leaf: u32) -> Option<&KeyPackage> {
+        self.node(leaf_node(leaf))?.key_package.as_ref()
+    }
+
+    /// Occupied leaves and their key packages
+    pub fn members(&self) -> Vec<(u32, &KeyPackage)> {
+        (0..self.leaf_capacity() as u32)
+            .filter_map(|leaf| self.leaf(leaf).map(|package| (leaf, package)))
+            .collect()
+    }
+
+    /// SHA256 over the public tree, bound into every epoch secret
+    pub fn tree_hash(&self) -> String {
+        hex::encode(Sha256::digest(serde_json::to_vec(&self.nodes).expect("tree serializes")))
+    }
+
+    /// Reject trees that are not a full binary tree in the array layout, so that
+    /// walking towards the root always terminates
+    fn check_shape(&self) -> anyhow::Result<()> {
+        let len = self.nodes.len();
+        if len == 0 || !(len + 1).is_power_of_two() || self.leaf_capacity() > MAX_TREE_LEAVES {
+            return Err(anyhow::anyhow!("Malformed ratchet tree"));
+        }
+        Ok(())
+    }
+
+    fn node(&self, index: usize) -> Option<&TreeNode> {
+        self.nodes.get(index)?.as_ref()
+    }
+
+    fn root(&self) -> usize {
+        (self.nodes.len() - 1) / 2
+    }
+
+    /// Parents of `index` up to and including the root
+    fn direct_path(&self, mut index: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        while index != self.root() {
+            index = parent(index);
+            path.push(index);
+        }
+        path
+    }
+
+    /// Smallest set of non-blank nodes covering the subtree under `index`
+    fn resolution(&self, index: usize) -> Vec<usize> {
+        match self.node(index) {
+            Some(node) => std::iter::once(index)
+                .chain(node.unmerged_leaves.iter().map(|leaf| leaf_node(*leaf)))
+                .collect(),
+            None if level(index) == 0 => Vec::new(),
+            None => {
+                let mut nodes = self.resolution(left(index));
+                nodes.extend(self.resolution(right(index)));
+                nodes
+            }
+        }
+    }
+
+    /// Put a member in the leftmost blank leaf, doubling the tree when full
+    fn add_leaf(&mut self, package: KeyPackage) -> anyhow::Result<u32> {
+        let leaf = match (0..self.leaf_capacity() as u32).find(|leaf| self.node(leaf_node(*leaf)).is_none()) {
+            Some(leaf) => leaf,
+            None if self.nodes.is_empty() => {
+                self.nodes.push(None);
+                0
+            }
+            None => {
+                if self.leaf_capacity() * 2 > MAX_TREE_LEAVES {
+                    return Err(anyhow::anyhow!("Group is full"));
+                }
+                let leaf = self.leaf_capacity() as u32;
+                self.nodes.resize(self.nodes.len() * 2 + 1, None);
+                leaf
+            }
+        };
+        for index in self.direct_path(leaf_node(leaf)) {
+            if let Some(node) = self.nodes[index].as_mut() {
+                node.unmerged_leaves.push(leaf);
+            }
+        }
+        self.nodes[leaf_node(leaf)] = Some(TreeNode {
+            public_key: package.encryption_key,
+            key_package: Some(package),
+            unmerged_leaves: Vec::new(),
+        });
+        Ok(leaf)
+    }
+
+    /// Blank a leaf and every key it knew
+    fn remove_leaf(&mut self, leaf: u32) {
+        let index = leaf_node(leaf);
+        for node in std::iter::once(index).chain(self.direct_path(index)) {
+            self.nodes[node] = None;
+        }
+        for node in self.nodes.iter_mut().flatten() {
+            node.unmerged_leaves.retain(|l| *l != leaf);
+        }
+    }
+}
+
+fn leaf_node(leaf: u32) -> usize {
+    2 * leaf as usize
+}
+
+fn level(index: usize) -> u32 {
+    index.trailing_ones()
+}
+
+fn left(index: usize) -> usize {
+    index ^ (1 << (level(index) - 1))
+}
+
+fn right(index: usize) -> usize {
+    index ^ (3 << (level(index) - 1))
+}
+
+fn parent(index: usize) -> usize {
+    let k = level(index);
+    let b = (index >> (k + 1)) & 1;
+    (index | (1 << k)) ^ (b << (k + 1))
+}
+
+fn sibling(index: usize) -> usize {
+    let p = parent(index);
+    if index < p { right(p) } else { left(p) }
+}
+
+/// Whether leaf node `leaf` lies under parent node `ancestor`
+fn is_ancestor(ancestor: usize, leaf: usize) -> bool {
+    let k = level(ancestor);
+    leaf >> (k + 1) == ancestor >> (k + 1)
+}
+
+/// Change to membership, applied by the next commit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Proposal {
+    Add { key_package: KeyPackage },
+    Remove { leaf: u32 },
+}
+
+/// Data sealed to an X25519 key (ephemeral DH + HKDF + XChaCha20-Poly1305)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HpkeCiphertext {
+    pub ephemeral_public: [u8; 32],
+    pub nonce: String,             // base64
+    pub ciphertext: String,        // base64
+}
+
+impl HpkeCiphertext {
+    fn seal(recipient: &[u8; 32], plaintext: &[u8], aad: &str) -> anyhow::Result<Self> {
+        let ephemeral = X25519Secret::generate();
+        let key = hpke_key(&ephemeral.diffie_hellman(recipient)?, &ephemeral.public_key(), recipient);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(&nonce, Payload { msg: plaintext, aad: aad.as_bytes() })
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+        Ok(Self {
+            ephemeral_public: ephemeral.public_key(),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            ciphertext: general_purpose::STANDARD.encode(ciphertext),
+        })
+    }
+
+    fn open(&self, secret: &X25519Secret, aad: &str) -> anyhow::Result<Vec<u8>> {
+        let nonce = general_purpose::STANDARD.decode(&self.nonce)?;
+        if nonce.len() != 24 {
+            return Err(anyhow::anyhow!("Nonce must be 24 bytes"));
+        }
+        let key = hpke_key(&secret.diffie_hellman(&self.ephemeral_public)?, &self.ephemeral_public, &secret.public_key());
+        XChaCha20Poly1305::new(&key.into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload { msg: &general_purpose::STANDARD.decode(&self.ciphertext)?, aad: aad.as_bytes() },
+            )
+            .map_err(|_| anyhow::anyhow!("Not sealed to this key or tampered with"))
+    }
+}
+
+fn hpke_key(shared: &[u8; 32], ephemeral: &[u8; 32], recipient: &[u8; 32]) -> [u8; 32] {
+    hkdf_expand(b"zerotrace_treekem_hpke_v1", shared, &[ephemeral.as_slice(), recipient].concat())
+}
+
+/// New key of one node on the committer's path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathNode {
+    pub public_key: [u8; 32],
+    pub encrypted_path_secrets: Vec<HpkeCiphertext>, // One per node of the sibling subtree's resolution
+}
+
+/// Applies proposals and starts epoch `epoch + 1`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commit {
+    pub group_id: String,
+    pub epoch: u64,                // Epoch the commit was made in
+    pub committer: u32,            // Committer's leaf
+    pub proposals: Vec<Proposal>,
+    pub leaf_key: [u8; 32],        // Committer's fresh leaf key
+    pub path: Vec<PathNode>,       // Committer's direct path, leaf to root
+    pub confirmation_tag: String,  // hex HMAC of the commit under the new epoch secret
+    pub signature: String,         // hex signature by the committer's key package signing key
+}
+
+impl Commit {
+    /// Bytes covered by the confirmation tag
+    fn content(&self) -> String {
+        let body = serde_json::to_vec(&(&self.proposals, &self.leaf_key, &self.path)).expect("commit serializes");
+        format!(
+            "zerotrace_treekem_commit_v2:{}:{}:{}:{}",
+            self.group_id,
+            self.epoch,
+            self.committer,
+            hex::encode(Sha256::digest(body))
+        )
+    }
+
+    /// Bytes signed by the committer
+    pub fn signing_payload(&self) -> String {
+        format!("{}:{}", self.content(), self.confirmation_tag)
+    }
+}
+
+/// Everything a new member needs to join at the commit's epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Welcome {
+    pub group_id: String,
+    pub epoch: u64,
+    pub tree: RatchetTree,
+    pub leaf: u32,                 // The new member's leaf
+    pub committer: u32,
+    pub secrets: HpkeCiphertext,   // epoch secret || path secret of the committer/joiner common ancestor
+    pub confirmation_tag: String,  // hex HMAC of the group info under the epoch secret
+    pub signature: String,         // hex signature by the committer's key package signing key
+}
+
+impl Welcome {
+    /// Group info covered by the confirmation tag
+    fn content(&self) -> String {
+        format!(
+            "zerotrace_treekem_welcome_v1:{}:{}:{}:{}:{}",
+            self.group_id,
+            self.epoch,
+            self.leaf,
+            self.committer,
+            self.tree.tree_hash()
+        )
+    }
+
+    /// Bytes signed by the committer: the group info, the sealed secrets and the tag
+    pub fn signing_payload(&self) -> String {
+        let secrets = serde_json::to_vec(&self.secrets).expect("welcome serializes");
+        format!("{}:{}:{}", self.content(), hex::encode(Sha256::digest(secrets)), self.confirmation_tag)
+    }
+}
+
+/// One member's view of a TreeKEM group: the public tree plus the secrets of its
+/// own leaf and the ancestors it knows. Serializable for persistence.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TreeKemGroup {
+    group_id: String,
+    epoch: u64,
+    own_leaf: u32,
+    tree: RatchetTree,
+    secrets: HashMap<usize, [u8; 32]>, // node -> X25519 secret
+    epoch_secret: [u8; 32],
+}
+
+impl TreeKemGroup {
+    /// Start a group with a single member (epoch 0)
+    pub fn create(group_id: &str, key_package: KeyPackage, leaf_secret: &X25519Secret) -> anyhow::Result<Self> {
+        key_package.verify()?;
+        if key_package.encryption_key != leaf_secret.public_key() {
+            return Err(anyhow::anyhow!("Leaf secret does not match the key package"));
+        }
+        let mut tree = RatchetTree::default();
+        let own_leaf = tree.add_leaf(key_package)?;
+        let mut epoch_secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut epoch_secret);
+        Ok(Self {
+            group_id: group_id.to_string(),
+            epoch: 0,
+            own_leaf,
+            tree,
+            secrets: HashMap::from([(leaf_node(own_leaf), leaf_secret.to_bytes())]),
+            epoch_secret,
+        })
+    }
+
+    /// Join from a Welcome sealed to the key package of `leaf_secret`. The Welcome
+    /// must be signed by the committer's key package; callers check that
+    /// `committer()`'s identity is someone they expect to be invited by.
+    pub fn join(welcome: &Welcome, leaf_secret: &X25519Secret) -> anyhow::Result<Self> {
+        let tree = &welcome.tree;
+        tree.check_shape()?;
+        if welcome.committer as usize >= tree.leaf_capacity() || welcome.leaf == welcome.committer {
+            return Err(anyhow::anyhow!("Malformed welcome"));
+        }
+        if tree.node(leaf_node(welcome.leaf)).map(|n| n.public_key) != Some(leaf_secret.public_key()) {
+            return Err(anyhow::anyhow!("Welcome is not for this key package"));
+        }
+        let committer_key = tree
+            .leaf(welcome.committer)
+            .ok_or_else(|| anyhow::anyhow!("Welcome committer is not a member"))?
+            .verify()?;
+        committer_key
+            .verify(welcome.signing_payload().as_bytes(), &parse_signature(&welcome.signature)?)
+            .map_err(|_| anyhow::anyhow!("Welcome signature is invalid"))?;
+        let plaintext = welcome.secrets.open(leaf_secret, &welcome_aad(&welcome.group_id, welcome.epoch, welcome.leaf))?;
+        if plaintext.len() != 64 {
+            return Err(anyhow::anyhow!("Malformed welcome secrets"));
+        }
+        let epoch_secret: [u8; 32] = plaintext[..32].try_into().expect("32 bytes");
+        verify_confirmation(&epoch_secret, &welcome.content(), &welcome.confirmation_tag)?;
+        let mut group = Self {
+            group_id: welcome.group_id.clone(),
+            epoch: welcome.epoch,
+            own_leaf: welcome.leaf,
+            tree: tree.clone(),
+            secrets: HashMap::from([(leaf_node(welcome.leaf), leaf_secret.to_bytes())]),
+            epoch_secret,
+        };
+        let path = tree.direct_path(leaf_node(welcome.committer));
+        let start = path
+            .iter()
+            .position(|node| is_ancestor(*node, leaf_node(welcome.leaf)))
+            .ok_or_else(|| anyhow::anyhow!("Malformed welcome"))?;
+        let expected: Vec<[u8; 32]> = path[start..]
+            .iter()
+            .map(|node| tree.node(*node).map(|n| n.public_key).ok_or_else(|| anyhow::anyhow!("Malformed welcome")))
+            .collect::<anyhow::Result<_>>()?;
+        group.learn_path(&path[start..], plaintext[32..].try_into().expect("32 bytes"), &expected)?;
+        Ok(group)
+    }
+
+    pub fn group_id(&self) -> &str {
+        &self.group_id
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn own_leaf(&self) -> u32 {
+        self.own_leaf
+    }
+
+    pub fn tree(&self) -> &RatchetTree {
+        &self.tree
+    }
+
+    /// Key package of the member at `leaf`, e.g. to see who sent a Welcome
+    pub fn member(&self, leaf: u32) -> Option<&KeyPackage> {
+        self.tree.leaf(leaf)
+    }
+
+    /// Apply `proposals`, refresh this member's path and move to the next epoch.
+    /// Returns the commit for the other members and a Welcome per added member.
+    pub fn commit(&mut self, identity: &IdentityManager, proposals: Vec<Proposal>) -> anyhow::Result<(Commit, Vec<Welcome>)> {
+        let own_package = self.tree.leaf(self.own_leaf).ok_or_else(|| anyhow::anyhow!("Not a member"))?;
+        if own_package.signing_key != hex::encode(identity.get_public_key()) {
+            return Err(anyhow::anyhow!("Identity does not own this leaf"));
+        }
+        let (mut tree, added) = self.apply_proposals(self.own_leaf, &proposals)?;
+
+        // Fresh leaf secret, then one path secret per parent up to the root
+        let own_node = leaf_node(self.own_leaf);
+        let mut path_secret = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut path_secret);
+        let leaf_key = node_secret(&path_secret);
+        tree.nodes[own_node].as_mut().expect("own leaf").public_key = leaf_key.public_key();
+        let mut secrets = HashMap::from([(own_node, leaf_key.to_bytes())]);
+        let mut path_secrets = Vec::new();
+        for node in tree.direct_path(own_node) {
+            path_secret = next_path_secret(&path_secret);
+            let key = node_secret(&path_secret);
+            tree.nodes[node] = Some(TreeNode { public_key: key.public_key(), key_package: None, unmerged_leaves: Vec::new() });
+            secrets.insert(node, key.to_bytes());
+            path_secrets.push((node, path_secret));
+        }
+        let commit_secret = next_path_secret(&path_secret);
+
+        let mut path = Vec::new();
+        let mut child = own_node;
+        for (node, secret) in &path_secrets {
+            let encrypted_path_secrets = resolution_excluding(&tree, sibling(child), &added)
+                .into_iter()
+                .map(|target| {
+                    let recipient = tree.node(target).expect("resolution is non-blank").public_key;
+                    HpkeCiphertext::seal(&recipient, secret, &path_aad(&self.group_id, self.epoch, *node))
+                })
+                .collect::<anyhow::Result<_>>()?;
+            path.push(PathNode { public_key: tree.node(*node).expect("path node").public_key, encrypted_path_secrets });
+            child = *node;
+        }
+
+        let epoch = self.epoch + 1;
+        let epoch_secret = derive_epoch_secret(&self.epoch_secret, &commit_secret, &self.group_id, epoch, &tree);
+        let mut commit = Commit {
+            group_id: self.group_id.clone(),
+            epoch: self.epoch,
+            committer: self.own_leaf,
+            proposals,
+            leaf_key: leaf_key.public_key(),
+            path,
+            confirmation_tag: String::new(),
+            signature: String::new(),
+        };
+        commit.confirmation_tag = confirmation_tag(&epoch_secret, &commit.content());
+        commit.signature = hex::encode(identity.sign(commit.signing_payload().as_bytes()).to_bytes());
+        let welcomes = added
+            .iter()
+            .map(|leaf| {
+                let (_, common) = path_secrets
+                    .iter()
+                    .find(|(node, _)| is_ancestor(*node, leaf_node(*leaf)))
+                    .expect("the root is a common ancestor");
+                let recipient = tree.leaf(*leaf).expect("added leaf").encryption_key;
+                let mut welcome = Welcome {
+                    group_id: self.group_id.clone(),
+                    epoch,
+                    tree: tree.clone(),
+                    leaf: *leaf,
+                    committer: self.own_leaf,
+                    secrets: HpkeCiphertext::seal(
+                        &recipient,
+                        &[epoch_secret.as_slice(), common].concat(),
+                        &welcome_aad(&self.group_id, epoch, *leaf),
+                    )?,
+                    confirmation_tag: String::new(),
+                    signature: String::new(),
+                };
+                welcome.confirmation_tag = confirmation_tag(&epoch_secret, &welcome.content());
+                welcome.signature = hex::encode(identity.sign(welcome.signing_payload().as_bytes()).to_bytes());
+                Ok(welcome)
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        self.tree = tree;
+        self.secrets = secrets;
+        self.epoch = epoch;
+        self.epoch_secret = epoch_secret;
+        Ok((commit, welcomes))
+    }
+
+    /// Apply another member's commit for the current epoch. Fails without changing
+    /// state if it is stale, forged, or this member was removed by it.
+    pub fn process_commit(&mut self, commit: &Commit) -> anyhow::Result<()> {
+        if commit.group_id != self.group_id || commit.epoch != self.epoch {
+            return Err(anyhow::anyhow!("Commit is for epoch {}, group is at {}", commit.epoch, self.epoch));
+        }
+        if commit.committer == self.own_leaf {
+            return Err(anyhow::anyhow!("Own commits are applied by `commit`"));
+        }
+        let committer_key = self
+            .tree
+            .leaf(commit.committer)
+            .ok_or_else(|| anyhow::anyhow!("Committer is not a member"))?
+            .verify()?;
+        committer_key
+            .verify(commit.signing_payload().as_bytes(), &parse_signature(&commit.signature)?)
+            .map_err(|_| anyhow::anyhow!("Commit signature is invalid"))?;
+
+        let (mut tree, added) = self.apply_proposals(commit.committer, &commit.proposals)?;
+        if tree.leaf(self.own_leaf).is_none() {
+            return Err(anyhow::anyhow!("This member was removed from the group"));
+        }
+        let committer_node = leaf_node(commit.committer);
+        let path = tree.direct_path(committer_node);
+        if commit.path.len() != path.len() {
+            return Err(anyhow::anyhow!("Commit path does not match the tree"));
+        }
+
+        // Ciphertext for us: at the lowest committer path node above our leaf, sealed
+        // to the one node of the sibling subtree's resolution whose secret we hold
+        let own_node = leaf_node(self.own_leaf);
+        let start = path.iter().position(|node| is_ancestor(*node, own_node)).expect("the root is a common ancestor");
+        let child = if start == 0 { committer_node } else { path[start - 1] };
+        let resolution = resolution_excluding(&tree, sibling(child), &added);
+        let ciphertexts = &commit.path[start].encrypted_path_secrets;
+        if ciphertexts.len() != resolution.len() {
+            return Err(anyhow::anyhow!("Commit path does not match the tree"));
+        }
+        let (target, ciphertext) = resolution
+            .iter()
+            .zip(ciphertexts)
+            .find(|(node, _)| self.secrets.contains_key(node))
+            .ok_or_else(|| anyhow::anyhow!("No path secret is sealed to this member"))?;
+        let path_secret: [u8; 32] = ciphertext
+            .open(&X25519Secret::from_bytes(self.secrets[target]), &path_aad(&self.group_id, self.epoch, path[start]))?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Path secret must be 32 bytes"))?;
+
+        tree.nodes[committer_node].as_mut().expect("committer leaf").public_key = commit.leaf_key;
+        for (node, update) in path.iter().zip(&commit.path) {
+            tree.nodes[*node] = Some(TreeNode { public_key: update.public_key, key_package: None, unmerged_leaves: Vec::new() });
+        }
+        let mut next = self.clone();
+        next.tree = tree;
+        next.secrets.retain(|node, _| !path.contains(node));
+        let expected: Vec<[u8; 32]> = commit.path[start..].iter().map(|p| p.public_key).collect();
+        let commit_secret = next.learn_path(&path[start..], path_secret, &expected)?;
+        next.secrets.retain(|node, _| next.tree.node(*node).is_some());
+        next.epoch += 1;
+        next.epoch_secret = derive_epoch_secret(&self.epoch_secret, &commit_secret, &self.group_id, next.epoch, &next.tree);
+
+        // A committer that sealed different path secrets to different members would
+        // split the group across epoch secrets; the tag makes that visible
+        verify_confirmation(&next.epoch_secret, &commit.content(), &commit.confirmation_tag)?;
+        *self = next;
+        Ok(())
+    }
+
+    /// Symmetric key for messages in the current epoch
+    pub fn message_key(&self) -> [u8; 32] {
+        hkdf_expand(
+            b"zerotrace_treekem_message_v1",
+            &self.epoch_secret,
+            format!("{}:{}", self.group_id, self.epoch).as_bytes(),
+        )
+    }
+
+    /// Encrypt and prove a message to the group under the current epoch
+    pub fn seal<S: IdentitySigner>(&self, sender: &S, plaintext: &str, cstate: &CStateSnapshot) -> anyhow::Result<SendRequest> {
+        SendRequest::seal_at_epoch(sender, &self.group_id, &self.group_id, self.epoch, &self.message_key(), plaintext, cstate)
+    }
+
+    /// Decrypt a group message. Messages from any other epoch are rejected: older
+    /// epochs' keys are gone, newer ones need their commit processed first.
+    pub fn open(&self, message: &Message) -> anyhow::Result<String> {
+        if message.thread_id != self.group_id {
+            return Err(anyhow::anyhow!("Message is for another thread"));
+        }
+        if message.epoch != self.epoch {
+            return Err(anyhow::anyhow!("Message is from epoch {}, group is at {}", message.epoch, self.epoch));
+        }
+        message.decrypt(&self.message_key())
+    }
+
+    /// Check proposals against the tree and apply them to a copy.
+    /// Returns the new tree and the leaves that were added.
+    fn apply_proposals(&self, committer: u32, proposals: &[Proposal]) -> anyhow::Result<(RatchetTree, Vec<u32>)> {
+        let mut tree = self.tree.clone();
+        let mut added = Vec::new();
+        for proposal in proposals {
+            match proposal {
+                Proposal::Remove { leaf } => {
+                    if *leaf == committer || tree.leaf(*leaf).is_none() {
+                        return Err(anyhow::anyhow!("Cannot remove leaf {}", leaf));
+                    }
+                    tree.remove_leaf(*leaf);
+                }
+                Proposal::Add { key_package } => {
+                    key_package.verify()?;
+                    if tree.members().iter().any(|(_, p)| p.identity_hash == key_package.identity_hash) {
+                        return Err(anyhow::anyhow!("{} is already a member", key_package.identity_hash));
+                    }
+                    added.push(tree.add_leaf(key_package.clone())?);
+                }
+            }
+        }
+        Ok((tree, added))
+    }
+
+    /// Derive secrets for `nodes` (bottom-up) from the first one's path secret,
+    /// checking each against the published public key. Returns the commit secret.
+    fn learn_path(&mut self, nodes: &[usize], mut path_secret: [u8; 32], expected: &[[u8; 32]]) -> anyhow::Result<[u8; 32]> {
+        for (i, (node, public_key)) in nodes.iter().zip(expected).enumerate() {
+            if i > 0 {
+                path_secret = next_path_secret(&path_secret);
+            }
+            let key = node_secret(&path_secret);
+            if key.public_key() != *public_key {
+                return Err(anyhow::anyhow!("Path secret does not match the tree"));
+            }
+            self.secrets.insert(*node, key.to_bytes());
+        }
+        Ok(next_path_secret(&path_secret))
+    }
+}
+
+fn resolution_excluding(tree: &RatchetTree, index: usize, added: &[u32]) -> Vec<usize> {
+    tree.resolution(index)
+        .into_iter()
+        .filter(|node| !added.iter().any(|leaf| leaf_node(*leaf) == *node))
+        .collect()
+}
+
+fn next_path_secret(path_secret: &[u8; 32]) -> [u8; 32] {
+    hkdf_expand(b"zerotrace_treekem_path_v1", path_secret, b"path")
+}
+
+fn node_secret(path_secret: &[u8; 32]) -> X25519Secret {
+    X25519Secret::from_bytes(hkdf_expand(b"zerotrace_treekem_node_v1", path_secret, b"node"))
+}
+
+fn derive_epoch_secret(previous: &[u8; 32], commit_secret: &[u8; 32], group_id: &str, epoch: u64, tree: &RatchetTree) -> [u8; 32] {
+    hkdf_expand(
+        b"zerotrace_treekem_epoch_v1",
+        &[previous.as_slice(), commit_secret].concat(),
+        format!("{}:{}:{}", group_id, epoch, tree.tree_hash()).as_bytes(),
+    )
+}
+
+fn confirmation_mac(epoch_secret: &[u8; 32], content: &str) -> Hmac<Sha256> {
+    let key = hkdf_expand(b"zerotrace_treekem_confirm_v1", epoch_secret, b"confirm");
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&key).expect("HMAC accepts keys of any length");
+    mac.update(content.as_bytes());
+    mac
+}
+
+fn confirmation_tag(epoch_secret: &[u8; 32], content: &str) -> String {
+    hex::encode(confirmation_mac(epoch_secret, content).finalize().into_bytes())
+}
+
+fn verify_confirmation(epoch_secret: &[u8; 32], content: &str, tag: &str) -> anyhow::Result<()> {
+    confirmation_mac(epoch_secret, content)
+        .verify_slice(&hex::decode(tag)?)
+        .map_err(|_| anyhow::anyhow!("Confirmation tag does not match the epoch secret"))
+}
+
+fn path_aad(group_id: &str, epoch: u64, node: usize) -> String {
+    format!("zerotrace_treekem_path_v1:{}:{}:{}", group_id, epoch, node)
+}
+
+fn welcome_aad(group_id: &str, epoch: u64, leaf: u32) -> String {
+    format!("zerotrace_treekem_welcome_v1:{}:{}:{}", group_id, epoch, leaf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member() -> (IdentityManager, KeyPackage, X25519Secret) {
+        let identity = IdentityManager::new();
+        let (package, secret) = KeyPackage::generate(&identity);
+        (identity, package, secret)
+    }
+
+    /// Alice creates a group and adds Bob
+    fn pair() -> ((IdentityManager, TreeKemGroup), (IdentityManager, TreeKemGroup), Welcome) {
+        let (alice, alice_package, alice_secret) = member();
+        let (bob, bob_package, bob_secret) = member();
+        let mut alice_group = TreeKemGroup::create("team", alice_package, &alice_secret).unwrap();
+        let (_, welcomes) = alice_group.commit(&alice, vec![Proposal::Add { key_package: bob_package }]).unwrap();
+        let bob_group = TreeKemGroup::join(&welcomes[0], &bob_secret).unwrap();
+        ((alice, alice_group), (bob, bob_group), welcomes[0].clone())
+    }
+
+    #[test]
+    fn members_agree_on_the_epoch_key() {
+        let ((alice, mut alice_group), (bob, mut bob_group), _) = pair();
+        assert_eq!(alice_group.message_key(), bob_group.message_key());
+        assert_eq!(bob_group.member(0).unwrap().identity_hash, alice.get_identity_hash());
+
+        // Bob adds Carol, Alice follows the commit
+        let (carol, carol_package, carol_secret) = member();
+        let (commit, welcomes) = bob_group.commit(&bob, vec![Proposal::Add { key_package: carol_package }]).unwrap();
+        alice_group.process_commit(&commit).unwrap();
+        let carol_group = TreeKemGroup::join(&welcomes[0], &carol_secret).unwrap();
+        assert_eq!(alice_group.epoch(), 2);
+        assert_eq!(alice_group.message_key(), bob_group.message_key());
+        assert_eq!(carol_group.message_key(), bob_group.message_key());
+
+        // Alice removes Bob: Carol follows, Bob cannot
+        let (commit, _) = alice_group.commit(&alice, vec![Proposal::Remove { leaf: bob_group.own_leaf() }]).unwrap();
+        let mut carol_group = carol_group;
+        carol_group.process_commit(&commit).unwrap();
+        assert!(bob_group.process_commit(&commit).is_err());
+        assert_eq!(carol_group.message_key(), alice_group.message_key());
+        assert_ne!(carol_group.message_key(), bob_group.message_key());
+
+        let message = carol_group.seal(&carol, "hi", &CStateSnapshot::default()).unwrap();
+        assert_eq!(message.epoch, carol_group.epoch());
+    }
+
+    #[test]
+    fn forged_welcomes_are_rejected() {
+        let (bob, bob_package, bob_secret) = member();
+        let (alice, alice_package, alice_secret) = member();
+        let mut group = TreeKemGroup::create("team", alice_package, &alice_secret).unwrap();
+        let (_, welcomes) = group.commit(&alice, vec![Proposal::Add { key_package: bob_package }]).unwrap();
+
+        let mut wrong_epoch = welcomes[0].clone();
+        wrong_epoch.epoch += 1;
+        assert!(TreeKemGroup::join(&wrong_epoch, &bob_secret).is_err());
+
+        // Re-signed by someone who is not the committer's key package
+        let mut forged = welcomes[0].clone();
+        forged.signature = hex::encode(bob.sign(forged.signing_payload().as_bytes()).to_bytes());
+        assert!(TreeKemGroup::join(&forged, &bob_secret).is_err());
+
+        TreeKemGroup::join(&welcomes[0], &bob_secret).unwrap();
+    }
+
+    #[test]
+    fn unconfirmed_commits_are_rejected() {
+        let ((alice, mut alice_group), (_, mut bob_group), _) = pair();
+        let (_, carol_package, carol_secret) = member();
+        let (mut commit, mut welcomes) = alice_group.commit(&alice, vec![Proposal::Add { key_package: carol_package }]).unwrap();
+
+        // Correctly signed, but the tag does not match the epoch secret Bob derives
+        commit.confirmation_tag = hex::encode([0u8; 32]);
+        commit.signature = hex::encode(alice.sign(commit.signing_payload().as_bytes()).to_bytes());
+        assert!(bob_group.process_commit(&commit).is_err());
+        assert_eq!(bob_group.epoch(), 1);
+
+        let welcome = &mut welcomes[0];
+        welcome.confirmation_tag = hex::encode([0u8; 32]);
+        welcome.signature = hex::encode(alice.sign(welcome.signing_payload().as_bytes()).to_bytes());
+        assert!(TreeKemGroup::join(welcome, &carol_secret).is_err());
+    }
+
+    #[test]
+    fn out_of_range_welcome_leaves_are_rejected() {
+        let (bob, bob_package, bob_secret) = member();
+        let (alice, alice_package, alice_secret) = member();
+        let mut group = TreeKemGroup::create("team", alice_package, &alice_secret).unwrap();
+        let (_, welcomes) = group.commit(&alice, vec![Proposal::Add { key_package: bob_package }]).unwrap();
+        let welcome = &welcomes[0];
+        assert_eq!(welcome.tree.leaf(welcome.leaf).unwrap().identity_hash, bob.get_identity_hash());
+        for committer in [2, 1 << 20, u32::MAX] {
+            let mut bad = welcome.clone();
+            bad.committer = committer;
+            assert!(TreeKemGroup::join(&bad, &bob_secret).is_err());
+        }
+        let mut bad = welcome.clone();
+        bad.tree.nodes.push(None);
+        assert!(TreeKemGroup::join(&bad, &bob_secret).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 const API_BASE = "http://127.0.0.1:8080";
 
 let currentIdentity = null;
+let client = null; // ZeroTrace.Client holding the local keys
 let currentThreadId = null;
 let recipientIdentity = null;
 let conversations = new Map(); // threadId -> {recipientHash, lastMessage, timestamp}
@@ -8,8 +9,8 @@ let pollInterval = null;
 let lastMessageCount = 0;
 
 // Initialize
-document.addEventListener("DOMContentLoaded", () => {
-  loadStoredIdentity();
+document.addEventListener("DOMContentLoaded", async () => {
+  await loadStoredIdentity();
   loadConversations();
   // Auto-refresh conversations from server on load
   if (currentIdentity) {
@@ -69,12 +70,22 @@ document.addEventListener("DOMContentLoaded", () => {
     });
 });
 
-function loadStoredIdentity() {
+async function loadStoredIdentity() {
   const stored = localStorage.getItem("zerotrace_identity");
-  if (stored) {
-    currentIdentity = JSON.parse(stored);
-    updateIdentityDisplay();
+  if (!stored) return;
+
+  const data = JSON.parse(stored);
+  if (!data.secretKey) {
+    // Identities created by the server before keys moved to the client
+    console.warn("Discarding stored identity without a local secret key");
+    localStorage.removeItem("zerotrace_identity");
+    localStorage.removeItem("zerotrace_conversations");
+    return;
   }
+  const identity = await ZeroTrace.Identity.fromSeed(ZeroTrace.fromHex(data.secretKey));
+  client = new ZeroTrace.Client(API_BASE, identity);
+  currentIdentity = data;
+  updateIdentityDisplay();
 }
 
 function updateIdentityDisplay() {
@@ -96,21 +107,23 @@ function updateIdentityDisplay() {
   updateProfileModal();
 }
 
-function createIdentity() {
+async function createIdentity() {
   console.log("🆔 Creating new identity...");
   const btn = document.getElementById("create-identity-btn");
   btn.disabled = true;
   btn.innerHTML = '<span class="loading"></span> Creating...';
 
-  fetch(`${API_BASE}/identity/create`, {
-    method: "POST",
-    headers: { "Content-Type": "application/json" },
-  })
-    .then((res) => res.json())
-    .then((data) => {
+  // Keys are generated here; only the public key is registered with the server
+  const identity = await ZeroTrace.Identity.generate();
+  const newClient = new ZeroTrace.Client(API_BASE, identity);
+  newClient
+    .register()
+    .then(() => {
+      client = newClient;
       currentIdentity = {
-        identityHash: data.identity_hash,
-        publicKey: data.public_key,
+        identityHash: identity.identityHash,
+        publicKey: ZeroTrace.toHex(identity.publicKey),
+        secretKey: ZeroTrace.toHex(identity.seed),
       };
       localStorage.setItem(
         "zerotrace_identity",
@@ -150,6 +163,7 @@ function confirmCreateNewIdentity() {
   
   // Reset state
   currentIdentity = null;
+  client = null;
   currentThreadId = null;
   recipientIdentity = null;
   conversations = new Map();
@@ -327,8 +341,7 @@ function connectToUser() {
   connectBtn.innerHTML = '<span class="loading"></span> Connecting...';
 
   recipientIdentity = input;
-  const ids = [currentIdentity.identityHash, recipientIdentity].sort();
-  currentThreadId = `${ids[0]}:${ids[1]}`;
+  currentThreadId = ZeroTrace.threadIdFor(currentIdentity.identityHash, recipientIdentity);
 
   // Add to conversations
   if (!conversations.has(currentThreadId)) {
@@ -407,23 +420,11 @@ function sendMessage() {
   sendBtn.disabled = true;
   sendBtn.innerHTML = '<span class="loading"></span>';
 
-  const ids = [currentIdentity.identityHash, recipientIdentity].sort();
-  const threadId = `${ids[0]}:${ids[1]}`;
-
-  const payload = {
-    thread_id: threadId,
-    recipient_id: recipientIdentity,
-    plaintext: plaintext,
-    sender_identity_hash: currentIdentity.identityHash,
-    sender_signature: "sig_stub",
-  };
+  const threadId = ZeroTrace.threadIdFor(currentIdentity.identityHash, recipientIdentity);
 
-  fetch(`${API_BASE}/send`, {
-    method: "POST",
-    headers: { "Content-Type": "application/json" },
-    body: JSON.stringify(payload),
-  })
-    .then((res) => res.json())
+  // Encrypted, committed, proven and signed locally; the server never sees the plaintext
+  client
+    .send(recipientIdentity, plaintext)
     .then((data) => {
       input.value = "";
       input.style.height = "auto";
@@ -455,16 +456,10 @@ function sendMessage() {
 }
 
 function loadMessages(silent = false) {
-  if (!currentThreadId) return;
-
-  const ids = currentThreadId.split(":");
-  if (ids.length === 2) {
-    const sortedIds = ids.sort();
-    currentThreadId = `${sortedIds[0]}:${sortedIds[1]}`;
-  }
+  if (!currentThreadId || !client) return;
 
-  fetch(`${API_BASE}/read/${currentThreadId}`)
-    .then((res) => res.json())
+  client
+    .read(currentThreadId, recipientIdentity)
     .then((messages) => {
       // Only update if message count actually changed
       if (messages.length !== lastMessageCount) {
@@ -476,7 +471,7 @@ function loadMessages(silent = false) {
         // Update conversation preview
         if (messages.length > 0 && conversations.has(currentThreadId)) {
           const lastMsg = messages[messages.length - 1];
-          conversations.get(currentThreadId).lastMessage = lastMsg.text;
+          conversations.get(currentThreadId).lastMessage = lastMsg.text || "";
           conversations.get(currentThreadId).timestamp =
             lastMsg.timestamp * 1000;
           saveConversations();
@@ -512,12 +507,14 @@ function displayMessages(messages) {
   // Create new HTML
   const newHTML = messages
     .map((msg, index) => {
-      const isSent = msg.sender === currentIdentity.identityHash;
+      const isSent = msg.sender_id === currentIdentity.identityHash;
       const time = new Date(msg.timestamp * 1000).toLocaleTimeString([], {
         hour: "2-digit",
         minute: "2-digit",
       });
-      const proofIcon = msg.proof_present ? "🟢" : "⭕";
+      const proofIcon = msg.endcap ? "🟢" : "⭕";
+      const text =
+        msg.text === null ? "🔒 Unable to decrypt this message" : msg.text;
 
       return `
             <div class="message ${
@@ -527,7 +524,7 @@ function displayMessages(messages) {
                     <span>${isSent ? "You" : "Them"}</span>
                     <span>${proofIcon}</span>
                 </div>
-                <div class="message-text">${escapeHtml(msg.text)}</div>
+                <div class="message-text">${escapeHtml(text)}</div>
                 <div class="message-time">${time}</div>
             </div>
         `;
@@ -691,23 +688,20 @@ function loadConversations() {
 }
 
 async function refreshConversationsFromServer() {
-  if (!currentIdentity) return;
+  if (!currentIdentity || !client) return;
 
   try {
-    const response = await fetch(
-      `${API_BASE}/threads/${currentIdentity.identityHash}`
-    );
-    const threads = await response.json();
+    const threads = await client.threads();
 
     // Merge server threads with local conversations
     for (const thread of threads) {
+      if (thread.kind !== "direct") continue;
       const threadId = thread.thread_id;
       const otherHash = thread.other_identity_hash;
 
-      // Fetch last message to get preview
+      // Fetch and decrypt messages to get preview
       try {
-        const msgResponse = await fetch(`${API_BASE}/read/${threadId}`);
-        const messages = await msgResponse.json();
+        const messages = await client.read(threadId, otherHash);
 
         if (messages.length > 0) {
           const lastMsg = messages[messages.length - 1];
@@ -0,0 +1,622 @@
+// ZeroTrace client-side protocol
+// Mirrors the Rust client: keys are generated and kept in the browser, and
+// messages are encrypted, committed, proven and signed before they are posted.
+// The server only ever receives public keys, ciphertext and signatures.
+
+const ZeroTrace = (() => {
+  const subtle = globalThis.crypto.subtle;
+  const encoder = new TextEncoder();
+  const decoder = new TextDecoder("utf-8", { fatal: true });
+
+  const PROTOCOL_VERSION = 1;
+  const SEND_MESSAGE_CFC = "0xdeadbeefcafebabe";
+
+  // PKCS#8 wrappers for raw 32-byte Ed25519 seeds and X25519 scalars
+  const ED25519_PKCS8_PREFIX = fromHex("302e020100300506032b657004220420");
+  const X25519_PKCS8_PREFIX = fromHex("302e020100300506032b656e04220420");
+
+  // ===== Encoding =====
+
+  function toHex(bytes) {
+    return Array.from(bytes, (b) => b.toString(16).padStart(2, "0")).join("");
+  }
+
+  function fromHex(hex) {
+    if (hex.length % 2 !== 0 || /[^0-9a-f]/i.test(hex)) {
+      throw new Error("Invalid hex");
+    }
+    const out = new Uint8Array(hex.length / 2);
+    for (let i = 0; i < out.length; i++) {
+      out[i] = parseInt(hex.substr(i * 2, 2), 16);
+    }
+    return out;
+  }
+
+  function toBase64(bytes) {
+    let binary = "";
+    for (const b of bytes) binary += String.fromCharCode(b);
+    return btoa(binary);
+  }
+
+  function fromBase64(text) {
+    return Uint8Array.from(atob(text), (c) => c.charCodeAt(0));
+  }
+
+  function concat(...parts) {
+    const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+    let offset = 0;
+    for (const part of parts) {
+      out.set(part, offset);
+      offset += part.length;
+    }
+    return out;
+  }
+
+  function u32be(n) {
+    const out = new Uint8Array(4);
+    new DataView(out.buffer).setUint32(0, n);
+    return out;
+  }
+
+  function u64be(n) {
+    const out = new Uint8Array(8);
+    new DataView(out.buffer).setBigUint64(0, BigInt(n));
+    return out;
+  }
+
+  function u64le(n) {
+    const out = new Uint8Array(8);
+    new DataView(out.buffer).setBigUint64(0, BigInt(n), true);
+    return out;
+  }
+
+  /// u32 length-prefixed field (`put_field` in lib.rs)
+  function field(bytes) {
+    return concat(u32be(bytes.length), bytes);
+  }
+
+  function nowSecs() {
+    return Math.floor(Date.now() / 1000);
+  }
+
+  // ===== Hashing =====
+
+  async function sha256(...parts) {
+    return new Uint8Array(await subtle.digest("SHA-256", concat(...parts)));
+  }
+
+  async function sha256Hex(...parts) {
+    return toHex(await sha256(...parts));
+  }
+
+  const KECCAK_ROUND_CONSTANTS = [
+    0x0000000000000001n, 0x0000000000008082n, 0x800000000000808an, 0x8000000080008000n,
+    0x000000000000808bn, 0x0000000080000001n, 0x8000000080008081n, 0x8000000000008009n,
+    0x000000000000008an, 0x0000000000000088n, 0x0000000080008009n, 0x000000008000000an,
+    0x000000008000808bn, 0x800000000000008bn, 0x8000000000008089n, 0x8000000000008003n,
+    0x8000000000008002n, 0x8000000000000080n, 0x000000000000800an, 0x800000008000000an,
+    0x8000000080008081n, 0x8000000000008080n, 0x0000000080000001n, 0x8000000080008008n,
+  ];
+  const KECCAK_ROTATIONS = [
+    0, 1, 62, 28, 27, 36, 44, 6, 55, 20, 3, 10, 43, 25, 39, 41, 45, 15, 21, 8, 18, 2, 61, 56, 14,
+  ];
+  const MASK64 = (1n << 64n) - 1n;
+
+  function rotl64(x, n) {
+    n = BigInt(n);
+    return n === 0n ? x : ((x << n) | (x >> (64n - n))) & MASK64;
+  }
+
+  function keccakF(state) {
+    for (const rc of KECCAK_ROUND_CONSTANTS) {
+      const c = [0, 1, 2, 3, 4].map(
+        (x) => state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20]
+      );
+      for (let x = 0; x < 5; x++) {
+        const d = c[(x + 4) % 5] ^ rotl64(c[(x + 1) % 5], 1);
+        for (let y = 0; y < 25; y += 5) state[x + y] ^= d;
+      }
+      const b = new Array(25);
+      for (let x = 0; x < 5; x++) {
+        for (let y = 0; y < 5; y++) {
+          b[y + ((2 * x + 3 * y) % 5) * 5] = rotl64(state[x + 5 * y], KECCAK_ROTATIONS[x + 5 * y]);
+        }
+      }
+      for (let x = 0; x < 5; x++) {
+        for (let y = 0; y < 25; y += 5) {
+          state[x + y] = b[x + y] ^ (~b[((x + 1) % 5) + y] & MASK64 & b[((x + 2) % 5) + y]);
+        }
+      }
+      state[0] ^= rc;
+    }
+  }
+
+  /// Keccak-256 (original padding, as `sha3::Keccak256`)
+  function keccak256(bytes) {
+    const rate = 136;
+    const padded = new Uint8Array(Math.floor(bytes.length / rate + 1) * rate);
+    padded.set(bytes);
+    padded[bytes.length] ^= 0x01;
+    padded[padded.length - 1] ^= 0x80;
+
+    const state = new Array(25).fill(0n);
+    const view = new DataView(padded.buffer);
+    for (let offset = 0; offset < padded.length; offset += rate) {
+      for (let i = 0; i < rate / 8; i++) {
+        state[i] ^= view.getBigUint64(offset + i * 8, true);
+      }
+      keccakF(state);
+    }
+    const out = new Uint8Array(32);
+    const outView = new DataView(out.buffer);
+    for (let i = 0; i < 4; i++) outView.setBigUint64(i * 8, state[i], true);
+    return out;
+  }
+
+  // ===== XChaCha20-Poly1305 =====
+
+  function quarterRound(s, a, b, c, d) {
+    s[a] = (s[a] + s[b]) | 0; s[d] ^= s[a]; s[d] = (s[d] << 16) | (s[d] >>> 16);
+    s[c] = (s[c] + s[d]) | 0; s[b] ^= s[c]; s[b] = (s[b] << 12) | (s[b] >>> 20);
+    s[a] = (s[a] + s[b]) | 0; s[d] ^= s[a]; s[d] = (s[d] << 8) | (s[d] >>> 24);
+    s[c] = (s[c] + s[d]) | 0; s[b] ^= s[c]; s[b] = (s[b] << 7) | (s[b] >>> 25);
+  }
+
+  function chachaRounds(state) {
+    const s = Int32Array.from(state);
+    for (let i = 0; i < 10; i++) {
+      quarterRound(s, 0, 4, 8, 12);
+      quarterRound(s, 1, 5, 9, 13);
+      quarterRound(s, 2, 6, 10, 14);
+      quarterRound(s, 3, 7, 11, 15);
+      quarterRound(s, 0, 5, 10, 15);
+      quarterRound(s, 1, 6, 11, 12);
+      quarterRound(s, 2, 7, 8, 13);
+      quarterRound(s, 3, 4, 9, 14);
+    }
+    return s;
+  }
+
+  function chachaState(key, counter, nonce) {
+    const words = (bytes) => {
+      const view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+      return Array.from({ length: bytes.length / 4 }, (_, i) => view.getInt32(i * 4, true));
+    };
+    return Int32Array.from([
+      0x61707865, 0x3320646e, 0x79622d32, 0x6b206574,
+      ...words(key),
+      counter,
+      ...words(nonce),
+    ]);
+  }
+
+  function hchacha20(key, nonce16) {
+    const input = chachaState(key, 0, nonce16.subarray(4));
+    input[12] = new DataView(nonce16.buffer, nonce16.byteOffset).getInt32(0, true);
+    const s = chachaRounds(input);
+    const out = new Uint8Array(32);
+    const view = new DataView(out.buffer);
+    [0, 1, 2, 3, 12, 13, 14, 15].forEach((w, i) => view.setInt32(i * 4, s[w], true));
+    return out;
+  }
+
+  function chacha20Block(key, counter, nonce12) {
+    const input = chachaState(key, counter, nonce12);
+    const s = chachaRounds(input);
+    const out = new Uint8Array(64);
+    const view = new DataView(out.buffer);
+    for (let i = 0; i < 16; i++) view.setInt32(i * 4, (s[i] + input[i]) | 0, true);
+    return out;
+  }
+
+  function chacha20Xor(key, nonce12, counter, data) {
+    const out = new Uint8Array(data.length);
+    for (let offset = 0; offset < data.length; offset += 64, counter++) {
+      const block = chacha20Block(key, counter, nonce12);
+      for (let i = 0; i < 64 && offset + i < data.length; i++) {
+        out[offset + i] = data[offset + i] ^ block[i];
+      }
+    }
+    return out;
+  }
+
+  function leBigInt(bytes) {
+    let n = 0n;
+    for (let i = bytes.length - 1; i >= 0; i--) n = (n << 8n) | BigInt(bytes[i]);
+    return n;
+  }
+
+  function poly1305(key, message) {
+    const p = (1n << 130n) - 5n;
+    const r = leBigInt(key.subarray(0, 16)) & 0x0ffffffc0ffffffc0ffffffc0fffffffn;
+    const s = leBigInt(key.subarray(16, 32));
+    let acc = 0n;
+    for (let offset = 0; offset < message.length; offset += 16) {
+      const block = message.subarray(offset, offset + 16);
+      acc = ((acc + leBigInt(block) + (1n << BigInt(8 * block.length))) * r) % p;
+    }
+    acc = (acc + s) & ((1n << 128n) - 1n);
+    const tag = new Uint8Array(16);
+    for (let i = 0; i < 16; i++, acc >>= 8n) tag[i] = Number(acc & 0xffn);
+    return tag;
+  }
+
+  function pad16(bytes) {
+    return new Uint8Array((16 - (bytes.length % 16)) % 16);
+  }
+
+  function aeadTag(polyKey, ad, ciphertext) {
+    return poly1305(
+      polyKey,
+      concat(ad, pad16(ad), ciphertext, pad16(ciphertext), u64le(ad.length), u64le(ciphertext.length))
+    );
+  }
+
+  function xchachaParams(key, nonce) {
+    const subkey = hchacha20(key, nonce.subarray(0, 16));
+    const nonce12 = concat(new Uint8Array(4), nonce.subarray(16, 24));
+    const polyKey = chacha20Block(subkey, 0, nonce12).subarray(0, 32);
+    return { subkey, nonce12, polyKey };
+  }
+
+  /// XChaCha20-Poly1305 seal; returns ciphertext || tag
+  function xchachaSeal(key, nonce, plaintext, ad) {
+    const { subkey, nonce12, polyKey } = xchachaParams(key, nonce);
+    const ciphertext = chacha20Xor(subkey, nonce12, 1, plaintext);
+    return concat(ciphertext, aeadTag(polyKey, ad, ciphertext));
+  }
+
+  function xchachaOpen(key, nonce, sealed, ad) {
+    if (sealed.length < 16) throw new Error("Decryption failed");
+    const { subkey, nonce12, polyKey } = xchachaParams(key, nonce);
+    const ciphertext = sealed.subarray(0, sealed.length - 16);
+    const expected = aeadTag(polyKey, ad, ciphertext);
+    let diff = 0;
+    for (let i = 0; i < 16; i++) diff |= expected[i] ^ sealed[sealed.length - 16 + i];
+    if (diff !== 0) throw new Error("Decryption failed");
+    return chacha20Xor(subkey, nonce12, 1, ciphertext);
+  }
+
+  // ===== Key agreement (keyexchange.rs) =====
+
+  const FIELD_P = (1n << 255n) - 19n;
+
+  function modPow(base, exp, mod) {
+    let result = 1n;
+    base %= mod;
+    for (; exp > 0n; exp >>= 1n, base = (base * base) % mod) {
+      if (exp & 1n) result = (result * base) % mod;
+    }
+    return result;
+  }
+
+  /// Birational map from an ED25519 public key to its X25519 u-coordinate
+  function ed25519PublicToX25519(publicKey) {
+    const y = leBigInt(publicKey) & ((1n << 255n) - 1n);
+    if (y >= FIELD_P || y === 1n) throw new Error("Invalid ED25519 public key");
+    const u = ((1n + y) * modPow((1n - y + FIELD_P) % FIELD_P, FIELD_P - 2n, FIELD_P)) % FIELD_P;
+    const out = new Uint8Array(32);
+    let n = u;
+    for (let i = 0; i < 32; i++, n >>= 8n) out[i] = Number(n & 0xffn);
+    return out;
+  }
+
+  async function hkdf(salt, ikm, info) {
+    const key = await subtle.importKey("raw", ikm, "HKDF", false, ["deriveBits"]);
+    const bits = await subtle.deriveBits({ name: "HKDF", hash: "SHA-256", salt, info }, key, 256);
+    return new Uint8Array(bits);
+  }
+
+  // ===== Identity (identity.rs) =====
+
+  class Identity {
+    constructor(seed, publicKey, signingKey) {
+      this.seed = seed;
+      this.publicKey = publicKey;
+      this.signingKey = signingKey;
+    }
+
+    static async generate() {
+      const pair = await subtle.generateKey({ name: "Ed25519" }, true, ["sign", "verify"]);
+      const pkcs8 = new Uint8Array(await subtle.exportKey("pkcs8", pair.privateKey));
+      return Identity.fromSeed(pkcs8.subarray(pkcs8.length - 32));
+    }
+
+    /// Rebuild an identity from its 32-byte ED25519 secret key
+    static async fromSeed(seed) {
+      seed = Uint8Array.from(seed);
+      const signingKey = await subtle.importKey(
+        "pkcs8",
+        concat(ED25519_PKCS8_PREFIX, seed),
+        { name: "Ed25519" },
+        true,
+        ["sign"]
+      );
+      const jwk = await subtle.exportKey("jwk", signingKey);
+      const publicKey = fromBase64(jwk.x.replace(/-/g, "+").replace(/_/g, "/") + "=");
+      const identity = new Identity(seed, publicKey, signingKey);
+      identity.identityHash = await sha256Hex(encoder.encode("zerotrace_identity"), publicKey);
+      return identity;
+    }
+
+    async sign(message) {
+      return new Uint8Array(await subtle.sign({ name: "Ed25519" }, this.signingKey, message));
+    }
+
+    async signHex(message) {
+      return toHex(await this.sign(typeof message === "string" ? encoder.encode(message) : message));
+    }
+
+    /// `IdentityManager::registration_request`
+    async registrationRequest() {
+      const publicKey = toHex(this.publicKey);
+      return {
+        identity_hash: this.identityHash,
+        public_key: publicKey,
+        signature: await this.signHex(`zerotrace_register_v1:${this.identityHash}:${publicKey}`),
+      };
+    }
+
+    /// `LoginRequest::sign` for the root key
+    async loginRequest(challenge) {
+      return {
+        identity_hash: this.identityHash,
+        challenge,
+        device_key: null,
+        signature: await this.signHex(`zerotrace_login_v1:${this.identityHash}:${challenge}`),
+      };
+    }
+
+    /// `IdentityManager::derive_thread_key`: X25519 with the converted identity keys, then HKDF
+    async deriveThreadKey(threadId, peerPublicKey) {
+      const scalar = (await subtle.digest("SHA-512", this.seed)).slice(0, 32);
+      const secret = await subtle.importKey(
+        "pkcs8",
+        concat(X25519_PKCS8_PREFIX, new Uint8Array(scalar)),
+        { name: "X25519" },
+        false,
+        ["deriveBits"]
+      );
+      const peer = await subtle.importKey(
+        "raw",
+        ed25519PublicToX25519(peerPublicKey),
+        { name: "X25519" },
+        false,
+        []
+      );
+      const shared = new Uint8Array(await subtle.deriveBits({ name: "X25519", public: peer }, secret, 256));
+      if (shared.every((b) => b === 0)) {
+        throw new Error("Key agreement produced a low-order point");
+      }
+      return hkdf(encoder.encode("zerotrace_thread_key_v1"), shared, encoder.encode(threadId));
+    }
+  }
+
+  // ===== Messages (lib.rs, commitments.rs, proofs.rs) =====
+
+  function threadIdFor(a, b) {
+    return a <= b ? `${a}:${b}` : `${b}:${a}`;
+  }
+
+  /// `MessageAad::encode`
+  function messageAad(threadId, senderId, sequence, version = PROTOCOL_VERSION) {
+    return concat(
+      encoder.encode("zerotrace_message_aad"),
+      u32be(version),
+      field(encoder.encode(threadId)),
+      field(encoder.encode(senderId)),
+      u64be(sequence)
+    );
+  }
+
+  async function messageCommitment(senderHash, threadId, nonce, plaintextHash) {
+    const first = await sha256(
+      encoder.encode("zerotrace_commitment_v1"),
+      encoder.encode(senderHash),
+      encoder.encode(threadId),
+      nonce,
+      encoder.encode(plaintextHash)
+    );
+    return toHex(keccak256(concat(first, encoder.encode("poseidon2_simulation"))));
+  }
+
+  async function merkleRoot(leaves) {
+    if (leaves.length === 0) return "0".repeat(64);
+    let level = leaves;
+    while (level.length > 1) {
+      const next = [];
+      for (let i = 0; i < level.length; i += 2) {
+        next.push(
+          i + 1 < level.length
+            ? await sha256Hex(encoder.encode(level[i]), encoder.encode(level[i + 1]))
+            : level[i]
+        );
+      }
+      level = next;
+    }
+    return level[0];
+  }
+
+  /// `CFCProof::for_send_message`
+  async function sendMessageProof(startRoot, endRoot, commitment) {
+    const proofHash = await sha256(
+      encoder.encode("cfc_proof_simulation"),
+      encoder.encode(SEND_MESSAGE_CFC),
+      encoder.encode(startRoot),
+      encoder.encode(endRoot),
+      encoder.encode(commitment)
+    );
+    return {
+      cfc_fingerprint: SEND_MESSAGE_CFC,
+      start_cstate_root: startRoot,
+      end_cstate_root: endRoot,
+      proof_bytes: toBase64(proofHash),
+      public_inputs: [commitment],
+      timestamp: nowSecs(),
+    };
+  }
+
+  /// `SendRequest::signing_payload`
+  async function sendSigningPayload(request) {
+    return concat(
+      encoder.encode("zerotrace_send_v2"),
+      field(encoder.encode(request.sender_identity_hash)),
+      field(encoder.encode(request.thread_id)),
+      field(encoder.encode(request.recipient_id)),
+      await sha256(encoder.encode(request.ciphertext)),
+      field(encoder.encode(request.iv)),
+      u64be(request.epoch),
+      u32be(request.version),
+      u64be(request.timestamp),
+      u64be(request.endcap.vaa_nonce)
+    );
+  }
+
+  /// `SendRequest::seal`: encrypt, commit, prove and sign a message to a direct thread
+  async function sealMessage(sender, recipientId, threadId, key, plaintext, cstate) {
+    const senderHash = sender.identityHash;
+    const vaaNonce = cstate.vaa_nonce + 1;
+    const nonce = globalThis.crypto.getRandomValues(new Uint8Array(24));
+    const sealed = xchachaSeal(key, nonce, encoder.encode(plaintext), messageAad(threadId, senderHash, vaaNonce));
+
+    const commitment = await messageCommitment(
+      senderHash,
+      threadId,
+      nonce,
+      await sha256Hex(encoder.encode(plaintext))
+    );
+    const endRoot = await merkleRoot([...cstate.thread_roots, commitment]);
+    const proof = await sendMessageProof(cstate.cstate_root, endRoot, commitment);
+
+    const request = {
+      thread_id: threadId,
+      recipient_id: recipientId,
+      sender_identity_hash: senderHash,
+      ciphertext: toBase64(sealed),
+      iv: toBase64(nonce),
+      message_commitment: commitment,
+      endcap: {
+        proof,
+        encrypted_blob_address: `da://encrypted/${globalThis.crypto.randomUUID()}`,
+        vaa_nonce: vaaNonce,
+        signature: await sender.signHex(`${commitment}:${vaaNonce}`),
+      },
+      epoch: 0,
+      version: PROTOCOL_VERSION,
+      timestamp: nowSecs(),
+      device_key: null,
+      sender_signature: "",
+    };
+    request.sender_signature = await sender.signHex(await sendSigningPayload(request));
+    return request;
+  }
+
+  /// `Message::decrypt`: fails unless thread, sender, sequence and version match what was sealed
+  function openMessage(message, key) {
+    const nonce = fromBase64(message.iv);
+    if (nonce.length !== 24) throw new Error("iv must be a 24-byte XChaCha20 nonce");
+    const ad = messageAad(message.thread_id, message.sender_id, message.sequence, message.version);
+    return decoder.decode(xchachaOpen(key, nonce, fromBase64(message.ciphertext), ad));
+  }
+
+  // ===== API client =====
+
+  class Client {
+    constructor(apiBase, identity) {
+      this.apiBase = apiBase;
+      this.identity = identity;
+      this.session = null;
+      this.publicKeys = new Map(); // identity hash -> current public key
+    }
+
+    async request(method, path, body, auth = false) {
+      const headers = {};
+      if (body !== undefined) headers["Content-Type"] = "application/json";
+      if (auth) headers["Authorization"] = `Bearer ${await this.token()}`;
+      const res = await fetch(`${this.apiBase}${path}`, {
+        method,
+        headers,
+        body: body === undefined ? undefined : JSON.stringify(body),
+      });
+      if (!res.ok) {
+        if (res.status === 401) this.session = null;
+        throw new Error(`${method} ${path} failed (${res.status}): ${await res.text()}`);
+      }
+      return res.json();
+    }
+
+    async register() {
+      return this.request("POST", "/identity/register", await this.identity.registrationRequest());
+    }
+
+    /// Challenge-response login; the token is reused until shortly before it expires
+    async token() {
+      if (this.session && this.session.expires_at > nowSecs() + 30) {
+        return this.session.token;
+      }
+      const { challenge } = await this.request("POST", "/auth/challenge", {
+        identity_hash: this.identity.identityHash,
+      });
+      this.session = await this.request("POST", "/auth/login", await this.identity.loginRequest(challenge));
+      return this.session.token;
+    }
+
+    async publicKeyOf(identityHash) {
+      if (!this.publicKeys.has(identityHash)) {
+        const identity = await this.request("GET", `/identity/${identityHash}`);
+        this.publicKeys.set(identityHash, Uint8Array.from(identity.public_key));
+      }
+      return this.publicKeys.get(identityHash);
+    }
+
+    async threadKey(threadId, peerHash) {
+      return this.identity.deriveThreadKey(threadId, await this.publicKeyOf(peerHash));
+    }
+
+    async cstate() {
+      return this.request("GET", `/cstate/${this.identity.identityHash}`);
+    }
+
+    async send(recipientId, plaintext) {
+      const threadId = threadIdFor(this.identity.identityHash, recipientId);
+      const key = await this.threadKey(threadId, recipientId);
+      const sealed = await sealMessage(this.identity, recipientId, threadId, key, plaintext, await this.cstate());
+      return this.request("POST", "/send", sealed);
+    }
+
+    /// Messages of a direct thread, each with `text` decrypted locally (null if it fails to open)
+    async read(threadId, peerHash) {
+      const messages = await this.request("GET", `/messages/${threadId}`, undefined, true);
+      const key = await this.threadKey(threadId, peerHash);
+      return messages.map((message) => {
+        try {
+          return { ...message, text: openMessage(message, key) };
+        } catch (err) {
+          return { ...message, text: null };
+        }
+      });
+    }
+
+    async threads() {
+      return this.request("GET", `/threads/${this.identity.identityHash}`, undefined, true);
+    }
+  }
+
+  return {
+    PROTOCOL_VERSION,
+    Identity,
+    Client,
+    threadIdFor,
+    sealMessage,
+    openMessage,
+    keccak256,
+    xchachaSeal,
+    xchachaOpen,
+    toHex,
+    fromHex,
+  };
+})();
+
+if (typeof module !== "undefined") {
+  module.exports = ZeroTrace;
+}